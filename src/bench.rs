@@ -0,0 +1,120 @@
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+use las::Reader;
+use tracing::info;
+
+use crate::binning::{bin_points, BinningParams};
+use crate::error::Result;
+use crate::filter::PointFilter;
+use crate::input::Input;
+use crate::report::Report;
+use crate::synthetic::Scene;
+use crate::triangulation::{triangulate, Schedule, TriangulationParams};
+use crate::util::GridDefinition;
+use crate::{Function, Variable};
+
+/// Points per square map unit of the benchmark cloud, typical of airborne lidar.
+const DENSITY: f64 = 10.0;
+
+fn log_throughput(stage: &str, items: u64, unit: &str, elapsed: Duration) {
+    info!(
+        "{stage}: {:.2}s, {:.0} {unit}/s",
+        elapsed.as_secs_f64(),
+        items as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+}
+
+/// Runs the binning and triangulation cores against a synthetic cloud of about `points` points,
+/// logging the throughput of each stage.
+pub fn run(points: u64, res: f64) -> Result<()> {
+    let scene = Scene::with_points(points, DENSITY, 0);
+    let grid = GridDefinition::new(scene.bounds(), res);
+    let (width, height) = (grid.width, grid.height);
+    let cells = grid.len() as u64;
+
+    info!(
+        "Generating {} points over {:.0}x{:.0}, {width}x{height} cells...",
+        scene.num_pulses(),
+        scene.size,
+        scene.size
+    );
+    let start = Instant::now();
+    let las = scene.write(Cursor::new(Vec::new()))?.into_inner();
+    log_throughput("Generate", scene.num_pulses(), "points", start.elapsed());
+
+    let filter = PointFilter::default();
+
+    let start = Instant::now();
+    let bins = bin_points(
+        Input::new(
+            vec![(
+                "synthetic".to_string(),
+                Reader::new(Cursor::new(las.clone()))?,
+            )],
+            0,
+        ),
+        grid,
+        &filter,
+        Variable::Z,
+        &BinningParams {
+            funcs: vec![Function::Median],
+            breaklines: None,
+            weight_by_returns: false,
+            swath_edge_angle: None,
+            strict: false,
+            focal_radius: None,
+            carry: None,
+            min_count: 1,
+        },
+        &mut Report::default(),
+    )?;
+    log_throughput("Bin", scene.num_pulses(), "points", start.elapsed());
+
+    let start = Instant::now();
+    for i in 0..grid.len() {
+        bins.collapse(&Function::Median, i);
+    }
+    log_throughput("Collapse (median)", cells, "cells", start.elapsed());
+
+    // Freezing at a few times the point spacing, as for a real spike-free run.
+    let spacing = 1.0 / DENSITY.sqrt();
+
+    let start = Instant::now();
+    let surface = triangulate(
+        Input::new(
+            vec![("synthetic".to_string(), Reader::new(Cursor::new(las))?)],
+            0,
+        ),
+        &filter,
+        Variable::Z,
+        &TriangulationParams {
+            freeze_distance: 3.0 * spacing,
+            insertion_buffer: Schedule {
+                start: 0.5,
+                end: 0.5,
+            },
+            max_edge: None,
+            max_area: None,
+            seeds: Vec::new(),
+            seed_constraints: false,
+            max_vertices: None,
+            tin_error: None,
+            chunk_size: None,
+            voxel: None,
+        },
+        &mut Report::default(),
+        None,
+    )?;
+    log_throughput("Triangulate", scene.num_pulses(), "points", start.elapsed());
+
+    let start = Instant::now();
+    for y in 0..height {
+        for x in 0..width {
+            surface.pixel(&grid, x, y);
+        }
+    }
+    log_throughput("Interpolate", cells, "cells", start.elapsed());
+
+    Ok(())
+}
@@ -1,77 +1,691 @@
-use las::{Bounds, Reader};
+use std::collections::HashMap;
 
-use crate::error::{Error, Result};
-use crate::util::get_raster_size;
+use las::Point;
+use tracing::{info, instrument};
+
+use crate::error::Result;
+use crate::filter::PointFilter;
+use crate::input::Input;
+use crate::report::Report;
+use crate::util::GridDefinition;
+use crate::vector::VectorFeature;
 use crate::{get_var, Function, Variable, NODATA};
 
-pub fn collapse_cell(points: Vec<f64>, function: &Function) -> f64 {
-    let len = points.len();
-    if len == 0 {
-        return NODATA;
+type Segment = [[f64; 2]; 2];
+
+/// Breakline segments indexed by the cells they pass through.
+pub struct Breaklines {
+    segments: Vec<Segment>,
+    cells: HashMap<usize, Vec<usize>>,
+}
+
+impl Breaklines {
+    pub fn new(features: &[VectorFeature], grid: &GridDefinition) -> Self {
+        let (res, width, height) = (grid.res, grid.width, grid.height);
+
+        let segments: Vec<Segment> = features
+            .iter()
+            .flat_map(|f| f.parts.iter())
+            .flat_map(|part| part.windows(2))
+            .map(|w| [[w[0][0], w[0][1]], [w[1][0], w[1][1]]])
+            .collect();
+
+        let mut cells: HashMap<usize, Vec<usize>> = HashMap::new();
+
+        for (i, [a, b]) in segments.iter().enumerate() {
+            // Sample along the segment at half the resolution, marking each sample's cell and
+            // its neighbours. This is conservative, the exact test happens per point.
+            let len = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+            let steps = (len / (res * 0.5)).ceil().max(1.0) as usize;
+
+            for step in 0..=steps {
+                let t = step as f64 / steps as f64;
+                let Some((x, y)) =
+                    grid.signed_cell(a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t)
+                else {
+                    continue;
+                };
+
+                for (dx, dy) in (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (dx, dy))) {
+                    let (x, y) = (x + dx, y + dy);
+
+                    if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                        continue;
+                    }
+
+                    let cell = cells.entry(grid.index(x as usize, y as usize)).or_default();
+                    if cell.last() != Some(&i) {
+                        cell.push(i);
+                    }
+                }
+            }
+        }
+
+        Self { segments, cells }
+    }
+
+    /// Whether a breakline separates `point` from the center of the cell `i`.
+    fn separates(&self, i: usize, point: [f64; 2], center: [f64; 2]) -> bool {
+        self.cells.get(&i).is_some_and(|segments| {
+            segments
+                .iter()
+                .any(|s| segments_intersect(&self.segments[*s], &[point, center]))
+        })
+    }
+}
+
+fn segments_intersect([a, b]: &Segment, [c, d]: &Segment) -> bool {
+    fn orient(p: &[f64; 2], q: &[f64; 2], r: &[f64; 2]) -> f64 {
+        (q[0] - p[0]) * (r[1] - p[1]) - (q[1] - p[1]) * (r[0] - p[0])
+    }
+
+    let (d1, d2) = (orient(a, b, c), orient(a, b, d));
+    let (d3, d4) = (orient(c, d, a), orient(c, d, b));
+
+    (d1 * d2 < 0.0) && (d3 * d4 < 0.0)
+}
+
+/// A binned value and its weight in weighted functions.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub value: f64,
+    pub weight: f32,
+    /// Classification of the sampled point.
+    pub class: u8,
+    /// Intensity of the sampled point.
+    pub intensity: u16,
+}
+
+/// Samples per cell, for each distinct variable used by the binning functions.
+type Cells = Vec<Vec<Vec<Sample>>>;
+
+/// Binning parameters, see `Commands::Bin`.
+pub struct BinningParams<'a> {
+    /// One output band per function.
+    pub funcs: Vec<Function>,
+    pub breaklines: Option<&'a Breaklines>,
+    /// Weight each point by 1 / number of returns, so multi-return pulses count once.
+    pub weight_by_returns: bool,
+    /// Scan angle (degrees) from which points count as swath edge points.
+    pub swath_edge_angle: Option<f64>,
+    /// Error on points outside of the extent rather than skipping them.
+    pub strict: bool,
+    /// Bin each point into every cell whose center is within this distance, rather than the cell
+    /// it falls in, for point-based focal statistics.
+    pub focal_radius: Option<f64>,
+    /// Variable carried along by the functions picking a point (min and max), of the point they
+    /// pick.
+    pub carry: Option<Variable>,
+    /// Cells with fewer points than this are NODATA, rather than a value of too few points to
+    /// mean anything.
+    pub min_count: usize,
+}
+
+/// Weighted percentile (0-100) linearly interpolated between order statistics. With equal
+/// weights this matches numpy's (and lidR's) default 'linear' method. Expects sorted samples.
+fn percentile(samples: &[Sample], p: f64) -> f64 {
+    let total = total_weight(samples);
+    let last = samples[samples.len() - 1].weight as f64;
+
+    if samples.len() == 1 || total - last <= 0.0 {
+        return samples[0].value;
+    }
+
+    // Position of each sample in 0-1, (C_k - w_k) / (C_n - w_n) for cumulative weight C.
+    let target = p / 100.0 * (total - last);
+    let mut cumulative = 0.0;
+
+    for pair in samples.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let (pos_a, pos_b) = (cumulative, cumulative + a.weight as f64);
+
+        if target <= pos_b {
+            let t = match pos_b - pos_a > 0.0 {
+                true => (target - pos_a) / (pos_b - pos_a),
+                false => 1.0,
+            };
+
+            return a.value + (b.value - a.value) * t;
+        }
+
+        cumulative = pos_b;
+    }
+
+    samples[samples.len() - 1].value
+}
+
+fn total_weight(samples: &[Sample]) -> f64 {
+    samples.iter().map(|s| s.weight as f64).sum()
+}
+
+/// The lowest and highest values, of the samples `keep` keeps. None if it keeps none.
+fn min_max(samples: &[Sample], keep: impl Fn(&Sample) -> bool) -> Option<(f64, f64)> {
+    samples
+        .iter()
+        .filter(|s| keep(s))
+        .fold(None, |acc, s| match acc {
+            Some((min, max)) => Some((
+                match s.value < min {
+                    true => s.value,
+                    false => min,
+                },
+                match s.value > max {
+                    true => s.value,
+                    false => max,
+                },
+            )),
+            None => Some((s.value, s.value)),
+        })
+}
+
+/// The most common (highest total weight) value and its weight, the lowest value on ties.
+fn mode(samples: &[Sample]) -> (f64, f64) {
+    let mut weights: HashMap<u64, (f64, f64)> = HashMap::new();
+    for s in samples.iter() {
+        weights.entry(s.value.to_bits()).or_insert((s.value, 0.0)).1 += s.weight as f64;
     }
 
-    match *function {
-        Function::Mean => points.into_iter().sum::<f64>() / len as f64,
-        Function::Median => {
-            if len == 1 {
-                return points[0];
+    weights
+        .into_values()
+        .fold((f64::MAX, 0.0), |acc, (value, weight)| {
+            match weight > acc.1 || (weight == acc.1 && value < acc.0) {
+                true => (value, weight),
+                false => acc,
             }
+        })
+}
 
-            let mut points = points;
-            points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+/// Collapses a cell's samples with `function`, `res` being the cell size. None for NODATA,
+/// including cells of fewer than `min_count` samples.
+pub fn collapse_cell(
+    samples: Vec<Sample>,
+    function: &Function,
+    res: f64,
+    min_count: usize,
+) -> Option<f64> {
+    let len = samples.len();
+    if len == 0 || len < min_count {
+        return None;
+    }
+
+    let sorted = |mut samples: Vec<Sample>| {
+        samples.sort_by(|a, b| a.value.partial_cmp(&b.value).unwrap());
+        samples
+    };
+    let span = |(min, max): (f64, f64)| max - min;
+
+    let value = match *function {
+        Function::Mean => {
+            let sum: f64 = samples.iter().map(|s| s.value * s.weight as f64).sum();
+
+            sum / total_weight(&samples)
+        }
+        Function::IntensityWeightedMean => {
+            let (sum, weights) = samples.iter().fold((0.0, 0.0), |(sum, weights), s| {
+                let weight = s.weight as f64 * s.intensity as f64;
+                (sum + s.value * weight, weights + weight)
+            });
 
-            match points.len() % 2 == 0 {
-                true => (points[len / 2 - 1] + points[len / 2]) / 2.0,
-                false => points[len / 2],
+            match weights > 0.0 {
+                true => sum / weights,
+                false => return None,
             }
         }
-        Function::Min => points.into_iter().fold(f64::MAX, |acc, p| acc.min(p)),
-        Function::Max => points.into_iter().fold(f64::MIN, |acc, p| acc.max(p)),
+        Function::Median => percentile(&sorted(samples), 50.0),
+        Function::Percentile(p) => percentile(&sorted(samples), p),
+        Function::Min | Function::TimeMin => min_max(&samples, |_| true)?.0,
+        Function::MinPercentile(p) => percentile(&sorted(samples), p),
+        Function::Max | Function::TimeMax => min_max(&samples, |_| true)?.1,
         Function::Count => len as f64,
+        Function::TimeSpan => span(min_max(&samples, |_| true)?),
+        Function::Flightlines => {
+            let mut ids = samples.into_iter().map(|s| s.value).collect::<Vec<f64>>();
+            ids.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            ids.dedup();
+
+            ids.len() as f64
+        }
+        Function::Range(class) => span(min_max(&samples, |s| class.is_none_or(|c| s.class == c))?),
+        Function::Mode => mode(&samples).0,
+        Function::ClassAgreement => {
+            let (_, weight) = mode(&samples);
+
+            weight / total_weight(&samples)
+        }
+        Function::Spacing => match first_returns(&samples) {
+            0 => return None,
+            first => (res * res / first as f64).sqrt(),
+        },
+    };
+
+    Some(value)
+}
+
+/// Collapses cell `i` of the counts, or else of the bins of the variable of `func`.
+fn collapse_bin(
+    counts: Option<&[u32]>,
+    vars: &[Variable],
+    data: &Cells,
+    var: Variable,
+    res: f64,
+    min_count: usize,
+    func: &Function,
+    i: usize,
+) -> f64 {
+    if let Some(counts) = counts {
+        return match counts[i] {
+            0 => NODATA,
+            count if (count as usize) < min_count => NODATA,
+            count => count as f64,
+        };
     }
+
+    let var = vars
+        .iter()
+        .position(|v| *v == func.variable().unwrap_or(var))
+        .unwrap();
+
+    collapse_cell(data[var][i].clone(), func, res, min_count).unwrap_or(NODATA)
 }
 
-pub fn bin_points(
-    mut reader: Reader,
-    bounds: Bounds,
+/// Number of first returns among samples of the return number.
+fn first_returns(samples: &[Sample]) -> u64 {
+    samples.iter().filter(|s| s.value == 1.0).count() as u64
+}
+
+/// First returns and cells with any, over the bins of the return number.
+fn count_first_returns(bins: &[Vec<Sample>]) -> (u64, u64) {
+    bins.iter()
+        .map(|samples| first_returns(samples))
+        .filter(|first| *first > 0)
+        .fold((0, 0), |(first, cells), n| (first + n, cells + 1))
+}
+
+/// Appends a sample of `point` of each variable to the bins of `cells`.
+fn push_samples(data: &mut Cells, vars: &[Variable], cells: &[usize], point: &Point, weight: f32) {
+    for (var, bins) in vars.iter().zip(data.iter_mut()) {
+        let value = get_var(var, point);
+
+        // Missing values, e.g. GPS time in a point format without it.
+        if value.is_nan() {
+            continue;
+        }
+
+        // Append a variable (the point's Z value by default) to the cell bins
+        for i in cells.iter() {
+            bins[*i].push(Sample {
+                value,
+                weight,
+                class: u8::from(point.classification),
+                intensity: point.intensity,
+            });
+        }
+    }
+}
+
+/// The carried variable of the point a function picks per cell, as an argmax (or argmin).
+struct Carried {
+    /// Index of the function in `BinningParams::funcs`.
+    func: usize,
+    /// Index of the function's variable in the binned variables.
+    var: usize,
+    /// Whether the function picks the highest value, else the lowest.
+    highest: bool,
+    /// Per cell, the picked point's value of the function's variable and of the carried
+    /// variable. NaN for empty cells.
+    picked: Vec<(f64, f64)>,
+}
+
+impl Carried {
+    /// Picks a point with `value` and `carried` in `cells` over the one picked so far, if it's
+    /// higher (lower). Ties keep the first point.
+    fn pick(&mut self, value: f64, carried: f64, cells: &[usize]) {
+        if value.is_nan() {
+            return;
+        }
+
+        for i in cells.iter() {
+            let (picked, _) = self.picked[*i];
+            let better = match self.highest {
+                true => value > picked,
+                false => value < picked,
+            };
+
+            if picked.is_nan() || better {
+                self.picked[*i] = (value, carried);
+            }
+        }
+    }
+
+    fn value(&self, i: usize) -> f64 {
+        match self.picked[i].1 {
+            carried if carried.is_nan() => NODATA,
+            carried => carried,
+        }
+    }
+}
+
+/// Binned samples per cell, for each distinct variable used by the binning functions.
+pub struct Bins {
     res: f64,
-    class: Option<u8>,
     var: Variable,
-    func: Function,
-) -> Result<Vec<f64>> {
-    // Calculate the outpur raster's width and height
-    let (width, height) = get_raster_size(&bounds, res);
-    let len = width * height;
+    vars: Vec<Variable>,
+    data: Cells,
+    min_count: usize,
+    /// Point counts per cell instead of samples, when counting is all that's needed.
+    counts: Option<Vec<u32>>,
+    /// Per cell, whether any point was below the swath edge scan angle, None for empty cells.
+    below_swath_edge: Option<Vec<Option<bool>>>,
+    carried: Vec<Carried>,
+}
+
+impl Bins {
+    /// Collapses cell `i` with `func`, by default the cell bin's median.
+    pub fn collapse(&self, func: &Function, i: usize) -> f64 {
+        collapse_bin(
+            self.counts.as_deref(),
+            &self.vars,
+            &self.data,
+            self.var,
+            self.res,
+            self.min_count,
+            func,
+            i,
+        )
+    }
 
-    // Allocate the bins
-    let mut data: Vec<Vec<f64>> = vec![Vec::new(); len];
+    /// Nominal pulse density and spacing over the cells with first returns, if binned with
+    /// `Function::Spacing`.
+    pub fn pulse_density(&self) -> Option<(f64, f64)> {
+        let var = self
+            .vars
+            .iter()
+            .position(|v| *v == Variable::ReturnNumber)?;
 
-    for point in reader.points() {
-        let point = point?;
+        let (first, cells) = count_first_returns(&self.data[var]);
+
+        if cells == 0 {
+            return None;
+        }
+
+        let density = first as f64 / (cells as f64 * self.res * self.res);
+
+        Some((density, 1.0 / density.sqrt()))
+    }
+
+    /// Whether cell `i` is only covered by swath edge points. None for empty cells, or if
+    /// swath edges aren't tracked.
+    pub fn swath_edge(&self, i: usize) -> Option<bool> {
+        self.below_swath_edge.as_ref()?[i].map(|below| !below)
+    }
+
+    /// The carried variable of the point the `func`th function picked in cell `i`, NODATA for
+    /// empty cells, cells under the minimum count, or if it doesn't pick points.
+    pub fn carried(&self, func: usize, i: usize) -> f64 {
+        match self.carried.iter().find(|c| c.func == func) {
+            Some(c) if self.data[c.var][i].len() >= self.min_count => c.value(i),
+            _ => NODATA,
+        }
+    }
+}
+
+/// Bins points one at a time, so several binnings can share a single read of the input.
+pub struct Binner<'a> {
+    grid: GridDefinition,
+    var: Variable,
+    params: &'a BinningParams<'a>,
+    vars: Vec<Variable>,
+    data: Cells,
+    counts: Option<Vec<u32>>,
+    below_swath_edge: Option<Vec<Option<bool>>>,
+    carried: Vec<Carried>,
+    /// Cells the current point is binned into.
+    cells: Vec<usize>,
+}
+
+impl<'a> Binner<'a> {
+    pub fn new(grid: GridDefinition, var: Variable, params: &'a BinningParams<'a>) -> Self {
+        let len = grid.len();
+
+        // Functions may bin a different variable than `var`, each distinct variable gets its own
+        // set of bins.
+        let mut vars: Vec<Variable> = Vec::new();
+        for func in params.funcs.iter() {
+            let var = func.variable().unwrap_or(var);
+            if !vars.contains(&var) {
+                vars.push(var);
+            }
+        }
+
+        // Counting needs no samples, a flat array of counters keeps memory low for very large
+        // density rasters.
+        let count_only = params.funcs.iter().all(|f| matches!(f, Function::Count));
+        let counts = count_only.then(|| {
+            info!("Counting only, using compact counters");
+            vec![0u32; len]
+        });
+
+        // Allocate the bins
+        let data = match count_only {
+            true => Vec::new(),
+            false => vec![vec![Vec::new(); len]; vars.len()],
+        };
+        let below_swath_edge = params.swath_edge_angle.map(|_| vec![None; len]);
+        let carried = match params.carry {
+            Some(_) => params
+                .funcs
+                .iter()
+                .enumerate()
+                .filter_map(|(func, f)| {
+                    Some(Carried {
+                        func,
+                        var: vars
+                            .iter()
+                            .position(|v| *v == f.variable().unwrap_or(var))
+                            .unwrap(),
+                        highest: f.picks_highest()?,
+                        picked: vec![(f64::NAN, f64::NAN); len],
+                    })
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Self {
+            grid,
+            var,
+            params,
+            vars,
+            data,
+            counts,
+            below_swath_edge,
+            carried,
+            cells: Vec::new(),
+        }
+    }
+
+    /// Fills `cells` with the indices of the cells (x, y) a point at `x`, `y` is binned into:
+    /// those with their center within the focal radius, or the one it's in.
+    fn find_cells(&mut self, x: f64, y: f64) {
+        let grid = self.grid;
+        let (bounds, res) = (&grid.bounds, grid.res);
+        self.cells.clear();
+
+        let Some(radius) = self.params.focal_radius else {
+            if let Some((x_idx, y_idx)) = grid.cell(x, y) {
+                self.cells.push(grid.index(x_idx, y_idx));
+            }
+            return;
+        };
+
+        // Range of cells whose centers may be within the radius, clamped to the raster.
+        let range = |v: f64, min: f64, len: usize| {
+            let first = ((v - radius - min) / res - 0.5).ceil().max(0.0);
+            let last = ((v + radius - min) / res - 0.5)
+                .floor()
+                .min(len as f64 - 1.0);
+            (first as usize, last)
+        };
+        let (x_first, x_last) = range(x, bounds.min.x, grid.width);
+        let (y_first, y_last) = range(y, bounds.min.y, grid.height);
+
+        if x_last < 0.0 || y_last < 0.0 {
+            return;
+        }
+
+        for y_idx in y_first..=y_last as usize {
+            for x_idx in x_first..=x_last as usize {
+                let (center_x, center_y) = grid.cell_center(x_idx, y_idx);
+
+                if (center_x - x).powi(2) + (center_y - y).powi(2) <= radius * radius {
+                    self.cells.push(grid.index(x_idx, y_idx));
+                }
+            }
+        }
+    }
+
+    /// Bins a single point, if it passes `filter`.
+    pub fn add(&mut self, point: &Point, filter: &PointFilter, report: &mut Report) -> Result<()> {
+        let (grid, params) = (self.grid, self.params);
 
         // Filter out points if filter is present.
-        if let Some(c) = class {
-            if u8::from(point.classification) != c {
-                continue;
+        if let Some(criterion) = filter.rejects(point) {
+            report.filtered(criterion);
+            return Ok(());
+        }
+
+        // Get the array indices of the cells from the point's x, y position.
+        self.find_cells(point.x, point.y);
+        if self.cells.is_empty() {
+            return report.out_of_extent(point.x, point.y, params.strict);
+        }
+
+        // Points across a breakline from the cell center would smear the edge.
+        if let Some(breaklines) = params.breaklines {
+            self.cells.retain(|i| {
+                let (center_x, center_y) = grid.center(*i);
+
+                !breaklines.separates(*i, [point.x, point.y], [center_x, center_y])
+            });
+
+            if self.cells.is_empty() {
+                return Ok(());
+            }
+        }
+
+        report.points_used += 1;
+
+        if let (Some(angle), Some(below)) =
+            (params.swath_edge_angle, self.below_swath_edge.as_mut())
+        {
+            let point_below = (point.scan_angle.abs() as f64) < angle;
+            for i in self.cells.iter() {
+                below[*i] = Some(below[*i].unwrap_or(false) || point_below);
+            }
+        }
+
+        if let Some(carry) = params.carry {
+            let carried = get_var(&carry, point);
+
+            for c in self.carried.iter_mut() {
+                let var = params.funcs[c.func].variable().unwrap_or(self.var);
+                c.pick(get_var(&var, point), carried, &self.cells);
+            }
+        }
+
+        if let Some(counts) = self.counts.as_mut() {
+            // Missing values aren't counted, as with samples.
+            if !get_var(&self.var, point).is_nan() {
+                for i in self.cells.iter() {
+                    counts[*i] = counts[*i].saturating_add(1);
+                }
             }
+
+            return Ok(());
         }
 
-        // Get an array index from the point's x, y position.
-        let x_idx = ((point.x - bounds.min.x) / res).floor() as usize;
-        let y_idx = ((point.y - bounds.min.y) / res).floor() as usize;
-        let i = y_idx * width + x_idx;
+        let weight = match params.weight_by_returns {
+            true => 1.0 / point.number_of_returns.max(1) as f32,
+            false => 1.0,
+        };
+
+        push_samples(&mut self.data, &self.vars, &self.cells, point, weight);
 
-        // Get the array of values for a given cell, ignore out of bounds.
-        if let Some(cell) = data.get_mut(i) {
-            // Append a variable (the point's Z value by default) to the cell bin
-            cell.push(get_var(&var, &point));
+        Ok(())
+    }
+
+    pub fn finish(self) -> Bins {
+        Bins {
+            res: self.grid.res,
+            var: self.var,
+            vars: self.vars,
+            data: self.data,
+            min_count: self.params.min_count,
+            counts: self.counts,
+            below_swath_edge: self.below_swath_edge,
+            carried: self.carried,
         }
     }
+}
+
+#[instrument(name = "bin", skip_all, fields(res = grid.res))]
+pub fn bin_points(
+    mut input: Input,
+    grid: GridDefinition,
+    filter: &PointFilter,
+    var: Variable,
+    params: &BinningParams,
+    report: &mut Report,
+) -> Result<Bins> {
+    let mut binner = Binner::new(grid, var, params);
+
+    while let Some(point) = input.next_point(report)? {
+        binner.add(&point, filter, report)?;
+    }
 
-    // Return an "Ok" result, collapsing each cell into a single value given a certain function,
-    // by default the cell bin's median.
-    Ok(data
-        .into_iter()
-        .map(|cell| collapse_cell(cell, &func))
-        .collect::<Vec<f64>>())
+    Ok(binner.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(values: &[f64]) -> Vec<Sample> {
+        values
+            .iter()
+            .map(|&value| Sample {
+                value,
+                weight: 1.0,
+                class: 2,
+                intensity: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn percentiles() {
+        let sorted = samples(&[1.0, 2.0, 3.0, 4.0]);
+
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 25.0), 1.75);
+        assert_eq!(percentile(&sorted, 50.0), 2.5);
+        assert_eq!(percentile(&sorted, 100.0), 4.0);
+        assert_eq!(percentile(&samples(&[7.0]), 90.0), 7.0);
+    }
+
+    #[test]
+    fn collapse() {
+        let unsorted = samples(&[4.0, 1.0, 3.0, 2.0, 5.0]);
+        let collapse =
+            |function, min_count| collapse_cell(unsorted.clone(), &function, 1.0, min_count);
+
+        assert_eq!(collapse(Function::Median, 1), Some(3.0));
+        assert_eq!(collapse(Function::Mean, 1), Some(3.0));
+        assert_eq!(collapse(Function::Min, 1), Some(1.0));
+        assert_eq!(collapse(Function::Range(None), 1), Some(4.0));
+        assert_eq!(collapse(Function::Range(Some(6)), 1), None);
+        assert_eq!(collapse(Function::Count, 5), Some(5.0));
+        assert_eq!(collapse(Function::Count, 6), None);
+        assert_eq!(collapse_cell(Vec::new(), &Function::Max, 1.0, 0), None);
+    }
 }
@@ -0,0 +1,184 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::util::GridDefinition;
+
+/// Rows of the output rendered between checkpoints.
+pub const CHECKPOINT_ROWS: usize = 256;
+
+/// Progress of a long triangulation run, saved next to its output so a crashed run can carry on
+/// from its last completed stage: the triangulation is saved once built, then the rows of the
+/// output as they're written.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The run's arguments, a checkpoint of another run is ignored.
+    run: String,
+    /// The output's grid, saved as its origin, resolution and size. A checkpoint of another
+    /// extent or resolution is ignored.
+    grid: GridDefinition,
+    /// Rows of the output written.
+    pub rows_done: usize,
+}
+
+/// `path` with `suffix` appended to its file name.
+fn sidecar(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+
+    path.with_file_name(name)
+}
+
+impl Checkpoint {
+    pub fn new(run: String, grid: GridDefinition) -> Self {
+        Self {
+            run,
+            grid,
+            rows_done: 0,
+        }
+    }
+
+    /// The checkpoint file of `output`.
+    fn path(output: &Path) -> PathBuf {
+        sidecar(output, ".checkpoint")
+    }
+
+    /// The saved triangulation of `output`'s run.
+    pub fn tin_path(output: &Path) -> PathBuf {
+        sidecar(output, ".checkpoint.tin")
+    }
+
+    /// The checkpoint of the run `run` writing `output` on `grid`, None if there's none or it
+    /// belongs to another run.
+    pub fn load(output: &Path, run: &str, grid: &GridDefinition) -> Result<Option<Self>> {
+        let path = Self::path(output);
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let checkpoint: Self = toml::from_str(&text)
+            .map_err(|e| Error::Checkpoint(format!("{}: {e}", path.display())))?;
+
+        if checkpoint.run != run || checkpoint.grid != *grid || !Self::tin_path(output).exists() {
+            warn!(
+                "Ignoring {}, it's from a different run or incomplete",
+                path.display()
+            );
+            return Ok(None);
+        }
+
+        info!(
+            "Resuming from {}, {} rows written",
+            path.display(),
+            checkpoint.rows_done
+        );
+
+        Ok(Some(checkpoint))
+    }
+
+    /// Saves the checkpoint of `output`, replacing the previous one in a single step so a crash
+    /// while saving leaves it intact.
+    pub fn save(&self, output: &Path) -> Result<()> {
+        let path = Self::path(output);
+        let tmp = sidecar(output, ".checkpoint.tmp");
+
+        let text = toml::to_string(self).map_err(|e| Error::Checkpoint(e.to_string()))?;
+        fs::write(&tmp, text)?;
+        fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Removes the checkpoint and saved triangulation of `output`, once its run is done.
+    pub fn remove(output: &Path) -> Result<()> {
+        for path in [Self::path(output), Self::tin_path(output)] {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use las::{Bounds, Vector};
+
+    use super::*;
+
+    fn grid(res: f64) -> GridDefinition {
+        let bounds = Bounds {
+            min: Vector {
+                x: 100.0,
+                y: 200.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: 110.0,
+                y: 205.0,
+                z: 0.0,
+            },
+        };
+
+        GridDefinition::new(bounds, res)
+    }
+
+    #[test]
+    fn sidecars() {
+        let output = Path::new("out/dem.tif");
+
+        assert_eq!(
+            Checkpoint::path(output),
+            Path::new("out/dem.tif.checkpoint")
+        );
+        assert_eq!(
+            Checkpoint::tin_path(output),
+            Path::new("out/dem.tif.checkpoint.tin")
+        );
+    }
+
+    #[test]
+    fn resumes_only_the_same_run() {
+        let output = crate::util::temp_path("checkpointed.tif");
+        assert!(Checkpoint::load(&output, "run", &grid(1.0))
+            .unwrap()
+            .is_none());
+
+        let mut checkpoint = Checkpoint::new("run".to_string(), grid(1.0));
+        checkpoint.rows_done = 512;
+        checkpoint.save(&output).unwrap();
+
+        // Not without the saved triangulation.
+        assert!(Checkpoint::load(&output, "run", &grid(1.0))
+            .unwrap()
+            .is_none());
+
+        fs::write(Checkpoint::tin_path(&output), b"").unwrap();
+        let loaded = Checkpoint::load(&output, "run", &grid(1.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.rows_done, 512);
+
+        assert!(Checkpoint::load(&output, "other run", &grid(1.0))
+            .unwrap()
+            .is_none());
+        assert!(Checkpoint::load(&output, "run", &grid(0.5))
+            .unwrap()
+            .is_none());
+
+        Checkpoint::remove(&output).unwrap();
+        assert!(!Checkpoint::path(&output).exists());
+        assert!(!Checkpoint::tin_path(&output).exists());
+        Checkpoint::remove(&output).unwrap();
+    }
+}
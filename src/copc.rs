@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use las::{Bounds, Header};
+
+use crate::error::{Error, Result};
+
+/// Size of a hierarchy entry: key (4 x i32), offset (u64), byte size (i32), point count (i32).
+const ENTRY_SIZE: usize = 32;
+
+/// Deepest octree level accepted, far beyond any real point spacing. Bounds the recursion into
+/// child pages too, which are one level deeper at least.
+const MAX_LEVEL: i32 = 32;
+
+/// The COPC info VLR, see https://copc.io.
+struct CopcInfo {
+    center: [f64; 3],
+    halfsize: f64,
+    root_hier_offset: u64,
+    root_hier_size: u64,
+}
+
+impl CopcInfo {
+    fn from_header(header: &Header) -> Option<Self> {
+        let vlr = header
+            .vlrs()
+            .iter()
+            .find(|vlr| vlr.user_id == "copc" && vlr.record_id == 1)?;
+
+        let f64_at = |i: usize| -> Option<f64> {
+            Some(f64::from_le_bytes(vlr.data.get(i..i + 8)?.try_into().ok()?))
+        };
+        let u64_at = |i: usize| -> Option<u64> {
+            Some(u64::from_le_bytes(vlr.data.get(i..i + 8)?.try_into().ok()?))
+        };
+
+        // center x, y, z, halfsize, spacing, root hierarchy offset and size, ...
+        Some(Self {
+            center: [f64_at(0)?, f64_at(8)?, f64_at(16)?],
+            halfsize: f64_at(24)?,
+            root_hier_offset: u64_at(40)?,
+            root_hier_size: u64_at(48)?,
+        })
+    }
+}
+
+/// An octree node with points.
+struct Node {
+    /// Level, x, y, z.
+    key: [i32; 4],
+    /// Byte offset of the node's LAZ chunk.
+    offset: u64,
+    point_count: u64,
+}
+
+/// The hierarchy being read: the file's length, and the pages read so far, so a page referencing
+/// itself or an ancestor is an error rather than endless recursion.
+struct Hierarchy {
+    len: u64,
+    visited: HashSet<u64>,
+}
+
+/// Reads every node with points of the hierarchy page at `offset`, recursing into child pages.
+/// `depth` is the page's nesting.
+fn read_page<R: Read + Seek>(
+    read: &mut R,
+    hierarchy: &mut Hierarchy,
+    offset: u64,
+    size: u64,
+    depth: i32,
+    nodes: &mut Vec<Node>,
+) -> Result<()> {
+    if offset
+        .checked_add(size)
+        .is_none_or(|end| end > hierarchy.len)
+    {
+        return Err(Error::Copc(format!(
+            "hierarchy page of {size} bytes at {offset} is past the end of the file"
+        )));
+    }
+    if depth > MAX_LEVEL {
+        return Err(Error::Copc(format!(
+            "hierarchy pages nested deeper than {MAX_LEVEL} levels"
+        )));
+    }
+    if !hierarchy.visited.insert(offset) {
+        return Err(Error::Copc(format!(
+            "hierarchy page at {offset} is referenced more than once"
+        )));
+    }
+
+    let mut page = vec![0u8; size as usize];
+    read.seek(SeekFrom::Start(offset))?;
+    read.read_exact(&mut page)?;
+
+    for entry in page.chunks_exact(ENTRY_SIZE) {
+        let i32_at = |i: usize| i32::from_le_bytes(entry[i..i + 4].try_into().unwrap());
+
+        let key = [i32_at(0), i32_at(4), i32_at(8), i32_at(12)];
+        let offset = u64::from_le_bytes(entry[16..24].try_into().unwrap());
+        let byte_size = i32_at(24);
+        let point_count = i32_at(28);
+
+        if !(0..=MAX_LEVEL).contains(&key[0]) {
+            return Err(Error::Copc(format!(
+                "hierarchy entry of octree level {}",
+                key[0]
+            )));
+        }
+
+        match point_count {
+            -1 if byte_size >= 0 => {
+                read_page(read, hierarchy, offset, byte_size as u64, depth + 1, nodes)?
+            }
+            0 => {}
+            count if count > 0 => nodes.push(Node {
+                key,
+                offset,
+                point_count: count as u64,
+            }),
+            _ => {
+                return Err(Error::Copc(format!(
+                    "hierarchy entry with {point_count} points and {byte_size} bytes"
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Point index ranges of the COPC file read by `read`, whose octree nodes intersect `extent` in
+/// x/y. None if the file isn't COPC.
+pub fn copc_ranges<R: Read + Seek>(
+    read: &mut R,
+    header: &Header,
+    extent: &Bounds,
+) -> Result<Option<Vec<Range<u64>>>> {
+    let Some(info) = CopcInfo::from_header(header) else {
+        return Ok(None);
+    };
+
+    let mut hierarchy = Hierarchy {
+        len: read.seek(SeekFrom::End(0))?,
+        visited: HashSet::new(),
+    };
+    let mut nodes = Vec::new();
+    read_page(
+        read,
+        &mut hierarchy,
+        info.root_hier_offset,
+        info.root_hier_size,
+        0,
+        &mut nodes,
+    )?;
+
+    // Node chunks are laid out one after another, so ordering them by offset gives each node's
+    // first point index.
+    nodes.sort_by_key(|n| n.offset);
+
+    let mut ranges: Vec<Range<u64>> = Vec::new();
+    let mut start = 0;
+
+    for node in nodes.iter() {
+        let [level, x, y, _] = node.key;
+        let side = 2.0 * info.halfsize / (1u64 << level) as f64;
+        let min_x = info.center[0] - info.halfsize + x as f64 * side;
+        let min_y = info.center[1] - info.halfsize + y as f64 * side;
+
+        let intersects = min_x <= extent.max.x
+            && min_x + side >= extent.min.x
+            && min_y <= extent.max.y
+            && min_y + side >= extent.min.y;
+
+        if intersects {
+            match ranges.last_mut() {
+                // Merge adjacent nodes, saving a seek.
+                Some(last) if last.end == start => last.end = start + node.point_count,
+                _ => ranges.push(start..start + node.point_count),
+            }
+        }
+
+        start += node.point_count;
+    }
+
+    Ok(Some(ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use las::{Builder, Vector, Vlr};
+
+    use super::*;
+
+    /// A header with the COPC info of a cube of half size 8 around the origin, its root
+    /// hierarchy page at `offset`.
+    fn header(offset: u64, size: u64) -> Header {
+        let mut data = vec![0u8; 160];
+        data[24..32].copy_from_slice(&8.0f64.to_le_bytes());
+        data[40..48].copy_from_slice(&offset.to_le_bytes());
+        data[48..56].copy_from_slice(&size.to_le_bytes());
+
+        let mut builder = Builder::from((1, 4));
+        builder.vlrs.push(Vlr {
+            user_id: "copc".to_string(),
+            record_id: 1,
+            description: "COPC info".to_string(),
+            data,
+        });
+
+        builder.into_header().unwrap()
+    }
+
+    fn entry(key: [i32; 4], offset: u64, byte_size: i32, point_count: i32) -> Vec<u8> {
+        let mut entry: Vec<u8> = key.iter().flat_map(|k| k.to_le_bytes()).collect();
+        entry.extend(offset.to_le_bytes());
+        entry.extend(byte_size.to_le_bytes());
+        entry.extend(point_count.to_le_bytes());
+
+        entry
+    }
+
+    /// A file of `len` bytes with hierarchy pages at their offsets.
+    fn file(len: usize, pages: &[(usize, Vec<Vec<u8>>)]) -> Cursor<Vec<u8>> {
+        let mut file = vec![0u8; len];
+        for (offset, entries) in pages {
+            let page = entries.concat();
+            file[*offset..offset + page.len()].copy_from_slice(&page);
+        }
+
+        Cursor::new(file)
+    }
+
+    fn extent(x: f64, y: f64) -> Bounds {
+        Bounds {
+            min: Vector {
+                x: x - 0.1,
+                y: y - 0.1,
+                z: 0.0,
+            },
+            max: Vector {
+                x: x + 0.1,
+                y: y + 0.1,
+                z: 0.0,
+            },
+        }
+    }
+
+    #[test]
+    fn not_copc() {
+        let header = Builder::from((1, 4)).into_header().unwrap();
+
+        assert!(copc_ranges(&mut file(0, &[]), &header, &extent(0.0, 0.0))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn ranges() {
+        let header = header(100, 96);
+        let mut read = file(
+            232,
+            &[
+                (
+                    100,
+                    vec![
+                        entry([0, 0, 0, 0], 1000, 500, 10),
+                        entry([1, 0, 0, 0], 2000, 300, 5),
+                        entry([1, 1, 1, 0], 200, 32, -1),
+                    ],
+                ),
+                (200, vec![entry([1, 1, 1, 0], 3000, 400, 7)]),
+            ],
+        );
+
+        let ranges = |x, y, read: &mut Cursor<Vec<u8>>| {
+            copc_ranges(read, &header, &extent(x, y)).unwrap().unwrap()
+        };
+
+        assert_eq!(ranges(4.0, 4.0, &mut read), [0..10, 15..22]);
+        assert_eq!(ranges(-4.0, -4.0, &mut read), [0..15]);
+        assert!(ranges(20.0, 20.0, &mut read).is_empty());
+    }
+
+    #[test]
+    fn invalid_hierarchies() {
+        let invalid = |header: Header, mut read: Cursor<Vec<u8>>| {
+            matches!(
+                copc_ranges(&mut read, &header, &extent(0.0, 0.0)),
+                Err(Error::Copc(_))
+            )
+        };
+
+        // A page referencing itself.
+        assert!(invalid(
+            header(100, 32),
+            file(132, &[(100, vec![entry([1, 0, 0, 0], 100, 32, -1)])])
+        ));
+        // A page past the end of the file.
+        assert!(invalid(header(100, 64), file(132, &[])));
+        // An entry beyond the deepest level.
+        assert!(invalid(
+            header(100, 32),
+            file(132, &[(100, vec![entry([40, 0, 0, 0], 1000, 10, 1)])])
+        ));
+        // An entry with a negative point count other than a child page's.
+        assert!(invalid(
+            header(100, 32),
+            file(132, &[(100, vec![entry([1, 0, 0, 0], 1000, 10, -2)])])
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+use gdal::spatial_ref::{AxisMappingStrategy, CoordTransform, SpatialRef};
+use las::{Bounds, Vector};
+
+use crate::error::Result;
+
+/// A CRS from a user definition (e.g. `EPSG:4326`, WKT or a PROJ string), with x as the
+/// easting or longitude whatever the CRS' axis order.
+pub fn spatial_ref(definition: &str) -> Result<SpatialRef> {
+    let mut srs = SpatialRef::from_definition(definition)?;
+    srs.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+
+    Ok(srs)
+}
+
+/// The XY bounding box of `bounds` transformed from `from` to `to`, along densified edges so
+/// curved edges are covered. Z is kept as is.
+pub fn transform_bounds(bounds: &Bounds, from: &SpatialRef, to: &SpatialRef) -> Result<Bounds> {
+    let [min_x, min_y, max_x, max_y] = CoordTransform::new(from, to)?.transform_bounds(
+        &[bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y],
+        21,
+    )?;
+
+    Ok(Bounds {
+        min: Vector {
+            x: min_x,
+            y: min_y,
+            z: bounds.min.z,
+        },
+        max: Vector {
+            x: max_x,
+            y: max_y,
+            z: bounds.max.z,
+        },
+    })
+}
+
+/// Units of elevations in a CRS.
+#[derive(Debug, Clone, Default)]
+pub struct VerticalUnits {
+    /// Unit name, e.g. 'metre'.
+    pub unit: Option<String>,
+    /// Metres per unit.
+    pub metres: Option<f64>,
+    /// Name of the vertical CRS, of a compound CRS.
+    pub crs: Option<String>,
+}
+
+/// The units of elevations in the CRS with the WKT `wkt`, and its vertical CRS if it's a
+/// compound one. Without a vertical CRS, elevations are assumed to share the units of a
+/// projected CRS, and unknown for a geographic one.
+pub fn vertical_units(wkt: &str) -> Result<VerticalUnits> {
+    let srs = SpatialRef::from_wkt(wkt)?;
+
+    match srs.get_attr_value("VERT_CS", 0)? {
+        Some(vertical) => Ok(VerticalUnits {
+            unit: srs.get_attr_value("COMPD_CS|VERT_CS|UNIT", 0)?,
+            metres: srs
+                .get_attr_value("COMPD_CS|VERT_CS|UNIT", 1)?
+                .and_then(|factor| factor.parse().ok()),
+            crs: Some(vertical),
+        }),
+        None => match srs.is_projected() {
+            true => Ok(VerticalUnits {
+                unit: Some(srs.linear_units_name()?),
+                metres: Some(srs.linear_units()),
+                crs: None,
+            }),
+            false => Ok(VerticalUnits::default()),
+        },
+    }
+}
+
+/// Whether the CRS with the WKT `wkt` has planar coordinates, projected or local (e.g. a site
+/// grid).
+pub fn is_planar(wkt: &str) -> bool {
+    SpatialRef::from_wkt(wkt).is_ok_and(|srs| srs.is_projected() || srs.is_local())
+}
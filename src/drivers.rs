@@ -0,0 +1,89 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use gdal::{Driver, DriverManager, Metadata};
+
+use crate::error::{Error, Result};
+
+/// Raster or vector data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverKind {
+    Raster,
+    Vector,
+}
+
+impl DriverKind {
+    fn capability(&self) -> &'static str {
+        match self {
+            DriverKind::Raster => "DCAP_RASTER",
+            DriverKind::Vector => "DCAP_VECTOR",
+        }
+    }
+}
+
+/// A driver of the linked GDAL able to create datasets, with the file extensions it claims.
+struct Creatable {
+    name: String,
+    extensions: Vec<String>,
+}
+
+/// The drivers of `kind` able to create datasets, in GDAL's registration order. Probed on first
+/// use, once, whichever thread gets there first.
+fn creatable(kind: DriverKind) -> &'static [Creatable] {
+    static RASTER: OnceLock<Vec<Creatable>> = OnceLock::new();
+    static VECTOR: OnceLock<Vec<Creatable>> = OnceLock::new();
+
+    let probe = || {
+        DriverManager::all()
+            .filter(|d| {
+                d.metadata_item(kind.capability(), "").is_some()
+                    && d.metadata_item("DCAP_CREATE", "").is_some()
+            })
+            .map(|d| Creatable {
+                name: d.short_name(),
+                extensions: d
+                    .metadata_item("DMD_EXTENSIONS", "")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(|e| e.to_lowercase())
+                    .collect(),
+            })
+            .collect()
+    };
+
+    match kind {
+        DriverKind::Raster => RASTER.get_or_init(probe),
+        DriverKind::Vector => VECTOR.get_or_init(probe),
+    }
+}
+
+/// Finds a driver of `kind` with creation support for the extension of `path`. Later raster
+/// drivers take precedence for a shared extension, earlier vector drivers. Lists the drivers
+/// that could be used instead if there's none.
+pub fn create_driver(path: &Path, kind: DriverKind) -> Result<Driver> {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let drivers = creatable(kind);
+    let claims = |d: &&Creatable| d.extensions.contains(&ext);
+
+    let found = match kind {
+        DriverKind::Raster => drivers.iter().rev().find(claims),
+        DriverKind::Vector => drivers.iter().find(claims),
+    };
+
+    match found {
+        Some(d) => Ok(DriverManager::get_driver_by_name(&d.name)?),
+        None => Err(Error::NoDriverForExtension(
+            ext,
+            drivers
+                .iter()
+                .filter(|d| !d.extensions.is_empty())
+                .map(|d| format!("{} ({})", d.name, d.extensions.join(", ")))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )),
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use las::Bounds;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::error::{Error, Result};
+use crate::remote::is_url;
+
+/// The parts of `ept.json` needed for reading, see
+/// https://entwine.io/en/latest/entwine-point-tile.html.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EptInfo {
+    /// Cubic bounds of the octree, [min x, y, z, max x, y, z].
+    bounds: [f64; 6],
+    data_type: String,
+    /// Points per axis of a node's grid, the node's spacing is its width / span.
+    span: u64,
+}
+
+/// Whether `path` is an EPT dataset rather than a point cloud file.
+pub fn is_ept(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == "ept.json")
+}
+
+/// Reads the hierarchy file of the subtree rooted at `key`, adding every node to `nodes`.
+fn read_hierarchy(root: &Path, key: &str, nodes: &mut Vec<[u64; 4]>) -> Result<()> {
+    let path = root.join("ept-hierarchy").join(format!("{key}.json"));
+    let hierarchy: HashMap<String, i64> = serde_json::from_str(&fs::read_to_string(&path)?)
+        .map_err(|e| Error::Ept(format!("{}: {e}", path.display())))?;
+
+    for (node, count) in hierarchy {
+        let parsed: Vec<u64> = node.split('-').filter_map(|n| n.parse().ok()).collect();
+
+        let [d, x, y, z] = parsed[..] else {
+            return Err(Error::Ept(format!("Invalid node key '{node}'")));
+        };
+
+        match count {
+            // Subtree in its own hierarchy file.
+            -1 if node != key => read_hierarchy(root, &node, nodes)?,
+            0 => {}
+            _ => nodes.push([d, x, y, z]),
+        }
+    }
+
+    Ok(())
+}
+
+/// Node files of the EPT dataset at `path` intersecting `extent`, down to the depth whose
+/// spacing is finer than `res`. Nodes at shallower depths hold points too, so these are a
+/// thinned version of the full dataset.
+pub fn ept_files(path: &Path, extent: Option<&Bounds>, res: f64) -> Result<Vec<PathBuf>> {
+    let name = path.display().to_string();
+
+    if is_url(path) {
+        return Err(Error::Unsupported(format!("remote EPT datasets ({name})")));
+    }
+
+    let info: EptInfo = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| Error::Ept(format!("{name}: {e}")))?;
+
+    if info.data_type != "laszip" {
+        return Err(Error::Unsupported(format!(
+            "EPT data type '{}', only laszip",
+            info.data_type
+        )));
+    }
+
+    let root = path.parent().unwrap_or(Path::new("."));
+    let [min_x, min_y, _, max_x, ..] = info.bounds;
+    let width = max_x - min_x;
+
+    // The first depth whose spacing is at least as fine as the resolution.
+    let max_depth = (0..32)
+        .find(|d| width / (info.span as f64 * (1u64 << d) as f64) <= res)
+        .unwrap_or(32);
+
+    let mut nodes = Vec::new();
+    read_hierarchy(root, "0-0-0-0", &mut nodes)?;
+
+    let total = nodes.len();
+
+    let mut files: Vec<PathBuf> = nodes
+        .into_iter()
+        .filter(|[d, x, y, _]| {
+            let side = width / (1u64 << d) as f64;
+            let (node_x, node_y) = (min_x + *x as f64 * side, min_y + *y as f64 * side);
+
+            *d <= max_depth
+                && extent.is_none_or(|e| {
+                    node_x <= e.max.x
+                        && node_x + side >= e.min.x
+                        && node_y <= e.max.y
+                        && node_y + side >= e.min.y
+                })
+        })
+        .map(|[d, x, y, z]| root.join("ept-data").join(format!("{d}-{x}-{y}-{z}.laz")))
+        .collect();
+
+    files.sort();
+
+    info!(
+        "{name}: reading {} of {total} nodes, to depth {max_depth}",
+        files.len()
+    );
+
+    Ok(files)
+}
@@ -10,14 +10,92 @@ pub enum Error {
     #[error("Las Error: {0}")]
     Las(#[from] las::Error),
 
+    #[error("Las Error in {0}: {1}")]
+    InputFile(String, las::Error),
+
+    #[error("No point cloud files found for '{0}'")]
+    NoInput(String),
+
     #[error("GDAL Error: {0}")]
     Gdal(#[from] gdal::errors::GdalError),
 
     #[error("Triangulation Insertion Error: {0}")]
     Insertion(#[from] spade::InsertionError),
 
-    #[error("Couldn't find a valid GDAL driver for extension '{0}'")]
-    NoDriverForExtension(String),
+    #[error("Couldn't find a GDAL driver creating '{0}' files. This GDAL build can create: {1}")]
+    NoDriverForExtension(String, String),
+
+    #[error("CRS Error: {0}")]
+    Crs(String),
+
+    #[error("Vector feature is missing a value for field '{0}'")]
+    MissingField(String),
+
+    #[error("Likely unit mismatch: {0}. Use --force to proceed anyway")]
+    UnitMismatch(String),
+
+    #[error("Point ({0}, {1}) is outside of the raster extent")]
+    OutOfExtent(f64, f64),
+
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+
+    #[error("Remote input Error: {0}")]
+    Remote(String),
+
+    #[error("Extra bytes Error: {0}")]
+    ExtraBytes(String),
+
+    #[error("Text input Error: {0}")]
+    TextInput(String),
+
+    #[error("PLY Error: {0}")]
+    Ply(String),
+
+    #[error("PCD Error: {0}")]
+    Pcd(String),
+
+    #[error("LAX index Error: {0}")]
+    Lax(String),
+
+    #[error("EPT Error: {0}")]
+    Ept(String),
+
+    #[error("COPC Error: {0}")]
+    Copc(String),
+
+    #[error("'{0}' is not a saved triangulation")]
+    InvalidTin(String),
+
+    #[error("Invalid products manifest: {0}")]
+    Manifest(String),
+
+    #[error("Filter profile Error: {0}")]
+    Profile(String),
+
+    #[error("Checkpoint Error: {0}")]
+    Checkpoint(String),
+
+    #[error("Telemetry Error: {0}")]
+    Telemetry(String),
+
+    #[error(transparent)]
+    Usage(#[from] clap::Error),
+
+    #[error("No data within the extent after filtering, not writing a raster")]
+    Empty,
+
+    #[error("Invalid input: {0}")]
+    Invalid(String),
+
+    #[error("Cancelled")]
+    Cancelled,
+
+    #[error("Verification failed: {0}")]
+    Verify(String),
+
+    #[error("Output too large: {0}")]
+    TooLarge(String),
 
     #[error("Something happened that really shouldn't: {0}")]
     ShouldntHappen(String),
@@ -0,0 +1,467 @@
+use std::fmt;
+
+use las::Point;
+
+/// A point dimension of filter expressions, named as in PDAL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    X,
+    Y,
+    Z,
+    Intensity,
+    ReturnNumber,
+    NumberOfReturns,
+    Classification,
+    /// In degrees, whatever the point format stores it as.
+    ScanAngle,
+    UserData,
+    PointSourceId,
+    GpsTime,
+    ScanDirectionFlag,
+    EdgeOfFlightLine,
+    Withheld,
+    Synthetic,
+    KeyPoint,
+    Overlap,
+    Red,
+    Green,
+    Blue,
+    Infrared,
+}
+
+const DIMENSIONS: &[(&str, Dimension)] = &[
+    ("X", Dimension::X),
+    ("Y", Dimension::Y),
+    ("Z", Dimension::Z),
+    ("Intensity", Dimension::Intensity),
+    ("ReturnNumber", Dimension::ReturnNumber),
+    ("NumberOfReturns", Dimension::NumberOfReturns),
+    ("Classification", Dimension::Classification),
+    ("ScanAngle", Dimension::ScanAngle),
+    ("UserData", Dimension::UserData),
+    ("PointSourceId", Dimension::PointSourceId),
+    ("GpsTime", Dimension::GpsTime),
+    ("ScanDirectionFlag", Dimension::ScanDirectionFlag),
+    ("EdgeOfFlightLine", Dimension::EdgeOfFlightLine),
+    ("Withheld", Dimension::Withheld),
+    ("Synthetic", Dimension::Synthetic),
+    ("KeyPoint", Dimension::KeyPoint),
+    ("Overlap", Dimension::Overlap),
+    ("Red", Dimension::Red),
+    ("Green", Dimension::Green),
+    ("Blue", Dimension::Blue),
+    ("Infrared", Dimension::Infrared),
+];
+
+impl Dimension {
+    /// The point's value, NaN if its format doesn't have the dimension. Flags are 1 or 0.
+    fn value(&self, point: &Point) -> f64 {
+        let flag = |set: bool| match set {
+            true => 1.0,
+            false => 0.0,
+        };
+        let color =
+            |channel: fn(&las::Color) -> u16| point.color.map_or(f64::NAN, |c| channel(&c) as f64);
+
+        match self {
+            Dimension::X => point.x,
+            Dimension::Y => point.y,
+            Dimension::Z => point.z,
+            Dimension::Intensity => point.intensity as f64,
+            Dimension::ReturnNumber => point.return_number as f64,
+            Dimension::NumberOfReturns => point.number_of_returns as f64,
+            Dimension::Classification => u8::from(point.classification) as f64,
+            Dimension::ScanAngle => point.scan_angle as f64,
+            Dimension::UserData => point.user_data as f64,
+            Dimension::PointSourceId => point.point_source_id as f64,
+            Dimension::GpsTime => point.gps_time.unwrap_or(f64::NAN),
+            Dimension::ScanDirectionFlag => {
+                flag(point.scan_direction == las::point::ScanDirection::LeftToRight)
+            }
+            Dimension::EdgeOfFlightLine => flag(point.is_edge_of_flight_line),
+            Dimension::Withheld => flag(point.is_withheld),
+            Dimension::Synthetic => flag(point.is_synthetic),
+            Dimension::KeyPoint => flag(point.is_key_point),
+            Dimension::Overlap => flag(point.is_overlap),
+            Dimension::Red => color(|c| c.red),
+            Dimension::Green => color(|c| c.green),
+            Dimension::Blue => color(|c| c.blue),
+            Dimension::Infrared => point.nir.map_or(f64::NAN, f64::from),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn apply(&self, a: f64, b: f64) -> f64 {
+        let truth = |b: bool| match b {
+            true => 1.0,
+            false => 0.0,
+        };
+
+        match self {
+            Op::Or => truth(a != 0.0 || b != 0.0),
+            Op::And => truth(a != 0.0 && b != 0.0),
+            Op::Eq => truth(a == b),
+            // Not true of a missing dimension either.
+            Op::Ne => truth(a != b && !a.is_nan() && !b.is_nan()),
+            Op::Lt => truth(a < b),
+            Op::Le => truth(a <= b),
+            Op::Gt => truth(a > b),
+            Op::Ge => truth(a >= b),
+            Op::Add => a + b,
+            Op::Sub => a - b,
+            Op::Mul => a * b,
+            Op::Div => a / b,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Dimension(Dimension),
+    Not(Box<Node>),
+    Neg(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn eval(&self, point: &Point) -> f64 {
+        match self {
+            Node::Number(n) => *n,
+            Node::Dimension(d) => d.value(point),
+            Node::Not(node) => match node.eval(point) == 0.0 {
+                true => 1.0,
+                false => 0.0,
+            },
+            Node::Neg(node) => -node.eval(point),
+            Node::Binary(Op::And, a, b) => match a.eval(point) != 0.0 {
+                true => Op::And.apply(1.0, b.eval(point)),
+                false => 0.0,
+            },
+            Node::Binary(Op::Or, a, b) => match a.eval(point) != 0.0 {
+                true => 1.0,
+                false => Op::Or.apply(0.0, b.eval(point)),
+            },
+            Node::Binary(op, a, b) => op.apply(a.eval(point), b.eval(point)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(Op),
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('|', Some('|')) => (Token::Op(Op::Or), 2),
+            ('&', Some('&')) => (Token::Op(Op::And), 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('+', _) => (Token::Op(Op::Add), 1),
+            ('-', _) => (Token::Op(Op::Sub), 1),
+            ('*', _) => (Token::Op(Op::Mul), 1),
+            ('/', _) => (Token::Op(Op::Div), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            (c, _) if c.is_ascii_digit() || c == '.' => {
+                let digits = |from: usize| {
+                    chars[from..]
+                        .iter()
+                        .position(|c| !c.is_ascii_digit())
+                        .unwrap_or(chars.len() - from)
+                };
+
+                let mut len = chars[i..]
+                    .iter()
+                    .position(|c| !(c.is_ascii_digit() || *c == '.'))
+                    .unwrap_or(chars.len() - i);
+
+                // An exponent, [eE][+-]?digits.
+                if matches!(chars.get(i + len), Some('e' | 'E')) {
+                    let sign = matches!(chars.get(i + len + 1), Some('+' | '-')) as usize;
+                    let exponent = digits(i + len + 1 + sign);
+
+                    if exponent > 0 {
+                        len += 1 + sign + exponent;
+                    }
+                }
+
+                let number: String = chars[i..i + len].iter().collect();
+
+                let number = number
+                    .parse::<f64>()
+                    .map_err(|_| format!("'{number}' is not a number"))?;
+                (Token::Number(number), len)
+            }
+            (c, _) if c.is_ascii_alphabetic() => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+                    .unwrap_or(chars.len() - i);
+
+                (Token::Name(chars[i..i + len].iter().collect()), len)
+            }
+            (c, _) => return Err(format!("unexpected '{c}'")),
+        };
+
+        tokens.push(token);
+        i += len;
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, by increasing precedence: ||, &&, comparisons, + and -,
+/// * and /, then ! and unary -.
+struct Parser {
+    tokens: Vec<Token>,
+    i: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.i)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.i).cloned();
+        self.i += 1;
+        token
+    }
+
+    /// Left associative binary operators of `ops`, between operands parsed by `operand`.
+    fn binary(
+        &mut self,
+        ops: &[Op],
+        operand: fn(&mut Self) -> Result<Node, String>,
+    ) -> Result<Node, String> {
+        let mut node = operand(self)?;
+
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if !ops.contains(&op) {
+                break;
+            }
+
+            self.i += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(operand(self)?));
+        }
+
+        Ok(node)
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        self.binary(&[Op::Or], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        self.binary(&[Op::And], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        self.binary(&[Op::Eq, Op::Ne, Op::Lt, Op::Le, Op::Gt, Op::Ge], Self::sum)
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        self.binary(&[Op::Add, Op::Sub], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        self.binary(&[Op::Mul, Op::Div], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Node::Not(Box::new(self.unary()?))),
+            Some(Token::Op(Op::Sub)) => Ok(Node::Neg(Box::new(self.unary()?))),
+            Some(Token::Number(n)) => Ok(Node::Number(n)),
+            Some(Token::Name(name)) => DIMENSIONS
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(&name))
+                .map(|(_, d)| Node::Dimension(*d))
+                .ok_or_else(|| {
+                    let names: Vec<&str> = DIMENSIONS.iter().map(|(n, _)| *n).collect();
+                    format!("unknown dimension '{name}' [{}]", names.join(", "))
+                }),
+            Some(Token::Open) => {
+                let node = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(node),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {token:?}")),
+            None => Err("unexpected end of the expression".to_string()),
+        }
+    }
+}
+
+/// A filter expression over point dimensions, e.g.
+/// 'Classification == 2 && Intensity > 100 && ReturnNumber == NumberOfReturns'. Comparisons and
+/// logical operators give 1 or 0, and a point matches if the expression is nonzero.
+/// Comparisons with a dimension the point format lacks are false.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    /// As given.
+    source: String,
+    root: Node,
+}
+
+impl Expr {
+    pub fn matches(&self, point: &Point) -> bool {
+        let value = self.root.eval(point);
+        value != 0.0 && !value.is_nan()
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+pub fn expr_parser(s: &str) -> core::result::Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        i: 0,
+    };
+
+    let root = parser.or()?;
+    if let Some(token) = parser.peek() {
+        return Err(format!("unexpected {token:?} in '{s}'"));
+    }
+
+    Ok(Expr {
+        source: s.trim().to_string(),
+        root,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(s: &str, point: &Point) -> f64 {
+        expr_parser(s).unwrap().root.eval(point)
+    }
+
+    fn error(s: &str) -> String {
+        expr_parser(s).unwrap_err()
+    }
+
+    #[test]
+    fn precedence() {
+        let point = Point::default();
+
+        assert_eq!(eval("1 + 2 * 3", &point), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", &point), 9.0);
+        assert_eq!(eval("10 - 2 - 3", &point), 5.0);
+        assert_eq!(eval("8 / 4 / 2", &point), 1.0);
+        assert_eq!(eval("-2 * 3", &point), -6.0);
+        assert_eq!(eval("!0 + 1", &point), 2.0);
+        assert_eq!(eval("1 + 1 == 2", &point), 1.0);
+        assert_eq!(eval("1 || 0 && 0", &point), 1.0);
+        assert_eq!(eval("(1 || 0) && 0", &point), 0.0);
+    }
+
+    #[test]
+    fn numbers() {
+        let point = Point::default();
+
+        assert_eq!(eval("1.5", &point), 1.5);
+        assert_eq!(eval(".5", &point), 0.5);
+        assert_eq!(eval("1e3", &point), 1000.0);
+        assert_eq!(eval("2.5E-1", &point), 0.25);
+        assert_eq!(eval("1e+2", &point), 100.0);
+        assert_eq!(eval("1e2-1", &point), 99.0);
+    }
+
+    #[test]
+    fn short_circuits() {
+        let point = Point::default();
+
+        assert_eq!(eval("0 && 1 / 0", &point), 0.0);
+        assert_eq!(eval("1 || 1 / 0", &point), 1.0);
+        assert_eq!(eval("2 && 3", &point), 1.0);
+        assert_eq!(eval("0 || 0", &point), 0.0);
+    }
+
+    #[test]
+    fn missing_dimensions() {
+        let point = Point {
+            z: 10.0,
+            ..Default::default()
+        };
+
+        for s in [
+            "GpsTime > 0",
+            "GpsTime <= 0",
+            "GpsTime == GpsTime",
+            "GpsTime != 0",
+            "GpsTime",
+            "Red + 1",
+        ] {
+            assert!(!expr_parser(s).unwrap().matches(&point), "{s}");
+        }
+
+        assert!(expr_parser("!(GpsTime > 0)").unwrap().matches(&point));
+        assert!(expr_parser("Z > 1e0 && z == 10").unwrap().matches(&point));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("Z > 1.2.3"), "'1.2.3' is not a number");
+        assert_eq!(error("Z $ 1"), "unexpected '$'");
+        assert_eq!(error("(Z > 1"), "missing ')'");
+        assert_eq!(error("Z >"), "unexpected end of the expression");
+        assert_eq!(error("Z > 1)"), "unexpected Close in 'Z > 1)'");
+        assert_eq!(error("1e"), "unexpected Name(\"e\") in '1e'");
+        assert!(error("Height > 1").starts_with("unknown dimension 'Height' [X, Y, Z,"));
+    }
+}
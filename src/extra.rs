@@ -0,0 +1,271 @@
+use std::fmt;
+
+use las::Header;
+
+use crate::error::{Error, Result};
+use crate::ply::{Encoding, Scalar};
+
+/// Size of a record of the Extra Bytes VLR.
+const RECORD_SIZE: usize = 192;
+
+/// Where and how an extra-bytes dimension is stored in each point's extra bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Layout {
+    offset: usize,
+    scalar: Scalar,
+    scale: f64,
+    add: f64,
+    no_data: Option<f64>,
+}
+
+/// A LAS 1.4 extra-bytes dimension, by the name declared in the Extra Bytes VLR. The layout is
+/// resolved from the input headers with `resolve`, until then values are NaN.
+#[derive(Clone, Copy)]
+pub struct ExtraBytes {
+    name: &'static str,
+    layout: Option<Layout>,
+}
+
+impl fmt::Debug for ExtraBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl PartialEq for ExtraBytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for ExtraBytes {}
+
+/// Size and type of an extra-bytes data type, the type being None for undocumented bytes and
+/// the deprecated arrays.
+fn data_type(data_type: u8, options: u8) -> (usize, Option<Scalar>) {
+    let scalar = |t: u8| match t {
+        1 => Scalar::U8,
+        2 => Scalar::I8,
+        3 => Scalar::U16,
+        4 => Scalar::I16,
+        5 => Scalar::U32,
+        6 => Scalar::I32,
+        7 => Scalar::U64,
+        8 => Scalar::I64,
+        9 => Scalar::F32,
+        _ => Scalar::F64,
+    };
+
+    match data_type {
+        0 => (options as usize, None),
+        1..=10 => (scalar(data_type).size(), Some(scalar(data_type))),
+        11..=20 => (2 * scalar(data_type - 10).size(), None),
+        _ => (3 * scalar(data_type - 20).size(), None),
+    }
+}
+
+impl ExtraBytes {
+    /// The dimension called `name`, resolved later.
+    pub fn named(name: &str) -> Self {
+        Self {
+            // Variables are Copy, and only a handful are ever named.
+            name: Box::leak(name.to_string().into_boxed_str()),
+            layout: None,
+        }
+    }
+
+    /// The layout of the dimension in the Extra Bytes VLR of `header`.
+    fn layout(&self, header: &Header) -> Result<Layout> {
+        let missing = || Error::ExtraBytes(format!("no extra-bytes dimension '{}'", self.name));
+
+        let vlr = header
+            .vlrs()
+            .iter()
+            .chain(header.evlrs().iter())
+            .find(|vlr| vlr.user_id == "LASF_Spec" && vlr.record_id == 4)
+            .ok_or_else(missing)?;
+
+        let mut offset = 0;
+
+        for record in vlr.data.chunks_exact(RECORD_SIZE) {
+            let f64_at = |i: usize| f64::from_le_bytes(record[i..i + 8].try_into().unwrap());
+
+            let options = record[3];
+            let name = String::from_utf8_lossy(&record[4..36]);
+            let (size, scalar) = data_type(record[2], options);
+
+            if name.trim_end_matches('\0') != self.name {
+                offset += size;
+                continue;
+            }
+
+            let Some(scalar) = scalar else {
+                return Err(Error::Unsupported(format!(
+                    "extra-bytes dimension '{}' of data type {}",
+                    self.name, record[2]
+                )));
+            };
+
+            // Options: no data (bit 0), scale (bit 3) and offset (bit 4) present.
+            let flag = |bit: u8| options & (1 << bit) != 0;
+            let no_data = match scalar {
+                Scalar::F32 | Scalar::F64 => f64_at(40),
+                Scalar::U64 => u64::from_le_bytes(record[40..48].try_into().unwrap()) as f64,
+                _ => i64::from_le_bytes(record[40..48].try_into().unwrap()) as f64,
+            };
+
+            return Ok(Layout {
+                offset,
+                scalar,
+                scale: match flag(3) {
+                    true => f64_at(112),
+                    false => 1.0,
+                },
+                add: match flag(4) {
+                    true => f64_at(136),
+                    false => 0.0,
+                },
+                no_data: flag(0).then_some(no_data),
+            });
+        }
+
+        Err(missing())
+    }
+
+    /// Resolves the layout from the headers of every input, which have to agree on it.
+    pub fn resolve(self, headers: &[&Header]) -> Result<Self> {
+        let mut layout = None;
+
+        for header in headers {
+            let header_layout = self.layout(header)?;
+
+            match layout {
+                Some(layout) if layout != header_layout => {
+                    return Err(Error::ExtraBytes(format!(
+                        "'{}' is stored differently between the inputs",
+                        self.name
+                    )))
+                }
+                _ => layout = Some(header_layout),
+            }
+        }
+
+        Ok(Self {
+            name: self.name,
+            layout,
+        })
+    }
+
+    /// The dimension's value in a point's extra bytes, NaN where it's no data.
+    pub fn value(&self, bytes: &[u8]) -> f64 {
+        let Some(layout) = self.layout else {
+            return f64::NAN;
+        };
+
+        let Some(bytes) = bytes.get(layout.offset..layout.offset + layout.scalar.size()) else {
+            return f64::NAN;
+        };
+
+        let raw = layout.scalar.decode(bytes, Encoding::LittleEndian);
+
+        match layout.no_data {
+            Some(no_data) if raw == no_data => f64::NAN,
+            _ => raw * layout.scale + layout.add,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use las::{Builder, Vlr};
+
+    use super::*;
+
+    /// An Extra Bytes VLR record of `name`, `data_type` and `options`, with no data, scale and
+    /// offset values as given.
+    fn record(name: &str, data_type: u8, options: u8, values: [f64; 3]) -> Vec<u8> {
+        let mut record = vec![0u8; RECORD_SIZE];
+        record[2] = data_type;
+        record[3] = options;
+        record[4..4 + name.len()].copy_from_slice(name.as_bytes());
+
+        let [no_data, scale, offset] = values;
+        record[40..48].copy_from_slice(&no_data.to_le_bytes());
+        record[112..120].copy_from_slice(&scale.to_le_bytes());
+        record[136..144].copy_from_slice(&offset.to_le_bytes());
+
+        record
+    }
+
+    fn header(records: &[Vec<u8>]) -> Header {
+        let mut builder = Builder::from((1, 4));
+        builder.vlrs.push(Vlr {
+            user_id: "LASF_Spec".to_string(),
+            record_id: 4,
+            description: "Extra Bytes".to_string(),
+            data: records.concat(),
+        });
+
+        builder.into_header().unwrap()
+    }
+
+    #[test]
+    fn data_types() {
+        assert_eq!(data_type(0, 3), (3, None));
+        assert_eq!(data_type(1, 0), (1, Some(Scalar::U8)));
+        assert_eq!(data_type(4, 0), (2, Some(Scalar::I16)));
+        assert_eq!(data_type(9, 0), (4, Some(Scalar::F32)));
+        assert_eq!(data_type(10, 0), (8, Some(Scalar::F64)));
+        // Deprecated 2 and 3 element arrays.
+        assert_eq!(data_type(13, 0), (4, None));
+        assert_eq!(data_type(30, 0), (24, None));
+    }
+
+    #[test]
+    fn values() {
+        // Scale (bit 3), offset (bit 4) and no data (bit 0), after a plain u16 and 3 raw bytes.
+        let header = header(&[
+            record("Amplitude", 3, 0, [0.0; 3]),
+            record("Raw", 0, 3, [0.0; 3]),
+            record("Deviation", 9, 0b11001, [-1.0, 0.5, 10.0]),
+        ]);
+        let resolve = |name: &str| ExtraBytes::named(name).resolve(&[&header]);
+
+        let amplitude = resolve("Amplitude").unwrap();
+        let deviation = resolve("Deviation").unwrap();
+
+        let bytes = |deviation: f32| {
+            [&700u16.to_le_bytes()[..], &[0; 3], &deviation.to_le_bytes()].concat()
+        };
+
+        assert_eq!(amplitude.value(&bytes(4.0)), 700.0);
+        assert_eq!(deviation.value(&bytes(4.0)), 12.0);
+        assert!(deviation.value(&bytes(-1.0)).is_nan());
+        // Short extra bytes, and an unresolved dimension.
+        assert!(deviation.value(&[0; 4]).is_nan());
+        assert!(ExtraBytes::named("Deviation").value(&bytes(4.0)).is_nan());
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let header_a = header(&[record("Amplitude", 3, 0, [0.0; 3])]);
+        let header_b = header(&[record("Amplitude", 4, 0, [0.0; 3])]);
+        let header_c = header(&[record("Amplitude", 13, 0, [0.0; 3])]);
+
+        assert!(matches!(
+            ExtraBytes::named("Range").resolve(&[&header_a]),
+            Err(Error::ExtraBytes(e)) if e == "no extra-bytes dimension 'Range'"
+        ));
+        assert!(matches!(
+            ExtraBytes::named("Amplitude").resolve(&[&header_a, &header_b]),
+            Err(Error::ExtraBytes(e)) if e == "'Amplitude' is stored differently between the inputs"
+        ));
+        assert!(matches!(
+            ExtraBytes::named("Amplitude").resolve(&[&header_c]),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(ExtraBytes::named("Amplitude")
+            .resolve(&[&header_a, &header_a])
+            .is_ok());
+    }
+}
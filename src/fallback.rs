@@ -0,0 +1,197 @@
+use tracing::info;
+
+use crate::idw::Buckets;
+use crate::output::Band;
+use crate::util::GridDefinition;
+
+/// A method filling the pixels the rasterization left without data, from the pixels with data
+/// around them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fallback {
+    /// Inverse distance weighted (power 2) mean of the pixels within the radius.
+    Idw(f64),
+    /// The nearest pixel within the radius.
+    Nearest(f64),
+}
+
+pub fn fallback_parser(s: &str) -> core::result::Result<Fallback, String> {
+    let (method, radius) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected <method>:<radius>, e.g. 'idw:10', got '{s}'"))?;
+
+    let radius = radius.trim().parse::<f64>().map_err(|e| e.to_string())?;
+    if radius <= 0.0 || !radius.is_finite() {
+        return Err(format!("the radius has to be positive, got {radius}"));
+    }
+
+    match method.trim() {
+        "idw" => Ok(Fallback::Idw(radius)),
+        "nearest" => Ok(Fallback::Nearest(radius)),
+        other => Err(format!(
+            "unknown fallback '{other}', expected idw or nearest"
+        )),
+    }
+}
+
+impl Fallback {
+    fn radius(&self) -> f64 {
+        match *self {
+            Fallback::Idw(radius) | Fallback::Nearest(radius) => radius,
+        }
+    }
+
+    /// Fills the `nodata` pixels of `data` within the radius of pixels with data, from those
+    /// pixels. Returns the indices of the filled pixels.
+    fn fill(&self, data: &mut [f64], grid: &GridDefinition, nodata: f64) -> Vec<usize> {
+        let radius = self.radius();
+        let mut buckets = Buckets::new(&grid.bounds, radius);
+
+        for (i, value) in data.iter().enumerate() {
+            if *value != nodata {
+                let (x, y) = grid.center(i);
+                buckets.add(x, y, *value);
+            }
+        }
+
+        // Filled from the pixels with data beforehand only, not from each other.
+        let fills: Vec<(usize, f64)> = data
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| **value == nodata)
+            .filter_map(|(i, _)| {
+                let (x, y) = grid.center(i);
+                let near = buckets
+                    .around(x, y)
+                    .map(|(px, py, value)| ((px - x).hypot(py - y), *value))
+                    .filter(|(distance, _)| *distance <= radius);
+
+                let value = match self {
+                    Fallback::Idw(_) => {
+                        let (sum, weights) = near.fold((0.0, 0.0), |(sum, weights), (d, v)| {
+                            let weight = d.powi(-2);
+                            (sum + weight * v, weights + weight)
+                        });
+
+                        (weights > 0.0).then_some(sum / weights)?
+                    }
+                    Fallback::Nearest(_) => near.min_by(|a, b| a.0.total_cmp(&b.0))?.1,
+                };
+
+                Some((i, value))
+            })
+            .collect();
+
+        for (i, value) in fills.iter() {
+            data[*i] = *value;
+        }
+
+        fills.into_iter().map(|(i, _)| i).collect()
+    }
+}
+
+/// Fills the NODATA pixels of `bands` with each of `fallbacks` in turn, each filling what the
+/// previous ones left. Returns the fallback that filled each pixel of the first band, None for
+/// the rasterization's pixels and those left without data.
+pub fn fill_nodata(
+    bands: &mut [Band],
+    grid: &GridDefinition,
+    fallbacks: &[Fallback],
+    nodata: f64,
+) -> Vec<Option<Fallback>> {
+    let mut fills = vec![None; grid.len()];
+
+    for fallback in fallbacks {
+        info!("Filling NODATA with {fallback:?}...");
+
+        for (i, band) in bands.iter_mut().enumerate() {
+            let filled = fallback.fill(&mut band.data, grid, nodata);
+
+            if i == 0 {
+                info!("Filled {} pixels", filled.len());
+                for j in filled {
+                    fills[j] = Some(*fallback);
+                }
+            }
+        }
+    }
+
+    fills
+}
+
+#[cfg(test)]
+mod tests {
+    use las::{Bounds, Vector};
+
+    use super::*;
+
+    const N: f64 = -9999.0;
+
+    /// A row of 5 pixels of 1 m.
+    fn row() -> GridDefinition {
+        let bounds = Bounds {
+            min: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: 5.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        };
+
+        GridDefinition::new(bounds, 1.0)
+    }
+
+    #[test]
+    fn fallbacks() {
+        assert_eq!(fallback_parser("idw:10"), Ok(Fallback::Idw(10.0)));
+        assert_eq!(
+            fallback_parser(" nearest : 2.5"),
+            Ok(Fallback::Nearest(2.5))
+        );
+        assert!(fallback_parser("idw").is_err());
+        assert!(fallback_parser("idw:0").is_err());
+        assert!(fallback_parser("spline:5").is_err());
+    }
+
+    #[test]
+    fn fills_within_the_radius() {
+        let grid = row();
+
+        let mut data = vec![1.0, N, N, N, 5.0];
+        Fallback::Nearest(1.5).fill(&mut data, &grid, N);
+        assert_eq!(data, [1.0, 1.0, N, 5.0, 5.0]);
+
+        // Only from the pixels with data beforehand.
+        let mut data = vec![1.0, N, N, N, 5.0];
+        Fallback::Idw(2.0).fill(&mut data, &grid, N);
+        assert_eq!(data, [1.0, 1.0, 3.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn chains() {
+        let grid = row();
+        let mut bands = vec![Band::new("z".to_string(), vec![1.0, N, N, N, 5.0])];
+
+        let fills = fill_nodata(
+            &mut bands,
+            &grid,
+            &[Fallback::Nearest(1.5), Fallback::Idw(2.0)],
+            N,
+        );
+
+        assert_eq!(bands[0].data, [1.0, 1.0, 3.0, 5.0, 5.0]);
+        assert_eq!(
+            fills,
+            [
+                None,
+                Some(Fallback::Nearest(1.5)),
+                Some(Fallback::Idw(2.0)),
+                Some(Fallback::Nearest(1.5)),
+                None
+            ]
+        );
+    }
+}
@@ -0,0 +1,535 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use las::Point;
+
+use crate::expr::{expr_parser, Expr};
+use crate::vector::Polygons;
+
+/// A set of LAS classification codes, or other byte values such as user data, e.g. 2,6,9-11.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassSet {
+    /// Sorted and without duplicates.
+    codes: Vec<u8>,
+}
+
+impl ClassSet {
+    pub fn new(mut codes: Vec<u8>) -> Self {
+        codes.sort();
+        codes.dedup();
+
+        Self { codes }
+    }
+
+    pub fn contains(&self, class: u8) -> bool {
+        self.codes.binary_search(&class).is_ok()
+    }
+}
+
+/// The codes as a comma list, with consecutive codes as ranges.
+impl fmt::Display for ClassSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_ranges(
+            f,
+            &self
+                .codes
+                .iter()
+                .map(|c| u16::from(*c))
+                .collect::<Vec<u16>>(),
+        )
+    }
+}
+
+/// Parses a comma list of classification codes and ranges of them, e.g. 2,6,9-11.
+pub fn class_set_parser(s: &str) -> core::result::Result<ClassSet, String> {
+    let codes = parse_ranges(s, "classification code", u8::MAX.into())?;
+
+    Ok(ClassSet {
+        codes: codes.into_iter().map(|c| c as u8).collect(),
+    })
+}
+
+/// Parses a comma list of user data values and ranges of them, e.g. 5 or 1-3.
+pub fn user_data_parser(s: &str) -> core::result::Result<ClassSet, String> {
+    let values = parse_ranges(s, "user data value", u8::MAX.into())?;
+
+    Ok(ClassSet {
+        codes: values.into_iter().map(|v| v as u8).collect(),
+    })
+}
+
+/// A set of point source IDs (flightlines), e.g. 103,104 or 100-110.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSet {
+    /// Sorted and without duplicates.
+    ids: Vec<u16>,
+}
+
+impl SourceSet {
+    pub fn contains(&self, id: u16) -> bool {
+        self.ids.binary_search(&id).is_ok()
+    }
+}
+
+/// The IDs as a comma list, with consecutive IDs as ranges.
+impl fmt::Display for SourceSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_ranges(f, &self.ids)
+    }
+}
+
+/// Parses a comma list of point source IDs and ranges of them, e.g. 103,104 or 100-110.
+pub fn source_set_parser(s: &str) -> core::result::Result<SourceSet, String> {
+    Ok(SourceSet {
+        ids: parse_ranges(s, "point source ID", u16::MAX)?,
+    })
+}
+
+/// Writes sorted `values` as a comma list, with consecutive values as ranges.
+fn write_ranges(f: &mut fmt::Formatter<'_>, values: &[u16]) -> fmt::Result {
+    let mut i = 0;
+
+    while i < values.len() {
+        let start = values[i];
+        while i + 1 < values.len() && values[i + 1] == values[i] + 1 {
+            i += 1;
+        }
+
+        if start != values[0] {
+            write!(f, ",")?;
+        }
+        match start == values[i] {
+            true => write!(f, "{start}")?,
+            false => write!(f, "{start}-{}", values[i])?,
+        }
+
+        i += 1;
+    }
+
+    Ok(())
+}
+
+/// Parses a comma list of values up to `max` and ranges of them, e.g. 2,6,9-11, into sorted
+/// values without duplicates. `what` names a value in errors.
+fn parse_ranges(s: &str, what: &str, max: u16) -> core::result::Result<Vec<u16>, String> {
+    let parse = |value: &str| {
+        value
+            .trim()
+            .parse::<u16>()
+            .ok()
+            .filter(|v| *v <= max)
+            .ok_or(format!("'{value}' is not a {what}"))
+    };
+
+    let mut values = Vec::new();
+
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    return Err(format!("'{part}' is an empty range"));
+                }
+
+                values.extend(start..=end);
+            }
+            None => values.push(parse(part)?),
+        }
+    }
+
+    values.sort();
+    values.dedup();
+
+    Ok(values)
+}
+
+/// Returns of a pulse, by return number and number of returns.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Returns {
+    First,
+    Last,
+    /// Neither the first nor the last of several returns.
+    Intermediate,
+    /// The only return of its pulse.
+    Single,
+    All,
+}
+
+impl Returns {
+    fn matches(&self, point: &Point) -> bool {
+        let (number, of) = (point.return_number, point.number_of_returns);
+
+        match self {
+            Returns::First => number <= 1,
+            Returns::Last => number >= of,
+            Returns::Intermediate => number > 1 && number < of,
+            Returns::Single => of <= 1,
+            Returns::All => true,
+        }
+    }
+}
+
+/// LAS point flags, from the classification bits of point formats 0-5 or the flag bits of
+/// formats 6-10 alike.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    /// Withheld, i.e. deleted, points.
+    Withheld,
+    /// Points created by other means than the scanner, e.g. digitized.
+    Synthetic,
+    /// Points in the overlap of flightlines, including those classified as overlap (12) in
+    /// point formats 0-5.
+    Overlap,
+    /// Model key points, as kept when thinning for a TIN.
+    Keypoint,
+}
+
+impl Flag {
+    fn is_set(&self, point: &Point) -> bool {
+        match self {
+            Flag::Withheld => point.is_withheld,
+            Flag::Synthetic => point.is_synthetic,
+            Flag::Overlap => point.is_overlap || u8::from(point.classification) == 12,
+            Flag::Keypoint => point.is_key_point,
+        }
+    }
+}
+
+/// Normalized difference vegetation index, (NIR - red) / (NIR + red), of points with both.
+fn ndvi(point: &Point) -> Option<f64> {
+    let (red, nir) = (f64::from(point.color?.red), f64::from(point.nir?));
+
+    (nir + red > 0.0).then_some((nir - red) / (nir + red))
+}
+
+/// A step of the filter chain, keeping the points it matches.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// LAS classification codes.
+    Class(ClassSet),
+    /// Z within a range, inclusive.
+    ZRange(f64, f64),
+    /// Intensity within a range, inclusive.
+    Intensity(u16, u16),
+    Returns(Returns),
+    /// Absolute scan angle in degrees at most this, whatever the point format stores it as.
+    ScanAngle(f64),
+    /// GPS time within a range, inclusive. Points without a GPS time don't match.
+    GpsTime(f64, f64),
+    /// Point source IDs, i.e. flightlines.
+    PointSource(SourceSet),
+    /// User data values.
+    UserData(ClassSet),
+    /// NDVI from the red and NIR channels within a range, inclusive. Points without both don't
+    /// match.
+    Ndvi(f64, f64),
+    /// Points of other classes than these.
+    DropClass(ClassSet),
+    /// Points without the flag.
+    Drop(Flag),
+    /// Points the expression holds for.
+    Where(Expr),
+}
+
+impl Filter {
+    pub fn matches(&self, point: &Point) -> bool {
+        match self {
+            Filter::Class(classes) => classes.contains(u8::from(point.classification)),
+            Filter::ZRange(min, max) => point.z >= *min && point.z <= *max,
+            Filter::Intensity(min, max) => point.intensity >= *min && point.intensity <= *max,
+            Filter::Returns(returns) => returns.matches(point),
+            Filter::ScanAngle(max) => f64::from(point.scan_angle.abs()) <= *max,
+            Filter::GpsTime(min, max) => point.gps_time.is_some_and(|t| t >= *min && t <= *max),
+            Filter::PointSource(ids) => ids.contains(point.point_source_id),
+            Filter::UserData(values) => values.contains(point.user_data),
+            Filter::Ndvi(min, max) => ndvi(point).is_some_and(|ndvi| ndvi >= *min && ndvi <= *max),
+            Filter::DropClass(classes) => !classes.contains(u8::from(point.classification)),
+            Filter::Drop(flag) => !flag.is_set(point),
+            Filter::Where(expr) => expr.matches(point),
+        }
+    }
+}
+
+/// The step as given to `--filter`.
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Class(c) => write!(f, "class={c}"),
+            Filter::ZRange(min, max) => write!(f, "zrange={min}:{max}"),
+            Filter::Intensity(min, max) => write!(f, "intensity={min}:{max}"),
+            Filter::Returns(returns) => write!(
+                f,
+                "returns={}",
+                returns.to_possible_value().unwrap_or_default().get_name()
+            ),
+            Filter::ScanAngle(max) => write!(f, "scan-angle={max}"),
+            Filter::GpsTime(min, max) => write!(f, "gps-time={min}:{max}"),
+            Filter::PointSource(ids) => write!(f, "point-source={ids}"),
+            Filter::UserData(values) => write!(f, "user-data={values}"),
+            Filter::Ndvi(min, max) => write!(f, "ndvi={min}:{max}"),
+            Filter::DropClass(c) => write!(f, "drop-class={c}"),
+            Filter::Drop(flag) => write!(
+                f,
+                "drop={}",
+                flag.to_possible_value().unwrap_or_default().get_name()
+            ),
+            Filter::Where(expr) => write!(f, "where={expr}"),
+        }
+    }
+}
+
+/// Parses a filter step [class=<codes>, zrange=<min>:<max>, intensity=<min>:<max>,
+/// returns=<returns>, scan-angle=<max>, gps-time=<min>:<max>, point-source=<ids>,
+/// user-data=<values>, ndvi=<min>:<max>, drop-class=<codes>, drop=<flag>, where=<expression>].
+pub fn filter_parser(s: &str) -> core::result::Result<Filter, String> {
+    let parse = |num: &str| num.trim().parse::<f64>().map_err(|e| e.to_string());
+
+    let Some((name, args)) = s.split_once('=') else {
+        return Err(format!("'{s}' is not <filter>=<parameters>"));
+    };
+
+    match name.trim() {
+        "class" => Ok(Filter::Class(class_set_parser(args)?)),
+        "zrange" => {
+            let (min, max) = args
+                .split_once(':')
+                .ok_or(format!("'{args}' is not <min>:<max>"))?;
+
+            Ok(Filter::ZRange(parse(min)?, parse(max)?))
+        }
+        "intensity" => {
+            let parse_u16 = |num: &str| num.trim().parse::<u16>().map_err(|e| e.to_string());
+            let (min, max) = args
+                .split_once(':')
+                .ok_or(format!("'{args}' is not <min>:<max>"))?;
+
+            Ok(Filter::Intensity(parse_u16(min)?, parse_u16(max)?))
+        }
+        "returns" => Ok(Filter::Returns(Returns::from_str(args.trim(), true)?)),
+        "scan-angle" => Ok(Filter::ScanAngle(parse(args)?)),
+        "gps-time" => {
+            let (min, max) = args
+                .split_once(':')
+                .ok_or(format!("'{args}' is not <min>:<max>"))?;
+
+            Ok(Filter::GpsTime(parse(min)?, parse(max)?))
+        }
+        "point-source" => Ok(Filter::PointSource(source_set_parser(args)?)),
+        "user-data" => Ok(Filter::UserData(user_data_parser(args)?)),
+        "ndvi" => {
+            let (min, max) = args
+                .split_once(':')
+                .ok_or(format!("'{args}' is not <min>:<max>"))?;
+
+            Ok(Filter::Ndvi(parse(min)?, parse(max)?))
+        }
+        "drop-class" => Ok(Filter::DropClass(class_set_parser(args)?)),
+        "drop" => Ok(Filter::Drop(Flag::from_str(args.trim(), true)?)),
+        "where" => Ok(Filter::Where(expr_parser(args)?)),
+        _ => Err(format!(
+            "Unknown filter '{name}' [class, zrange, intensity, returns, scan-angle, gps-time, \
+            point-source, user-data, ndvi, drop-class, drop, where]"
+        )),
+    }
+}
+
+/// Criterion of points dropped by `--exclude-polygons`.
+pub const EXCLUDE_POLYGONS: &str = "exclude-polygons";
+
+/// Criterion of points dropped by `--clip`.
+pub const CLIP: &str = "clip";
+
+/// Point filters shared by all rasterization methods.
+#[derive(Default)]
+pub struct PointFilter {
+    /// Filters applied in order, a point has to pass every one.
+    chain: Vec<Filter>,
+    /// Each filter as given, to account for the points it rejects.
+    labels: Vec<String>,
+    /// Areas whose points are dropped entirely.
+    pub exclude: Option<Polygons>,
+    /// Areas outside of which points are dropped.
+    pub clip: Option<Polygons>,
+}
+
+impl PointFilter {
+    pub fn new(chain: Vec<Filter>, exclude: Option<Polygons>, clip: Option<Polygons>) -> Self {
+        Self {
+            labels: chain.iter().map(|f| f.to_string()).collect(),
+            chain,
+            exclude,
+            clip,
+        }
+    }
+
+    /// Criteria of the active filters, as reported for the points they reject.
+    pub fn criteria(&self) -> impl Iterator<Item = &str> {
+        self.labels
+            .iter()
+            .map(String::as_str)
+            .chain(self.exclude.as_ref().map(|_| EXCLUDE_POLYGONS))
+            .chain(self.clip.as_ref().map(|_| CLIP))
+    }
+
+    /// Whether a point passes every active filter.
+    pub fn keep(&self, point: &Point) -> bool {
+        self.rejects(point).is_none()
+    }
+
+    /// The first filter `point` fails, as given, None if it passes every one.
+    pub fn rejects(&self, point: &Point) -> Option<&str> {
+        if let Some(i) = self.chain.iter().position(|f| !f.matches(point)) {
+            return Some(&self.labels[i]);
+        }
+
+        if let Some(exclude) = &self.exclude {
+            if exclude.contains(point.x, point.y) {
+                return Some(EXCLUDE_POLYGONS);
+            }
+        }
+
+        if let Some(clip) = &self.clip {
+            if !clip.contains(point.x, point.y) {
+                return Some(CLIP);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use las::point::Classification;
+
+    use super::*;
+
+    #[test]
+    fn class_sets() {
+        let classes = class_set_parser("9-11, 2,6,2").unwrap();
+
+        assert_eq!(classes.to_string(), "2,6,9-11");
+        assert!(classes.contains(10));
+        assert!(!classes.contains(7));
+
+        assert_eq!(
+            class_set_parser("2,256").unwrap_err(),
+            "'256' is not a classification code"
+        );
+        assert_eq!(
+            class_set_parser("5-3").unwrap_err(),
+            "'5-3' is an empty range"
+        );
+        assert_eq!(
+            class_set_parser("").unwrap_err(),
+            "'' is not a classification code"
+        );
+        assert_eq!(
+            user_data_parser("x").unwrap_err(),
+            "'x' is not a user data value"
+        );
+        assert_eq!(ClassSet::new(vec![7, 18, 7]).to_string(), "7,18");
+    }
+
+    #[test]
+    fn source_sets() {
+        let ids = source_set_parser("100-102,104,65535").unwrap();
+
+        assert_eq!(ids.to_string(), "100-102,104,65535");
+        assert!(ids.contains(101) && ids.contains(65535));
+        assert!(!ids.contains(103));
+        assert_eq!(
+            source_set_parser("65536").unwrap_err(),
+            "'65536' is not a point source ID"
+        );
+    }
+
+    #[test]
+    fn filters_round_trip() {
+        for s in [
+            "class=2,6,9-11",
+            "zrange=-1.5:300",
+            "intensity=10:200",
+            "returns=last",
+            "scan-angle=15",
+            "gps-time=0:1000.5",
+            "point-source=3-5",
+            "user-data=1",
+            "ndvi=0.2:1",
+            "drop-class=7,18",
+            "drop=overlap",
+            "where=Z > 1",
+        ] {
+            assert_eq!(filter_parser(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn filter_errors() {
+        assert_eq!(
+            filter_parser("class").unwrap_err(),
+            "'class' is not <filter>=<parameters>"
+        );
+        assert_eq!(
+            filter_parser("zrange=5").unwrap_err(),
+            "'5' is not <min>:<max>"
+        );
+        assert!(filter_parser("height=5")
+            .unwrap_err()
+            .starts_with("Unknown filter 'height' [class, zrange,"));
+        assert!(filter_parser("returns=second").is_err());
+        assert!(filter_parser("intensity=-1:5").is_err());
+    }
+
+    #[test]
+    fn filters_match() {
+        let point = Point {
+            z: 10.0,
+            return_number: 2,
+            number_of_returns: 3,
+            classification: Classification::Ground,
+            is_overlap: true,
+            ..Default::default()
+        };
+        let matches = |s: &str| filter_parser(s).unwrap().matches(&point);
+
+        assert!(matches("class=2"));
+        assert!(!matches("drop-class=2"));
+        assert!(matches("zrange=10:20"));
+        assert!(!matches("zrange=10.5:20"));
+        assert!(matches("returns=intermediate"));
+        assert!(!matches("returns=first") && !matches("returns=last"));
+        assert!(!matches("drop=overlap"));
+        assert!(matches("drop=withheld"));
+        // Missing dimensions don't match.
+        assert!(!matches("gps-time=0:1e9"));
+        assert!(!matches("ndvi=-1:1"));
+    }
+
+    #[test]
+    fn point_filter_rejects_with_the_first_failing_step() {
+        let filter = PointFilter::new(
+            vec![
+                filter_parser("zrange=0:100").unwrap(),
+                filter_parser("class=2").unwrap(),
+                filter_parser("drop=withheld").unwrap(),
+            ],
+            None,
+            None,
+        );
+        let point = |z: f64, is_withheld: bool| Point {
+            z,
+            classification: Classification::Ground,
+            is_withheld,
+            ..Default::default()
+        };
+
+        assert_eq!(filter.rejects(&point(200.0, true)), Some("zrange=0:100"));
+        assert_eq!(filter.rejects(&point(50.0, true)), Some("drop=withheld"));
+        assert_eq!(filter.rejects(&point(50.0, false)), None);
+        assert_eq!(
+            filter.criteria().collect::<Vec<_>>(),
+            ["zrange=0:100", "class=2", "drop=withheld"]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use las::Bounds;
+use tracing::{info, instrument};
+
+use crate::error::Result;
+use crate::filter::PointFilter;
+use crate::input::Input;
+use crate::report::{Report, MISSING_VALUE};
+use crate::util::GridDefinition;
+use crate::{get_var, Variable, NODATA};
+
+pub struct IdwParams {
+    pub power: f64,
+    /// Search radius along the major axis.
+    pub radius: f64,
+    /// Direction of the major axis, in degrees clockwise from north.
+    pub azimuth: f64,
+    /// Major / minor axis length, at least 1. Distances across the major axis count this many
+    /// times more than along it.
+    pub ratio: f64,
+    /// Error on points beyond the search radius of the extent rather than skipping them.
+    pub strict: bool,
+}
+
+/// Points bucketed on a grid of `radius` sized cells, so a search only visits the buckets
+/// around the query.
+pub struct Buckets {
+    min_x: f64,
+    min_y: f64,
+    size: f64,
+    width: usize,
+    height: usize,
+    /// (x, y, value) per bucket.
+    points: Vec<Vec<(f64, f64, f64)>>,
+}
+
+impl Buckets {
+    pub fn new(bounds: &Bounds, size: f64) -> Self {
+        let width = ((bounds.max.x - bounds.min.x) / size).ceil().max(1.0) as usize;
+        let height = ((bounds.max.y - bounds.min.y) / size).ceil().max(1.0) as usize;
+
+        Self {
+            min_x: bounds.min.x,
+            min_y: bounds.min.y,
+            size,
+            width,
+            height,
+            points: vec![Vec::new(); width * height],
+        }
+    }
+
+    fn bucket(&self, x: f64, y: f64) -> (isize, isize) {
+        (
+            ((x - self.min_x) / self.size).floor() as isize,
+            ((y - self.min_y) / self.size).floor() as isize,
+        )
+    }
+
+    /// False if the point is outside of the bucketed area.
+    pub fn add(&mut self, x: f64, y: f64, value: f64) -> bool {
+        let (bx, by) = self.bucket(x, y);
+
+        if bx < 0 || by < 0 || bx as usize >= self.width || by as usize >= self.height {
+            return false;
+        }
+
+        self.points[by as usize * self.width + bx as usize].push((x, y, value));
+        true
+    }
+
+    /// Points of the buckets around (x, y), a superset of those within `size` of it.
+    pub fn around(&self, x: f64, y: f64) -> impl Iterator<Item = &(f64, f64, f64)> {
+        let (bx, by) = self.bucket(x, y);
+
+        (by - 1..=by + 1)
+            .flat_map(move |y| (bx - 1..=bx + 1).map(move |x| (x, y)))
+            .filter(|(x, y)| {
+                *x >= 0 && *y >= 0 && (*x as usize) < self.width && (*y as usize) < self.height
+            })
+            .flat_map(|(x, y)| self.points[y as usize * self.width + x as usize].iter())
+    }
+}
+
+/// Inverse distance weighted interpolation of cell centers from the points within the search
+/// radius. With a `ratio` above 1 the search area is an ellipse along `azimuth` and distances
+/// across it are stretched, so linear features (e.g. beach profiles or channels) interpolate
+/// along their orientation rather than across it.
+#[instrument(skip_all, fields(res))]
+pub fn idw(
+    mut input: Input,
+    bounds: Bounds,
+    res: f64,
+    filter: &PointFilter,
+    var: Variable,
+    params: &IdwParams,
+    report: &mut Report,
+) -> Result<Vec<f64>> {
+    let grid = GridDefinition::new(bounds, res);
+
+    // Points up to a radius outside of the extent still contribute to the edge cells.
+    let mut search_bounds = bounds;
+    search_bounds.min.x -= params.radius;
+    search_bounds.min.y -= params.radius;
+    search_bounds.max.x += params.radius;
+    search_bounds.max.y += params.radius;
+
+    let mut buckets = Buckets::new(&search_bounds, params.radius);
+
+    while let Some(point) = input.next_point(report)? {
+        if let Some(criterion) = filter.rejects(&point) {
+            report.filtered(criterion);
+            continue;
+        }
+
+        let value = get_var(&var, &point);
+        if value.is_nan() {
+            report.filtered(MISSING_VALUE);
+            continue;
+        }
+
+        match buckets.add(point.x, point.y, value) {
+            true => report.points_used += 1,
+            false => report.out_of_extent(point.x, point.y, params.strict)?,
+        }
+    }
+
+    info!("Interpolating...");
+
+    let azimuth = params.azimuth.to_radians();
+    // Unit vectors along and across the major axis.
+    let (major_x, major_y) = (azimuth.sin(), azimuth.cos());
+    let ratio = params.ratio.max(1.0);
+
+    let mut data = vec![NODATA; grid.len()];
+
+    for (i, cell) in data.iter_mut().enumerate() {
+        let (x, y) = grid.center(i);
+
+        let mut sum = 0.0;
+        let mut weights = 0.0;
+
+        for (px, py, value) in buckets.around(x, y) {
+            let (dx, dy) = (px - x, py - y);
+            let along = dx * major_x + dy * major_y;
+            let across = (dx * major_y - dy * major_x) * ratio;
+            let distance = along.hypot(across);
+
+            if distance > params.radius {
+                continue;
+            }
+
+            if distance == 0.0 {
+                sum = *value;
+                weights = 1.0;
+                break;
+            }
+
+            let weight = distance.powf(-params.power);
+            sum += weight * value;
+            weights += weight;
+        }
+
+        if weights > 0.0 {
+            *cell = sum / weights;
+        }
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use las::{Point, Vector};
+
+    use super::*;
+    use crate::input::buffer_points;
+
+    /// IDW of 3 x 3 cells of 1 m from points with a Z 2 m north and east of the center cell.
+    fn center(azimuth: f64, ratio: f64) -> f64 {
+        let at = |x: f64, y: f64, z: f64| Point {
+            x,
+            y,
+            z,
+            ..Default::default()
+        };
+        let points = vec![at(1.5, 3.5, 10.0), at(3.5, 1.5, 0.0)];
+        let input = Input::new(
+            vec![("test".to_string(), buffer_points("test", points).unwrap())],
+            0,
+        );
+
+        let bounds = Bounds {
+            min: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: 3.0,
+                y: 3.0,
+                z: 0.0,
+            },
+        };
+        let params = IdwParams {
+            power: 2.0,
+            radius: 2.5,
+            azimuth,
+            ratio,
+            strict: false,
+        };
+
+        let data = idw(
+            input,
+            bounds,
+            1.0,
+            &PointFilter::new(Vec::new(), None, None),
+            Variable::Z,
+            &params,
+            &mut Report::default(),
+        )
+        .unwrap();
+
+        data[4]
+    }
+
+    #[test]
+    fn anisotropy() {
+        assert!((center(0.0, 1.0) - 5.0).abs() < 1e-9);
+        // The east point is 4 m away across a north-south major axis, beyond the radius.
+        assert!((center(0.0, 2.0) - 10.0).abs() < 1e-9);
+        assert!((center(90.0, 2.0) - 0.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn buckets() {
+        let bounds = Bounds {
+            min: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: 10.0,
+                y: 10.0,
+                z: 0.0,
+            },
+        };
+        let mut buckets = Buckets::new(&bounds, 2.0);
+
+        assert!(buckets.add(1.0, 1.0, 1.0));
+        assert!(buckets.add(9.0, 9.0, 2.0));
+        assert!(!buckets.add(-1.0, 1.0, 3.0));
+        assert!(!buckets.add(1.0, 10.5, 3.0));
+
+        let near: Vec<f64> = buckets.around(2.5, 2.5).map(|(_, _, v)| *v).collect();
+        assert_eq!(near, [1.0]);
+        assert_eq!(buckets.around(5.0, 5.0).count(), 0);
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use las::{Bounds, Point};
+
+use crate::error::Result;
+use crate::util::read_u64;
+
+/// Leading bytes of a chunk index, with a format version.
+const INDEX_MAGIC: &[u8; 8] = b"LRIDX\0\0\x01";
+
+/// Points per indexed chunk, LAZ files use their own chunk size.
+pub const INDEX_CHUNK_SIZE: u64 = 50_000;
+
+/// XY bounding boxes of consecutive chunks of a point cloud file, cached next to the file so
+/// later runs with an extent only read the chunks within it.
+pub struct ChunkIndex {
+    /// Size of the indexed file, to detect a changed file.
+    file_size: u64,
+    chunk_size: u64,
+    /// min x, min y, max x, max y per chunk. Chunks without points have an inverted box.
+    boxes: Vec<[f64; 4]>,
+}
+
+/// Path of the index of the file at `path`.
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lrx");
+
+    PathBuf::from(name)
+}
+
+impl ChunkIndex {
+    pub fn new(path: &Path, chunk_size: u64, points: u64) -> Result<Self> {
+        Ok(Self {
+            file_size: fs::metadata(path)?.len(),
+            chunk_size,
+            boxes: vec![
+                [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+                points.div_ceil(chunk_size) as usize
+            ],
+        })
+    }
+
+    /// The cached index of the file at `path`, None if there is none or the file has changed
+    /// since.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let Ok(file) = File::open(index_path(path)) else {
+            return Ok(None);
+        };
+        let mut r = BufReader::new(file);
+
+        let mut magic = [0u8; INDEX_MAGIC.len()];
+        r.read_exact(&mut magic)?;
+
+        let file_size = read_u64(&mut r)?;
+
+        if magic != *INDEX_MAGIC || file_size != fs::metadata(path)?.len() {
+            return Ok(None);
+        }
+
+        let chunk_size = read_u64(&mut r)?;
+        let mut boxes = vec![[0.0; 4]; read_u64(&mut r)? as usize];
+
+        for b in boxes.iter_mut() {
+            for value in b.iter_mut() {
+                *value = f64::from_bits(read_u64(&mut r)?);
+            }
+        }
+
+        Ok(Some(Self {
+            file_size,
+            chunk_size,
+            boxes,
+        }))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut w = BufWriter::new(File::create(index_path(path))?);
+
+        w.write_all(INDEX_MAGIC)?;
+
+        for value in [self.file_size, self.chunk_size, self.boxes.len() as u64] {
+            w.write_all(&value.to_le_bytes())?;
+        }
+
+        for value in self.boxes.iter().flatten() {
+            w.write_all(&value.to_le_bytes())?;
+        }
+
+        w.flush()?;
+
+        Ok(())
+    }
+
+    /// Grows the box of the chunk of the point at `index`.
+    pub fn add(&mut self, index: u64, point: &Point) {
+        if let Some(b) = self.boxes.get_mut((index / self.chunk_size) as usize) {
+            b[0] = b[0].min(point.x);
+            b[1] = b[1].min(point.y);
+            b[2] = b[2].max(point.x);
+            b[3] = b[3].max(point.y);
+        }
+    }
+
+    /// Point index ranges of the chunks intersecting `extent`, out of `total` points.
+    pub fn ranges(&self, extent: &Bounds, total: u64) -> Vec<Range<u64>> {
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+
+        for (i, b) in self.boxes.iter().enumerate() {
+            let intersects = b[0] <= extent.max.x
+                && b[2] >= extent.min.x
+                && b[1] <= extent.max.y
+                && b[3] >= extent.min.y;
+
+            if !intersects {
+                continue;
+            }
+
+            let start = i as u64 * self.chunk_size;
+            let end = (start + self.chunk_size).min(total);
+
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+
+        ranges
+    }
+}
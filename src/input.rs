@@ -0,0 +1,1401 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use gdal::spatial_ref::{CoordTransform, SpatialRef};
+use las::point::Format;
+use las::{Bounds, Builder, Header, Point, Reader, Transform, Vector, Writer};
+use tracing::{info, instrument, warn};
+
+use crate::copc::copc_ranges;
+use crate::crs::transform_bounds;
+use crate::ept::{ept_files, is_ept};
+use crate::error::{Error, Result};
+use crate::filter::PointFilter;
+use crate::index::{ChunkIndex, INDEX_CHUNK_SIZE};
+use crate::lax::Lax;
+use crate::outliers::OutlierRemoval;
+use crate::pcd::{is_pcd, read_pcd};
+use crate::ply::{is_ply, read_ply};
+use crate::remote::{is_url, RemoteReader};
+use crate::report::Report;
+use crate::service::{Observer, Stage};
+use crate::synthetic::Rng;
+use crate::text::{is_text, read_text, TextColumns};
+use crate::util::header_wkt;
+use crate::validation::Holdout;
+
+/// Default LAZ chunk size, used when the laszip VLR can't be read.
+const DEFAULT_CHUNK_SIZE: u64 = 50_000;
+
+/// A point cloud reader, with the point index ranges still to be read from it.
+struct Source {
+    /// Name (e.g. the file name) for error messages.
+    name: String,
+    reader: Reader,
+    ranges: VecDeque<Range<u64>>,
+    /// Chunk index being built while reading, saved next to the file at the given path.
+    index: Option<(PathBuf, ChunkIndex)>,
+}
+
+/// Point cloud readers, read one after the other as a single cloud, which tolerate a limited
+/// number of undecodable points.
+pub struct Input {
+    sources: Vec<Source>,
+    /// Index of the source being read.
+    current: usize,
+    /// Index of the next point to be read from the current source.
+    index: u64,
+    /// End of the range being read.
+    end: u64,
+    /// Points to skip past a decoding error, the LAZ chunk size for compressed data.
+    skip: Option<u64>,
+    max_corrupt: u64,
+    corrupt: u64,
+    /// Points withheld for cross-validation.
+    holdout: Option<Holdout>,
+    thinning: Option<Thinning>,
+    /// Transform of the points' XY into another CRS, with the transformed bounds.
+    reprojection: Option<(CoordTransform, Bounds)>,
+    /// Points read ahead to be reprojected together, returned before reading any more.
+    pending: VecDeque<Point>,
+    progress: Progress,
+    /// Factor scaling each source's intensities to 16 bits, empty to leave them be.
+    intensity_scale: Vec<f64>,
+    /// Each source's intensity quantiles matched to those of the reference, empty to leave
+    /// them be.
+    intensity_match: Vec<QuantileMap>,
+    /// XY outside of which points are skipped.
+    clip: Option<Bounds>,
+    /// Source and point indices of the outliers, skipped, with their criterion.
+    outliers: HashMap<(usize, u64), &'static str>,
+    /// Told of the progress, and may cancel reading.
+    observer: Option<Observer>,
+}
+
+/// Points reprojected together by `Input::next_point`.
+const REPROJECT_CHUNK: usize = 4096;
+
+/// Points sampled to detect the bit depth of intensities.
+const INTENSITY_SAMPLES: u64 = 100_000;
+
+/// Quantiles of the intensity histograms matched between inputs.
+const HARMONIZE_QUANTILES: usize = 100;
+
+/// Intensities of the reference histogram for `--harmonize-intensity`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Harmonize {
+    /// Every input's points pooled together.
+    Ensemble,
+    /// The input with this path or file name.
+    Reference(String),
+}
+
+/// Parses `ensemble` or the path of a reference input.
+pub fn harmonize_parser(s: &str) -> core::result::Result<Harmonize, String> {
+    match s {
+        "" => Err("Expected 'ensemble' or the path of an input".to_string()),
+        "ensemble" => Ok(Harmonize::Ensemble),
+        _ => Ok(Harmonize::Reference(s.to_string())),
+    }
+}
+
+/// Piecewise linear mapping between the quantiles of two histograms.
+struct QuantileMap {
+    /// Quantiles of the source, ascending, with the matching quantiles of the reference.
+    pairs: Vec<(f64, f64)>,
+}
+
+impl QuantileMap {
+    fn apply(&self, value: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.pairs.first(), self.pairs.last()) else {
+            return value;
+        };
+
+        let i = self.pairs.partition_point(|(from, _)| *from < value);
+        if i == 0 {
+            return first.1;
+        }
+        if i == self.pairs.len() {
+            return last.1;
+        }
+
+        let ((x0, y0), (x1, y1)) = (self.pairs[i - 1], self.pairs[i]);
+        match x1 > x0 {
+            true => y0 + (value - x0) / (x1 - x0) * (y1 - y0),
+            false => y1,
+        }
+    }
+}
+
+/// `HARMONIZE_QUANTILES` + 1 evenly spaced quantiles of `values`, empty if there are none.
+fn quantiles(values: &mut [f64]) -> Vec<f64> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+
+    values.sort_by(f64::total_cmp);
+
+    (0..=HARMONIZE_QUANTILES)
+        .map(|q| values[q * (values.len() - 1) / HARMONIZE_QUANTILES])
+        .collect()
+}
+
+/// Points to read before progress is logged.
+const PROGRESS_MIN_POINTS: u64 = 1_000_000;
+
+/// Reading progress over every input, from their header point counts, for an ETA across files
+/// of very different sizes.
+struct Progress {
+    total: u64,
+    done: u64,
+    /// Points done at which to log next.
+    next_log: u64,
+    start: Instant,
+}
+
+impl Progress {
+    fn new(total: u64) -> Self {
+        Self {
+            total,
+            done: 0,
+            next_log: total / 20,
+            start: Instant::now(),
+        }
+    }
+
+    /// Counts `points` as done, logging every 5% of large inputs.
+    fn advance(&mut self, points: u64) {
+        self.done += points;
+
+        if self.total < PROGRESS_MIN_POINTS || self.done < self.next_log || self.done == 0 {
+            return;
+        }
+
+        self.next_log = self.done + self.total / 20;
+
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let remaining = elapsed * (self.total.saturating_sub(self.done)) as f64 / self.done as f64;
+
+        info!(
+            "Read {:.0}% of {} points, ETA {}",
+            100.0 * self.done as f64 / self.total as f64,
+            self.total,
+            format_duration(remaining)
+        );
+    }
+}
+
+/// e.g. 1h 02m 03s, 4m 05s or 6s.
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    match (h, m) {
+        (0, 0) => format!("{s}s"),
+        (0, _) => format!("{m}m {s:02}s"),
+        _ => format!("{h}h {m:02}m {s:02}s"),
+    }
+}
+
+/// Decimation of the input, e.g. for quick looks at a coarse resolution.
+pub enum Thinning {
+    /// Keeps every nth point, `seen` counting the points so far.
+    Every { n: u64, seen: u64 },
+    /// Keeps a random fraction of the points.
+    Fraction(f64, Rng),
+}
+
+impl Thinning {
+    /// Whether the next point is kept.
+    fn keep(&mut self) -> bool {
+        match self {
+            Thinning::Every { n, seen } => {
+                *seen += 1;
+                (*seen - 1) % *n == 0
+            }
+            Thinning::Fraction(fraction, rng) => rng.uniform() < *fraction,
+        }
+    }
+}
+
+impl Input {
+    pub fn new(readers: Vec<(String, Reader)>, max_corrupt: u64) -> Self {
+        Self::from_sources(
+            readers
+                .into_iter()
+                .map(|(name, reader)| {
+                    let total = reader.header().number_of_points();
+
+                    Source {
+                        name,
+                        reader,
+                        ranges: VecDeque::from([0..total]),
+                        index: None,
+                    }
+                })
+                .collect(),
+            max_corrupt,
+        )
+    }
+
+    fn from_sources(sources: Vec<Source>, max_corrupt: u64) -> Self {
+        let mut input = Self {
+            sources,
+            current: 0,
+            index: 0,
+            end: 0,
+            skip: None,
+            max_corrupt,
+            corrupt: 0,
+            holdout: None,
+            thinning: None,
+            reprojection: None,
+            pending: VecDeque::new(),
+            progress: Progress::new(0),
+            intensity_scale: Vec::new(),
+            intensity_match: Vec::new(),
+            clip: None,
+            outliers: HashMap::new(),
+            observer: None,
+        };
+
+        input.progress = Progress::new(input.number_of_points());
+
+        input.skip = input
+            .sources
+            .first()
+            .and_then(|s| skip_size(s.reader.header()));
+
+        input
+    }
+
+    /// Opens every file of `paths`, expanding directories and wildcards with `expand_paths`.
+    /// http(s):// and s3:// URLs are read with range requests. COPC files only have the octree nodes
+    /// intersecting `extent` read, if given, and EPT datasets (`ept.json`) the nodes
+    /// intersecting `extent` down to a spacing of `res`. Local files with a `.lax` sidecar only
+    /// have the cells of its quadtree within `extent` read. With `index_cache`, other local files
+    /// only have the chunks within `extent` read once they have a cached chunk index, which is
+    /// built on their first full read. Text point files are read with `text_columns`, PLY
+    /// files' vertices and PCD files' points as points.
+    #[instrument(name = "open", skip_all, fields(paths = paths.len()))]
+    pub fn from_paths(
+        paths: &[PathBuf],
+        max_corrupt: u64,
+        extent: Option<&Bounds>,
+        res: f64,
+        index_cache: bool,
+        text_columns: &TextColumns,
+    ) -> Result<Self> {
+        let mut files = Vec::new();
+        for path in expand_paths(paths)? {
+            match is_ept(&path) {
+                true => files.extend(ept_files(&path, extent, res)?),
+                false => files.push(path),
+            }
+        }
+
+        let sources = files
+            .into_iter()
+            .map(|path| {
+                if path == Path::new(STDIN) {
+                    let reader = read_stdin()?;
+                    let total = reader.header().number_of_points();
+
+                    return Ok(Source {
+                        name: "stdin".to_string(),
+                        reader,
+                        ranges: VecDeque::from([0..total]),
+                        index: None,
+                    });
+                }
+
+                let name = path.display().to_string();
+
+                let buffered = if is_text(&path) {
+                    Some(read_text(&path, text_columns)?)
+                } else if is_ply(&path) {
+                    Some(read_ply(&path)?)
+                } else if is_pcd(&path) {
+                    Some(read_pcd(&path)?)
+                } else {
+                    None
+                };
+
+                if let Some(reader) = buffered {
+                    let total = reader.header().number_of_points();
+
+                    return Ok(Source {
+                        name,
+                        reader,
+                        ranges: VecDeque::from([0..total]),
+                        index: None,
+                    });
+                }
+
+                let remote = is_url(&path);
+
+                let reader = match remote {
+                    true => Reader::new(RemoteReader::open(&name)?),
+                    false => Reader::from_path(&path),
+                };
+
+                let reader = match reader {
+                    Ok(reader) => reader,
+                    Err(err) => return Err(Error::InputFile(name, err)),
+                };
+
+                let total = reader.header().number_of_points();
+
+                // The hierarchy is read separately from the points, with its own reader.
+                let ranges = match (extent, remote) {
+                    (Some(extent), true) => {
+                        copc_ranges(&mut RemoteReader::open(&name)?, reader.header(), extent)?
+                    }
+                    (Some(extent), false) => copc_ranges(
+                        &mut BufReader::new(File::open(&path)?),
+                        reader.header(),
+                        extent,
+                    )?,
+                    (None, _) => None,
+                }
+                .map(|ranges| (ranges, "COPC nodes"));
+
+                // A LAStools spatial index sidecar, otherwise.
+                let ranges = match (ranges, extent, remote) {
+                    (None, Some(extent), false) => {
+                        Lax::load(&path)?.map(|lax| (lax.ranges(extent), "LAX index cells"))
+                    }
+                    (ranges, ..) => ranges,
+                };
+
+                let mut index = None;
+
+                let ranges = match ranges {
+                    Some((ranges, from)) => {
+                        info!(
+                            "{}: reading {} of {} points from {from} within the extent",
+                            name,
+                            ranges.iter().map(|r| r.end - r.start).sum::<u64>(),
+                            total
+                        );
+                        ranges
+                    }
+                    None if index_cache && !remote => match (ChunkIndex::load(&path)?, extent) {
+                        (Some(cached), Some(extent)) => {
+                            let ranges = cached.ranges(extent, total);
+                            info!(
+                                "{}: reading {} of {} points from indexed chunks within the \
+                                 extent",
+                                name,
+                                ranges.iter().map(|r| r.end - r.start).sum::<u64>(),
+                                total
+                            );
+                            ranges
+                        }
+                        (Some(_), None) => vec![0..total],
+                        (None, _) => {
+                            let chunk_size = match skip_size(reader.header()) {
+                                Some(size) if size > 1 => size,
+                                _ => INDEX_CHUNK_SIZE,
+                            };
+
+                            index =
+                                Some((path.clone(), ChunkIndex::new(&path, chunk_size, total)?));
+                            vec![0..total]
+                        }
+                    },
+                    None => vec![0..total],
+                };
+
+                Ok(Source {
+                    name,
+                    reader,
+                    ranges: ranges.into(),
+                    index,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if sources.len() > 1 {
+            info!("Reading {} input files", sources.len());
+        }
+
+        Ok(Self::from_sources(sources, max_corrupt))
+    }
+
+    /// Drops points as `thinning` decides, before they're withheld or returned.
+    pub fn thin(&mut self, thinning: Thinning) {
+        self.thinning = Some(thinning);
+    }
+
+    /// Reprojects the points' XY from the CRS `from` to `to`, before they're withheld or
+    /// returned.
+    pub fn reproject(&mut self, from: &SpatialRef, to: &SpatialRef) -> Result<()> {
+        let bounds = transform_bounds(&self.bounds(), from, to)?;
+        self.reprojection = Some((CoordTransform::new(from, to)?, bounds));
+
+        Ok(())
+    }
+
+    /// Detects whether each input's intensities use 8, 12 or 16 bits, from a sample of their
+    /// points, and scales them all to 16 bits, so inputs from different vendors are comparable.
+    pub fn normalize_intensity(&mut self) -> Result<()> {
+        let mut max = vec![0u16; self.sources.len()];
+        self.sample_points(INTENSITY_SAMPLES, |i, point| {
+            max[i] = max[i].max(point.intensity)
+        })?;
+
+        self.intensity_scale = max
+            .into_iter()
+            .zip(self.sources.iter())
+            .map(|(max, source)| {
+                let bits = match max {
+                    0..=255 => 8,
+                    256..=4095 => 12,
+                    _ => 16,
+                };
+                info!("{}: {bits} bit intensities", source.name);
+
+                u16::MAX as f64 / ((1u32 << bits) - 1) as f64
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Matches the intensity histogram of each input to that of `reference`, from a sample of
+    /// their points, removing seams between them in intensity mosaics. Applied after any
+    /// `normalize_intensity`.
+    pub fn harmonize_intensity(&mut self, reference: &Harmonize) -> Result<()> {
+        let mut samples = vec![Vec::new(); self.sources.len()];
+        self.sample_points(INTENSITY_SAMPLES, |i, point| {
+            samples[i].push(point.intensity as f64)
+        })?;
+
+        for (values, scale) in samples.iter_mut().zip(self.intensity_scale.iter()) {
+            values.iter_mut().for_each(|v| *v *= scale);
+        }
+
+        let target = match reference {
+            Harmonize::Ensemble => quantiles(&mut samples.concat()),
+            Harmonize::Reference(reference) => {
+                let i = self
+                    .sources
+                    .iter()
+                    .position(|s| {
+                        s.name == *reference
+                            || Path::new(&s.name).file_name() == Some(reference.as_ref())
+                    })
+                    .ok_or_else(|| {
+                        Error::Unsupported(format!(
+                            "--harmonize-intensity reference '{reference}', which isn't an input"
+                        ))
+                    })?;
+
+                quantiles(&mut samples[i].clone())
+            }
+        };
+
+        if target.is_empty() {
+            warn!("No intensities sampled for the reference, not harmonizing them");
+            return Ok(());
+        }
+
+        self.intensity_match = samples
+            .iter_mut()
+            .zip(self.sources.iter())
+            .map(|(values, source)| {
+                let from = quantiles(values);
+                if let (Some(median), Some(target)) = (
+                    from.get(HARMONIZE_QUANTILES / 2),
+                    target.get(HARMONIZE_QUANTILES / 2),
+                ) {
+                    info!("{}: median intensity {median} -> {target}", source.name);
+                }
+
+                QuantileMap {
+                    pairs: from.into_iter().zip(target.iter().copied()).collect(),
+                }
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Only reads points within the XY of `bounds`, skipping inputs whose headers are entirely
+    /// outside of it, unless the points are reprojected.
+    pub fn clip(&mut self, bounds: Bounds) {
+        if self.reprojection.is_none() {
+            for source in self.sources.iter_mut() {
+                let b = source.reader.header().bounds();
+
+                if b.min.x > bounds.max.x
+                    || b.max.x < bounds.min.x
+                    || b.min.y > bounds.max.y
+                    || b.max.y < bounds.min.y
+                {
+                    source.ranges.clear();
+                }
+            }
+
+            self.progress = Progress::new(self.number_of_points());
+        }
+
+        self.clip = Some(bounds);
+    }
+
+    /// Skips the outliers found by each of `removals` among the points within the clip extent
+    /// which pass `filter`, from a first read of every point. Only to be called before reading
+    /// any points, the inputs are rewound afterwards.
+    pub fn remove_outliers(
+        &mut self,
+        removals: &[OutlierRemoval],
+        filter: &PointFilter,
+    ) -> Result<()> {
+        let mut positions = Vec::with_capacity(self.number_of_points() as usize);
+        let mut ids = Vec::with_capacity(positions.capacity());
+
+        // Points read ahead, reprojected together before being filtered.
+        let mut points = Vec::with_capacity(REPROJECT_CHUNK);
+        let mut point_ids = Vec::with_capacity(REPROJECT_CHUNK);
+        let (reprojection, clip) = (&self.reprojection, &self.clip);
+        let mut flush = |points: &mut Vec<Point>, point_ids: &mut Vec<(usize, u64)>| {
+            if let Some((transform, _)) = reprojection {
+                reproject_points(transform, points)?;
+            }
+
+            for (point, id) in points.drain(..).zip(point_ids.drain(..)) {
+                let within = clip.as_ref().map_or(true, |clip| {
+                    point.x >= clip.min.x
+                        && point.x <= clip.max.x
+                        && point.y >= clip.min.y
+                        && point.y <= clip.max.y
+                });
+
+                if within && filter.rejects(&point).is_none() {
+                    positions.push([point.x, point.y, point.z]);
+                    ids.push(id);
+                }
+            }
+
+            Ok::<_, Error>(())
+        };
+
+        info!("Reading points for outlier removal...");
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            let seek = |source: &mut Source, index: u64| {
+                source
+                    .reader
+                    .seek(index)
+                    .map_err(|err| Error::InputFile(source.name.clone(), err))
+            };
+            let scale = self.intensity_scale.get(i).copied();
+            let matching = self.intensity_match.get(i);
+
+            // Undecodable points are left out, they're skipped on the actual read.
+            for range in source.ranges.clone() {
+                seek(source, range.start)?;
+
+                for index in range {
+                    match source.reader.read_point() {
+                        Ok(Some(mut point)) => {
+                            adjust_intensity(&mut point, scale, matching);
+                            points.push(point);
+                            point_ids.push((i, index));
+
+                            if points.len() == REPROJECT_CHUNK {
+                                flush(&mut points, &mut point_ids)?;
+                            }
+                        }
+                        _ => break,
+                    }
+                }
+            }
+
+            seek(source, 0)?;
+        }
+
+        flush(&mut points, &mut point_ids)?;
+
+        for removal in removals {
+            let outliers = removal.outliers(&positions);
+            info!(
+                "Found {} points of {}: {}",
+                outliers.iter().filter(|o| **o).count(),
+                positions.len(),
+                removal.criterion()
+            );
+
+            for (id, _) in ids.iter().zip(outliers).filter(|(_, outlier)| *outlier) {
+                self.outliers.entry(*id).or_insert(removal.criterion());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reports the reading progress to `observer`, stopping with `Error::Cancelled` once it's
+    /// cancelled.
+    pub fn observe(&mut self, observer: Observer) {
+        self.observer = Some(observer);
+    }
+
+    /// Withholds points into `holdout` rather than returning them.
+    pub fn withhold(&mut self, holdout: Holdout) {
+        self.holdout = Some(holdout);
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = &Header> {
+        self.sources.iter().map(|s| s.reader.header())
+    }
+
+    /// Combined bounds of every input, reprojected if the points are.
+    pub fn bounds(&self) -> Bounds {
+        if let Some((_, bounds)) = &self.reprojection {
+            return *bounds;
+        }
+
+        let mut headers = self.headers();
+        let mut bounds = headers.next().map(|h| h.bounds()).unwrap_or_default();
+
+        for header in headers {
+            let b = header.bounds();
+
+            bounds.min.x = bounds.min.x.min(b.min.x);
+            bounds.min.y = bounds.min.y.min(b.min.y);
+            bounds.min.z = bounds.min.z.min(b.min.z);
+            bounds.max.x = bounds.max.x.max(b.max.x);
+            bounds.max.y = bounds.max.y.max(b.max.y);
+            bounds.max.z = bounds.max.z.max(b.max.z);
+        }
+
+        bounds
+    }
+
+    /// Total number of points to be read over every input.
+    pub fn number_of_points(&self) -> u64 {
+        self.sources
+            .iter()
+            .flat_map(|s| s.ranges.iter())
+            .map(|r| r.end - r.start)
+            .sum()
+    }
+
+    /// Calls `f` with the source index of about `n` points sampled evenly over every input, in
+    /// blocks of consecutive points to keep seeking and LAZ decompression cheap. Undecodable
+    /// points are left out. Only to be called before reading any points, the inputs are rewound
+    /// afterwards.
+    fn sample_points(&mut self, n: u64, mut f: impl FnMut(usize, &Point)) -> Result<()> {
+        const BLOCK: u64 = 1000;
+
+        // Distance between the starts of blocks.
+        let stride = (self.number_of_points() / n.div_ceil(BLOCK).max(1)).max(BLOCK);
+
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            let seek = |source: &mut Source, index: u64| {
+                source
+                    .reader
+                    .seek(index)
+                    .map_err(|err| Error::InputFile(source.name.clone(), err))
+            };
+
+            for range in source.ranges.clone() {
+                for start in (range.start..range.end).step_by(stride as usize) {
+                    seek(source, start)?;
+
+                    for _ in start..(start + BLOCK).min(range.end) {
+                        match source.reader.read_point() {
+                            Ok(Some(point)) => f(i, &point),
+                            _ => break,
+                        }
+                    }
+                }
+            }
+
+            seek(source, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// XY of about `n` points sampled evenly over every input, see `sample_points`.
+    pub fn sample_xy(&mut self, n: u64) -> Result<Vec<(f64, f64)>> {
+        let mut samples = Vec::with_capacity(n as usize);
+        self.sample_points(n, |_, point| samples.push((point.x, point.y)))?;
+
+        if let Some((transform, _)) = &self.reprojection {
+            let (mut x, mut y): (Vec<f64>, Vec<f64>) = samples.into_iter().unzip();
+            transform.transform_coords(&mut x, &mut y, &mut [])?;
+            samples = x.into_iter().zip(y).collect();
+        }
+
+        Ok(samples)
+    }
+
+    /// Reads the next point. Reprojected points are read ahead and transformed
+    /// `REPROJECT_CHUNK` at a time.
+    pub fn next_point(&mut self, report: &mut Report) -> Result<Option<Point>> {
+        loop {
+            let point = match self.reprojection.is_some() {
+                true => {
+                    if self.pending.is_empty() {
+                        self.read_ahead(report)?;
+                    }
+
+                    match self.pending.pop_front() {
+                        Some(point) => point,
+                        None => return Ok(None),
+                    }
+                }
+                false => match self.read_point(report)? {
+                    Some(point) => point,
+                    None => return Ok(None),
+                },
+            };
+
+            if let Some(clip) = &self.clip {
+                if point.x < clip.min.x
+                    || point.x > clip.max.x
+                    || point.y < clip.min.y
+                    || point.y > clip.max.y
+                {
+                    report.points_out_of_extent += 1;
+                    continue;
+                }
+            }
+
+            if self.holdout.as_ref().is_some_and(|h| h.take(&point)) {
+                report.points_withheld += 1;
+                continue;
+            }
+
+            return Ok(Some(point));
+        }
+    }
+
+    /// Reads up to `REPROJECT_CHUNK` points into `pending` and reprojects them together.
+    fn read_ahead(&mut self, report: &mut Report) -> Result<()> {
+        while self.pending.len() < REPROJECT_CHUNK {
+            match self.read_point(report)? {
+                Some(point) => self.pending.push_back(point),
+                None => break,
+            }
+        }
+
+        if let Some((transform, _)) = &self.reprojection {
+            reproject_points(transform, self.pending.make_contiguous())?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next point not dropped as an outlier or by thinning, with its intensity adjusted
+    /// but still in the input CRS. Up to `max_corrupt` points which fail to decode are skipped,
+    /// along with the rest of their LAZ chunk, before giving up.
+    fn read_point(&mut self, report: &mut Report) -> Result<Option<Point>> {
+        loop {
+            if self.index >= self.end && !self.next_range()? {
+                return Ok(None);
+            }
+
+            let source = &mut self.sources[self.current];
+
+            let err = match source.reader.read_point() {
+                Ok(Some(mut point)) => {
+                    if let Some((_, index)) = source.index.as_mut() {
+                        index.add(self.index, &point);
+                    }
+
+                    self.index += 1;
+                    report.points_read += 1;
+                    self.progress.advance(1);
+
+                    if let Some(observer) = &self.observer {
+                        observer.observe(
+                            Stage::Reading,
+                            self.progress.done,
+                            self.progress.total,
+                        )?;
+                    }
+
+                    if let Some(criterion) = self.outliers.get(&(self.current, self.index - 1)) {
+                        report.filtered(criterion);
+                        continue;
+                    }
+
+                    if self.thinning.as_mut().is_some_and(|t| !t.keep()) {
+                        report.points_thinned += 1;
+                        continue;
+                    }
+
+                    adjust_intensity(
+                        &mut point,
+                        self.intensity_scale.get(self.current).copied(),
+                        self.intensity_match.get(self.current),
+                    );
+
+                    return Ok(Some(point));
+                }
+                // Fewer points than the header claims.
+                Ok(None) => {
+                    let missing = self.end - self.index
+                        + source.ranges.iter().map(|r| r.end - r.start).sum::<u64>();
+                    self.progress.total = self.progress.total.saturating_sub(missing);
+                    self.end = self.index;
+                    source.ranges.clear();
+                    continue;
+                }
+                Err(err) => err,
+            };
+
+            let next = match self.skip {
+                Some(skip) => ((self.index / skip + 1) * skip).min(self.end),
+                None => return Err(Error::InputFile(source.name.clone(), err)),
+            };
+
+            self.corrupt += next - self.index;
+            self.progress.advance(next - self.index);
+            report.points_corrupt += next - self.index;
+
+            if self.corrupt > self.max_corrupt {
+                return Err(Error::InputFile(source.name.clone(), err));
+            }
+
+            warn!(
+                "Skipping {} corrupt point(s) at index {} of {}: {}",
+                next - self.index,
+                self.index,
+                source.name,
+                err
+            );
+
+            self.index = next;
+            if next < self.end {
+                source
+                    .reader
+                    .seek(next)
+                    .map_err(|err| Error::InputFile(source.name.clone(), err))?;
+            }
+        }
+    }
+
+    /// Moves on to the next range to read, of this or the following sources. False once every
+    /// range has been read.
+    fn next_range(&mut self) -> Result<bool> {
+        while let Some(source) = self.sources.get_mut(self.current) {
+            if let Some(range) = source.ranges.pop_front() {
+                if range.start != self.index {
+                    source
+                        .reader
+                        .seek(range.start)
+                        .map_err(|err| Error::InputFile(source.name.clone(), err))?;
+                }
+
+                self.index = range.start;
+                self.end = range.end;
+
+                return Ok(true);
+            }
+
+            if let Some((path, index)) = source.index.take() {
+                info!("Saving the chunk index of {}", source.name);
+                index.save(&path)?;
+            }
+
+            self.current += 1;
+            self.index = 0;
+
+            if let Some(source) = self.sources.get(self.current) {
+                info!(
+                    "Reading {} ({} of {})",
+                    source.name,
+                    self.current + 1,
+                    self.sources.len()
+                );
+                self.skip = skip_size(source.reader.header());
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// Scales and matches the intensity of a point of a source, as set up by
+/// `Input::normalize_intensity` and `Input::harmonize_intensity`.
+fn adjust_intensity(point: &mut Point, scale: Option<f64>, matching: Option<&QuantileMap>) {
+    if scale.is_some() || matching.is_some() {
+        let mut intensity = point.intensity as f64 * scale.unwrap_or(1.0);
+        if let Some(matching) = matching {
+            intensity = matching.apply(intensity);
+        }
+
+        point.intensity = intensity.round().clamp(0.0, u16::MAX as f64) as u16;
+    }
+}
+
+/// Transforms the XY of `points` with a single call into PROJ.
+fn reproject_points(transform: &CoordTransform, points: &mut [Point]) -> Result<()> {
+    let (mut x, mut y): (Vec<f64>, Vec<f64>) = points.iter().map(|p| (p.x, p.y)).unzip();
+    transform.transform_coords(&mut x, &mut y, &mut [])?;
+
+    for (point, (x, y)) in points.iter_mut().zip(x.into_iter().zip(y)) {
+        (point.x, point.y) = (x, y);
+    }
+
+    Ok(())
+}
+
+/// WKT of the CRS of the first LAS/LAZ input with one, reading only headers.
+pub fn peek_wkt(paths: &[PathBuf]) -> Result<Option<String>> {
+    for path in expand_paths(paths)? {
+        if let Some(wkt) = peek_header(&path)?.as_ref().and_then(header_wkt) {
+            return Ok(Some(wkt));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Name (the file stem) and XY bounds of each LAS/LAZ input, reading only headers.
+pub fn tile_extents(paths: &[PathBuf]) -> Result<Vec<(String, Bounds)>> {
+    let mut tiles = Vec::new();
+
+    for path in expand_paths(paths)? {
+        let Some(header) = peek_header(&path)? else {
+            warn!("{} isn't a LAS/LAZ file, not a tile", path.display());
+            continue;
+        };
+
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        tiles.push((name.to_string(), header.bounds()));
+    }
+
+    Ok(tiles)
+}
+
+/// The header of the LAS/LAZ file at `path`, None for other inputs.
+fn peek_header(path: &Path) -> Result<Option<Header>> {
+    if path == Path::new(STDIN) || !is_point_cloud(path) {
+        return Ok(None);
+    }
+
+    let name = path.display().to_string();
+    let reader = match is_url(path) {
+        true => Reader::new(RemoteReader::open(&name)?),
+        false => Reader::from_path(path),
+    }
+    .map_err(|err| Error::InputFile(name, err))?;
+
+    Ok(Some(reader.header().clone()))
+}
+
+/// Input path for reading from stdin.
+const STDIN: &str = "-";
+
+/// Reads a LAS/LAZ stream from stdin. Reading needs to seek, so the stream is buffered in
+/// memory.
+fn read_stdin() -> Result<Reader> {
+    let mut buf = Vec::new();
+    io::stdin().lock().read_to_end(&mut buf)?;
+
+    info!("Read {} bytes from stdin", buf.len());
+
+    Reader::new(Cursor::new(buf)).map_err(|err| Error::InputFile("stdin".to_string(), err))
+}
+
+/// Scales buffered coordinates may be stored with, finest first: from a tenth of a micrometre,
+/// about a centimetre in degrees, to millimetres.
+const BUFFER_SCALES: [f64; 5] = [1e-7, 1e-6, 1e-5, 1e-4, 1e-3];
+
+/// Buffers `points` read from a non-LAS input in memory as LAS, each axis offset by its minimum
+/// and scaled as finely as its extent fits in the LAS 32 bit integers. Both the points and the
+/// LAS copy are held in memory until the copy is written.
+pub fn buffer_points(name: &str, points: Vec<Point>) -> Result<Reader> {
+    let mut builder = Builder::from((1, 4));
+    let gps_time = points.iter().any(|p| p.gps_time.is_some());
+    let color = points.iter().any(|p| p.color.is_some());
+    builder.point_format = Format::new(match (gps_time, color) {
+        (false, false) => 0,
+        (true, false) => 1,
+        (false, true) => 2,
+        (true, true) => 3,
+    })?;
+
+    let transform = |axis: &str, f: fn(&Point) -> f64| -> Result<Transform> {
+        let (min, max) = points
+            .iter()
+            .map(f)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                (min.min(v), max.max(v))
+            });
+        if !min.is_finite() || !max.is_finite() {
+            return Ok(Transform {
+                scale: BUFFER_SCALES[BUFFER_SCALES.len() - 1],
+                offset: 0.0,
+            });
+        }
+
+        let offset = min.floor();
+        let scale = BUFFER_SCALES
+            .into_iter()
+            .find(|scale| (max - offset) / scale <= i32::MAX as f64)
+            .ok_or_else(|| {
+                Error::Unsupported(format!(
+                    "{name} spans {} along {axis}, too far to store with millimetre precision",
+                    max - min
+                ))
+            })?;
+
+        Ok(Transform { scale, offset })
+    };
+
+    builder.transforms = Vector {
+        x: transform("x", |p| p.x)?,
+        y: transform("y", |p| p.y)?,
+        z: transform("z", |p| p.z)?,
+    };
+
+    let count = points.len();
+    let mut writer = Writer::new(Cursor::new(Vec::new()), builder.into_header()?)?;
+
+    for point in points {
+        writer.write_point(point)?;
+    }
+
+    info!("Read {count} points from {name}");
+
+    Reader::new(Cursor::new(writer.into_inner()?.into_inner()))
+        .map_err(|err| Error::InputFile(name.to_string(), err))
+}
+
+/// Whether a file name matches a pattern with `*` (any run of characters) and `?` (any one
+/// character) wildcards.
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            wildcard_match(&pattern[1..], name)
+                || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) => p == n && wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Whether `path` is a LAS/LAZ file.
+pub fn is_point_cloud(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("las") || e.eq_ignore_ascii_case("laz"))
+}
+
+/// Expands directories into the LAS/LAZ files they contain, and wildcards in the file name
+/// (e.g. `tiles/*.laz`) into the matching files. Expanded paths are sorted, for a stable
+/// reading order. URLs are kept as they are, `?` being their query.
+pub fn expand_paths(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut expanded = Vec::new();
+
+    for path in paths {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut matches: Vec<PathBuf> = if is_url(path) {
+            vec![path.clone()]
+        } else if path.is_dir() {
+            fs::read_dir(path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<std::io::Result<Vec<PathBuf>>>()?
+                .into_iter()
+                .filter(|p| p.is_file() && is_point_cloud(p))
+                .collect()
+        } else if name.contains(['*', '?']) {
+            let pattern: Vec<char> = name.chars().collect();
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+
+            fs::read_dir(dir)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<std::io::Result<Vec<PathBuf>>>()?
+                .into_iter()
+                .filter(|p| {
+                    p.is_file()
+                        && p.file_name().is_some_and(|n| {
+                            wildcard_match(
+                                &pattern,
+                                &n.to_string_lossy().chars().collect::<Vec<_>>(),
+                            )
+                        })
+                })
+                .collect()
+        } else {
+            // Including stdin, as '-'.
+            vec![path.clone()]
+        };
+
+        if matches.is_empty() {
+            return Err(Error::NoInput(path.display().to_string()));
+        }
+
+        matches.sort();
+        expanded.extend(matches);
+    }
+
+    Ok(expanded)
+}
+
+/// Points to skip past a decoding error.
+pub fn skip_size(header: &Header) -> Option<u64> {
+    match header.point_format().is_compressed {
+        true => laz_chunk_size(header),
+        false => Some(1),
+    }
+}
+
+/// Chunk size from the laszip VLR, None for variable sized chunks.
+fn laz_chunk_size(header: &Header) -> Option<u64> {
+    let vlr = header
+        .vlrs()
+        .iter()
+        .find(|vlr| vlr.user_id == "laszip encoded" && vlr.record_id == 22204);
+
+    let Some(vlr) = vlr else {
+        return Some(DEFAULT_CHUNK_SIZE);
+    };
+
+    // compressor (u16), coder (u16), version (u8, u8, u16), options (u32), chunk size (u32)
+    let chunk_size = vlr
+        .data
+        .get(12..16)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .unwrap_or(DEFAULT_CHUNK_SIZE as u32);
+
+    match chunk_size {
+        u32::MAX => None,
+        size => Some(size as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use las::point::Classification;
+
+    use super::*;
+    use crate::filter::filter_parser;
+    use crate::outliers::RadiusFilter;
+
+    /// Points along a diagonal from (x, y), `step` apart.
+    fn diagonal(n: usize, x: f64, y: f64, step: f64) -> Vec<Point> {
+        (0..n)
+            .map(|i| Point {
+                x: x + i as f64 * step,
+                y: y + i as f64 * step,
+                z: i as f64,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn input(points: Vec<Point>) -> Input {
+        Input::new(
+            vec![("test".to_string(), buffer_points("test", points).unwrap())],
+            0,
+        )
+    }
+
+    /// Every point read, in order.
+    fn read_all(input: &mut Input, report: &mut Report) -> Vec<Point> {
+        let mut points = Vec::new();
+        while let Some(point) = input.next_point(report).unwrap() {
+            points.push(point);
+        }
+
+        points
+    }
+
+    #[test]
+    fn reprojects_in_chunks() {
+        let n = REPROJECT_CHUNK * 2 + 10;
+        let points = diagonal(n, 500_000.0, 6_000_000.0, 1.0);
+        let from = SpatialRef::from_epsg(32633).unwrap();
+        let to = SpatialRef::from_epsg(3857).unwrap();
+
+        let mut input = input(points.clone());
+        input.reproject(&from, &to).unwrap();
+        let mut report = Report::default();
+        let read = read_all(&mut input, &mut report);
+
+        assert_eq!(read.len(), n);
+        assert_eq!(report.points_read, n as u64);
+
+        let transform = CoordTransform::new(&from, &to).unwrap();
+        for (expected, point) in points.iter().zip(&read) {
+            let (mut x, mut y) = ([expected.x], [expected.y]);
+            transform.transform_coords(&mut x, &mut y, &mut []).unwrap();
+
+            assert!((point.x - x[0]).abs() < 1e-6);
+            assert!((point.y - y[0]).abs() < 1e-6);
+            assert_eq!(point.z, expected.z);
+        }
+    }
+
+    #[test]
+    fn outliers_among_filtered_points() {
+        // A ground grid, and a lone ground point whose only neighbours are noise.
+        let ground = |x: f64, y: f64| Point {
+            x,
+            y,
+            classification: Classification::Ground,
+            ..Default::default()
+        };
+        let mut points: Vec<Point> = (0..100)
+            .map(|i| ground((i % 10) as f64, (i / 10) as f64))
+            .collect();
+        points.push(ground(100.0, 100.0));
+        points.extend((1..=3).map(|i| Point {
+            x: 100.0 + i as f64 * 0.5,
+            y: 100.0,
+            classification: Classification::HighNoise,
+            ..Default::default()
+        }));
+
+        let removals = [OutlierRemoval::Radius(RadiusFilter {
+            radius: 2.0,
+            min_neighbors: 2,
+        })];
+        let lone = |points: &[Point]| points.iter().any(|p| p.x == 100.0 && p.y == 100.0);
+
+        let mut unfiltered = input(points.clone());
+        unfiltered
+            .remove_outliers(&removals, &PointFilter::new(Vec::new(), None, None))
+            .unwrap();
+        let read = read_all(&mut unfiltered, &mut Report::default());
+        assert_eq!(read.len(), 104);
+        assert!(lone(&read));
+
+        // Without the noise the lone point has no neighbours left.
+        let ground_only = PointFilter::new(vec![filter_parser("class=2").unwrap()], None, None);
+        let mut filtered = input(points);
+        filtered.remove_outliers(&removals, &ground_only).unwrap();
+        let read = read_all(&mut filtered, &mut Report::default());
+        assert_eq!(read.len(), 103);
+        assert!(!lone(&read));
+    }
+
+    /// Every point of a reader, in order.
+    fn read_reader(mut reader: Reader) -> Vec<Point> {
+        let mut points = Vec::new();
+        while let Some(point) = reader.read_point().unwrap() {
+            points.push(point);
+        }
+
+        points
+    }
+
+    #[test]
+    fn buffers_with_the_finest_scale_the_extent_allows() {
+        let at = |x: f64, y: f64, z: f64| Point {
+            x,
+            y,
+            z,
+            ..Default::default()
+        };
+
+        // Degrees keep about a centimetre.
+        let degrees = vec![at(12.3456789, 55.1234567, 10.5), at(12.5, 55.5, 11.25)];
+        let read = read_reader(buffer_points("degrees", degrees.clone()).unwrap());
+        for (expected, point) in degrees.iter().zip(&read) {
+            assert!((point.x - expected.x).abs() < 1e-7);
+            assert!((point.y - expected.y).abs() < 1e-7);
+            assert!((point.z - expected.z).abs() < 1e-7);
+        }
+
+        // Wide projected extents fall back to millimetres.
+        let wide = vec![at(100_000.0, 0.0, 0.0), at(2_000_000.123, 1.0, 0.0)];
+        let read = read_reader(buffer_points("wide", wide).unwrap());
+        assert!((read[1].x - 2_000_000.123).abs() < 1e-3);
+
+        let too_wide = vec![at(0.0, 0.0, 0.0), at(3_000_000.0, 0.0, 0.0)];
+        assert!(matches!(
+            buffer_points("too wide", too_wide),
+            Err(Error::Unsupported(_))
+        ));
+    }
+    #[test]
+    fn urls_are_not_expanded() {
+        let url = PathBuf::from("https://example.com/tiles/a.laz?X-Amz-Signature=abc");
+
+        assert_eq!(expand_paths(&[url.clone()]).unwrap(), vec![url]);
+    }
+    #[test]
+    fn harmonize_references() {
+        assert_eq!(harmonize_parser("ensemble"), Ok(Harmonize::Ensemble));
+        assert_eq!(
+            harmonize_parser("tiles/a.laz"),
+            Ok(Harmonize::Reference("tiles/a.laz".to_string()))
+        );
+        assert!(harmonize_parser("").is_err());
+    }
+
+    #[test]
+    fn quantile_maps() {
+        let map = QuantileMap {
+            pairs: vec![(0.0, 10.0), (10.0, 20.0), (10.0, 30.0), (20.0, 40.0)],
+        };
+
+        assert_eq!(map.apply(-5.0), 10.0);
+        assert_eq!(map.apply(5.0), 15.0);
+        assert_eq!(map.apply(15.0), 35.0);
+        assert_eq!(map.apply(25.0), 40.0);
+        assert_eq!(QuantileMap { pairs: Vec::new() }.apply(7.0), 7.0);
+
+        let mut values: Vec<f64> = (0..=100).rev().map(f64::from).collect();
+        assert_eq!(
+            quantiles(&mut values),
+            (0..=100).map(f64::from).collect::<Vec<_>>()
+        );
+        assert!(quantiles(&mut []).is_empty());
+    }
+
+    #[test]
+    fn harmonizes_to_a_reference() {
+        let points = |step: u16| -> Vec<Point> {
+            (0..1000)
+                .map(|i| Point {
+                    x: i as f64,
+                    intensity: i * step,
+                    ..Default::default()
+                })
+                .collect()
+        };
+        let mut input = Input::new(
+            vec![
+                ("a".to_string(), buffer_points("a", points(1)).unwrap()),
+                ("b".to_string(), buffer_points("b", points(2)).unwrap()),
+            ],
+            0,
+        );
+
+        input
+            .harmonize_intensity(&Harmonize::Reference("a".to_string()))
+            .unwrap();
+        let read = read_all(&mut input, &mut Report::default());
+
+        assert_eq!(read.len(), 2000);
+        for (i, point) in read.iter().enumerate() {
+            assert!(point.intensity.abs_diff((i % 1000) as u16) <= 1);
+        }
+
+        assert!(input
+            .harmonize_intensity(&Harmonize::Reference("c".to_string()))
+            .is_err());
+    }
+}
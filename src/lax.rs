@@ -0,0 +1,365 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use las::{Bounds, Reader};
+use tracing::info;
+
+use crate::error::{Error, Result};
+
+/// Finest quadtree level written, 4^12 cells.
+const MAX_LEVELS: u32 = 12;
+
+/// Points between two intervals of a cell below which they are written as one, trading a few
+/// extra points read for fewer seeks.
+const MERGE_GAP: u32 = 1000;
+
+/// A LASindex (`.lax`, as written by LAStools' lasindex) spatial index: a quadtree over the XY
+/// extent, with the intervals of point indices falling in each of its cells.
+pub struct Lax {
+    /// min x, max x, min y, max y of the quadtree.
+    bounds: [f64; 4],
+    /// Point index intervals of each cell, by cell index.
+    cells: BTreeMap<u32, Vec<Range<u64>>>,
+}
+
+/// Path of the `.lax` sidecar of the file at `path`.
+pub fn lax_path(path: &Path) -> PathBuf {
+    path.with_extension("lax")
+}
+
+/// Index of the first cell of each quadtree level.
+fn level_offset(level: u32) -> u32 {
+    (0..level).map(|l| 1 << (2 * l)).sum()
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f32(r: &mut impl Read) -> Result<f64> {
+    Ok(f32::from_bits(read_u32(r)?) as f64)
+}
+
+/// `value` as an f32 rounded down (`direction` -1) or up (1), past any rounding error.
+fn to_f32(value: f64, direction: f64) -> f32 {
+    (value + direction * value.abs() * f32::EPSILON as f64) as f32
+}
+
+fn expect(r: &mut impl Read, signature: &[u8; 4]) -> Result<()> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+
+    match &buf == signature {
+        true => Ok(()),
+        false => Err(Error::Lax(format!(
+            "expected '{}', found '{}'",
+            String::from_utf8_lossy(signature),
+            String::from_utf8_lossy(&buf)
+        ))),
+    }
+}
+
+impl Lax {
+    /// The `.lax` sidecar of the file at `path`, None if it has none.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let file = match File::open(lax_path(path)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut r = BufReader::new(file);
+
+        expect(&mut r, b"LASX")?;
+        read_u32(&mut r)?;
+
+        // Quadtree
+        expect(&mut r, b"LASS")?;
+        if read_u32(&mut r)? != 0 {
+            return Err(Error::Unsupported(
+                "LAX spatial index other than a quadtree".into(),
+            ));
+        }
+        expect(&mut r, b"LASQ")?;
+        read_u32(&mut r)?;
+        let _levels = read_u32(&mut r)?;
+        let level_index = read_u32(&mut r)?;
+        let _implicit_levels = read_u32(&mut r)?;
+        if level_index != 0 {
+            return Err(Error::Unsupported("LAX quadtree of a sub-tile".into()));
+        }
+
+        let mut bounds = [0.0; 4];
+        for value in bounds.iter_mut() {
+            *value = read_f32(&mut r)?;
+        }
+
+        // Intervals
+        expect(&mut r, b"LASV")?;
+        read_u32(&mut r)?;
+
+        let mut cells = BTreeMap::new();
+        for _ in 0..read_u32(&mut r)? {
+            let cell = read_u32(&mut r)?;
+            let intervals = read_u32(&mut r)?;
+            let _points = read_u32(&mut r)?;
+
+            let ranges = (0..intervals)
+                .map(|_| Ok(read_u32(&mut r)? as u64..read_u32(&mut r)? as u64 + 1))
+                .collect::<Result<Vec<_>>>()?;
+
+            cells.insert(cell, ranges);
+        }
+
+        Ok(Some(Self { bounds, cells }))
+    }
+
+    /// XY box (min x, min y, max x, max y) of the cell with index `cell`.
+    fn cell_box(&self, cell: u32) -> [f64; 4] {
+        let mut level = 0;
+        while level_offset(level + 1) <= cell {
+            level += 1;
+        }
+        let local = cell - level_offset(level);
+
+        let [mut min_x, mut max_x, mut min_y, mut max_y] = self.bounds;
+
+        for l in (0..level).rev() {
+            let (mid_x, mid_y) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+            let quadrant = (local >> (2 * l)) & 3;
+
+            match quadrant & 1 != 0 {
+                true => min_x = mid_x,
+                false => max_x = mid_x,
+            }
+            match quadrant & 2 != 0 {
+                true => min_y = mid_y,
+                false => max_y = mid_y,
+            }
+        }
+
+        [min_x, min_y, max_x, max_y]
+    }
+
+    /// Point index ranges of the cells intersecting `extent`, sorted and merged.
+    pub fn ranges(&self, extent: &Bounds) -> Vec<Range<u64>> {
+        let mut ranges: Vec<Range<u64>> = self
+            .cells
+            .iter()
+            .filter(|(cell, _)| {
+                let b = self.cell_box(**cell);
+
+                b[0] <= extent.max.x
+                    && b[2] >= extent.min.x
+                    && b[1] <= extent.max.y
+                    && b[3] >= extent.min.y
+            })
+            .flat_map(|(_, ranges)| ranges.iter().cloned())
+            .collect();
+
+        ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+
+        merged
+    }
+}
+
+/// Writes a `.lax` sidecar for the LAS/LAZ file at `path`, with quadtree cells of about
+/// `cell_size` map units.
+pub fn write_lax(path: &Path, cell_size: f64) -> Result<()> {
+    let mut reader = Reader::from_path(path)?;
+    let header_bounds = reader.header().bounds();
+
+    if reader.header().number_of_points() > u32::MAX as u64 {
+        return Err(Error::Unsupported(
+            "LAX indices of more than 2^32 points".into(),
+        ));
+    }
+
+    // A square over the XY bounds, rounded outwards to the f32 it's stored as.
+    let size = (header_bounds.max.x - header_bounds.min.x)
+        .max(header_bounds.max.y - header_bounds.min.y)
+        .max(cell_size);
+    let levels = ((size / cell_size).log2().ceil() as u32).min(MAX_LEVELS);
+    let (min_x, min_y) = (
+        to_f32(header_bounds.min.x, -1.0),
+        to_f32(header_bounds.min.y, -1.0),
+    );
+    let (max_x, max_y) = (
+        to_f32(min_x as f64 + size, 1.0),
+        to_f32(min_y as f64 + size, 1.0),
+    );
+
+    let cell_of = |x: f64, y: f64| -> u32 {
+        let (mut x0, mut x1, mut y0, mut y1) =
+            (min_x as f64, max_x as f64, min_y as f64, max_y as f64);
+        let mut local = 0;
+
+        for _ in 0..levels {
+            let (mid_x, mid_y) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+            local <<= 2;
+
+            match x < mid_x {
+                true => x1 = mid_x,
+                false => {
+                    x0 = mid_x;
+                    local |= 1;
+                }
+            }
+            match y < mid_y {
+                true => y1 = mid_y,
+                false => {
+                    y0 = mid_y;
+                    local |= 2;
+                }
+            }
+        }
+
+        level_offset(levels) + local
+    };
+
+    // Inclusive intervals and point counts per cell.
+    let mut cells: BTreeMap<u32, (Vec<(u32, u32)>, u32)> = BTreeMap::new();
+
+    for (i, point) in reader.points().enumerate() {
+        let point = point?;
+        let i = i as u32;
+        let (intervals, points) = cells.entry(cell_of(point.x, point.y)).or_default();
+
+        *points += 1;
+        match intervals.last_mut() {
+            Some((_, end)) if i - *end <= MERGE_GAP => *end = i,
+            _ => intervals.push((i, i)),
+        }
+    }
+
+    let mut w = BufWriter::new(File::create(lax_path(path))?);
+
+    w.write_all(b"LASX")?;
+    w.write_all(&0u32.to_le_bytes())?;
+
+    w.write_all(b"LASS")?;
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(b"LASQ")?;
+    for value in [0, levels, 0, 0] {
+        w.write_all(&u32::to_le_bytes(value))?;
+    }
+    for value in [min_x, max_x, min_y, max_y] {
+        w.write_all(&value.to_le_bytes())?;
+    }
+
+    w.write_all(b"LASV")?;
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(&(cells.len() as u32).to_le_bytes())?;
+
+    for (cell, (intervals, points)) in cells.iter() {
+        for value in [*cell, intervals.len() as u32, *points] {
+            w.write_all(&value.to_le_bytes())?;
+        }
+        for (start, end) in intervals {
+            w.write_all(&start.to_le_bytes())?;
+            w.write_all(&end.to_le_bytes())?;
+        }
+    }
+
+    w.flush()?;
+
+    info!(
+        "{}: indexed {} cells of {} levels",
+        path.display(),
+        cells.len(),
+        levels
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use las::{Builder, Point, Vector, Writer};
+
+    use super::*;
+    use crate::util::temp_path;
+
+    fn extent(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Bounds {
+        Bounds {
+            min: Vector {
+                x: min_x,
+                y: min_y,
+                z: 0.0,
+            },
+            max: Vector {
+                x: max_x,
+                y: max_y,
+                z: 0.0,
+            },
+        }
+    }
+
+    #[test]
+    fn quadtree() {
+        assert_eq!((0..4).map(level_offset).collect::<Vec<_>>(), [0, 1, 5, 21]);
+
+        let lax = Lax {
+            bounds: [0.0, 4.0, 0.0, 4.0],
+            cells: BTreeMap::from([
+                (1, vec![0..10]),
+                (2, vec![30..40]),
+                (4, vec![10..20, 50..60]),
+            ]),
+        };
+
+        assert_eq!(lax.cell_box(0), [0.0, 0.0, 4.0, 4.0]);
+        assert_eq!(lax.cell_box(1), [0.0, 0.0, 2.0, 2.0]);
+        assert_eq!(lax.cell_box(2), [2.0, 0.0, 4.0, 2.0]);
+        assert_eq!(lax.cell_box(4), [2.0, 2.0, 4.0, 4.0]);
+        assert_eq!(lax.cell_box(5), [0.0, 0.0, 1.0, 1.0]);
+
+        assert_eq!(lax.ranges(&extent(0.0, 0.0, 1.5, 1.5)), [0..10]);
+        assert_eq!(
+            lax.ranges(&extent(1.5, 0.0, 3.0, 3.0)),
+            [0..20, 30..40, 50..60]
+        );
+        assert!(lax.ranges(&extent(5.0, 5.0, 6.0, 6.0)).is_empty());
+    }
+
+    #[test]
+    fn write_and_load() {
+        let path = temp_path("index.las");
+        let mut writer =
+            Writer::from_path(&path, Builder::from((1, 4)).into_header().unwrap()).unwrap();
+        for (x, y) in [(0.5, 0.5), (3.5, 3.5), (0.6, 0.4)] {
+            writer
+                .write_point(Point {
+                    x,
+                    y,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        writer.close().unwrap();
+
+        assert!(Lax::load(&path).unwrap().is_none());
+
+        write_lax(&path, 1.0).unwrap();
+        let lax = Lax::load(&path).unwrap().unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(lax_path(&path)).unwrap();
+
+        assert_eq!(lax.ranges(&extent(0.4, 0.3, 0.7, 0.6)), [0..3]);
+        assert_eq!(lax.ranges(&extent(3.4, 3.4, 3.6, 3.6)), [1..2]);
+    }
+}
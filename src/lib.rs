@@ -0,0 +1,2251 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use las::{Bounds, Point, Vector};
+use tracing::{info, instrument, warn};
+
+use self::binning::{bin_points, BinningParams, Breaklines};
+use self::checkpoint::{Checkpoint, CHECKPOINT_ROWS};
+use self::crs::{spatial_ref, transform_bounds, vertical_units};
+pub use self::error::Error;
+use self::expr::{expr_parser, Expr};
+use self::extra::ExtraBytes;
+use self::fallback::{fallback_parser, fill_nodata, Fallback};
+use self::filter::{
+    class_set_parser, filter_parser, source_set_parser, user_data_parser, ClassSet, Filter, Flag,
+    PointFilter, Returns, SourceSet,
+};
+use self::idw::{idw, IdwParams};
+use self::input::{
+    expand_paths, harmonize_parser, is_point_cloud, peek_wkt, tile_extents, Harmonize, Input,
+    Thinning,
+};
+use self::lax::write_lax;
+use self::outliers::{radius_filter_parser, sor_parser, OutlierRemoval, RadiusFilter, Sor};
+use self::output::{
+    apply_nodata_policy, outlier_flags, preflight, raster_extent, read_rows, render, render_strips,
+    write_categories, write_raster, Band, BandType, Compression, NodataPolicy, RasterInfo,
+    RasterWriter, Units, LAS_CLASSES,
+};
+use self::products::run_products;
+use self::profile::Profile;
+use self::provenance::{provenance, Provenance, PROVENANCE};
+use self::remote::is_url;
+use self::report::Report;
+use self::service::Observer;
+use self::spline::{spline_surface, SplineParams};
+use self::stream::{stream, StreamParams};
+use self::synthetic::{Rng, Scene};
+use self::text::{text_columns_parser, TextColumns};
+use self::trees::{
+    canopy_height, local_maxima, segment_crowns, window_parser, write_crown_polygons, write_crowns,
+    write_tree_tops, Window,
+};
+use self::triangulation::{schedule_parser, triangulate, Schedule, Surface, TriangulationParams};
+use self::util::{clip_bounds, header_wkt, trim_bounds, unit_diagnostics, GridDefinition};
+use self::validate::validate;
+use self::validation::Holdout;
+use self::vector::{path_field_parser, read_features, read_named_extents, Polygons};
+use self::voxel::VoxelKeep;
+
+mod copc;
+mod crs;
+mod drivers;
+mod ept;
+mod error;
+mod expr;
+mod extra;
+mod fallback;
+mod filter;
+mod index;
+mod input;
+mod lax;
+mod outliers;
+mod output;
+mod pcd;
+mod ply;
+mod profile;
+mod provenance;
+mod remote;
+mod report;
+mod text;
+mod util;
+mod validation;
+mod vector;
+
+mod bench;
+mod binning;
+mod checkpoint;
+mod idw;
+mod products;
+mod spline;
+mod stream;
+mod synthetic;
+mod trees;
+mod triangulation;
+mod validate;
+mod verify;
+mod voxel;
+
+pub mod service;
+pub mod telemetry;
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    X,
+    Y,
+    Z,
+    Intensity,
+    GpsTime,
+    /// Flightline ID.
+    PointSourceId,
+    /// The LAS user data byte, e.g. a channel or sensor ID for per-channel rasters.
+    #[value(alias = "user_data")]
+    UserData,
+    /// LAS classification code, categorical: mode, min and max bands get a raster attribute
+    /// table of the class names.
+    Classification,
+    #[value(skip)]
+    ReturnNumber,
+    /// An extra-bytes dimension, e.g. 'extra:HeightAboveGround'.
+    #[value(skip)]
+    Extra(ExtraBytes),
+    /// Intensity of 16 bits, normalized to a range.
+    #[value(skip)]
+    ScaledIntensity(IntensityScale),
+}
+
+/// Range intensities are normalized to, from their detected bit depth.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum IntensityScale {
+    /// 0-255.
+    #[value(alias = "auto")]
+    Byte,
+    /// 0-1.
+    Unit,
+}
+
+impl Variable {
+    /// Resolves where extra-bytes dimensions are stored from the headers of the input.
+    fn resolve(self, headers: &[&las::Header]) -> Result<Self, Error> {
+        match self {
+            Variable::Extra(extra) => Ok(Variable::Extra(extra.resolve(headers)?)),
+            var => Ok(var),
+        }
+    }
+}
+
+fn variable_parser(s: &str) -> Result<Variable, String> {
+    match s.strip_prefix("extra:") {
+        Some(name) => Ok(Variable::Extra(ExtraBytes::named(name))),
+        None => Variable::from_str(s, true),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Function {
+    Mean,
+    /// Mean weighted by intensity, e.g. of Z for the radiometric centroid height of vegetation.
+    /// NODATA where every intensity is 0.
+    IntensityWeightedMean,
+    Median,
+
+    /// Percentile (0-100), linearly interpolated between points.
+    Percentile(f64),
+
+    Min,
+    Max,
+    /// Noise-robust minimum, the given percentile (0-100) rather than the absolute minimum.
+    MinPercentile(f64),
+
+    Count,
+    /// Max - min, optionally of points of a single classification only. The range of ground
+    /// points flags vegetation misclassified as ground.
+    Range(Option<u8>),
+    /// Most common value, e.g. the dominant flightline with `--var point-source-id`.
+    Mode,
+
+    /// Earliest GPS time.
+    TimeMin,
+    /// Latest GPS time.
+    TimeMax,
+    /// Latest - earliest GPS time, in seconds. Spans of days flag cells mixing data from
+    /// different sorties, e.g. after merging datasets.
+    TimeSpan,
+    /// Number of distinct flightlines (point source IDs).
+    Flightlines,
+    /// Fraction (0-1) of points with the cell's most common classification. Low values over
+    /// flightline overlaps point to unstable classification.
+    ClassAgreement,
+    /// Nominal point spacing, sqrt(cell area / first returns), as in the USGS lidar base
+    /// specification.
+    Spacing,
+}
+
+impl Function {
+    /// Whether the function picks the point with the highest value, or else the lowest, for
+    /// `--carry`. None for functions not picking a point.
+    fn picks_highest(&self) -> Option<bool> {
+        match self {
+            Function::Max | Function::TimeMax => Some(true),
+            Function::Min | Function::TimeMin => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Whether the function picks one of the binned values, so categorical values (e.g.
+    /// classification codes) stay categories.
+    fn is_categorical(&self) -> bool {
+        matches!(self, Function::Mode | Function::Min | Function::Max)
+    }
+
+    /// The variable a function operates on, if it overrides `--var`.
+    fn variable(&self) -> Option<Variable> {
+        match self {
+            Function::TimeMin | Function::TimeMax | Function::TimeSpan => Some(Variable::GpsTime),
+            Function::Flightlines => Some(Variable::PointSourceId),
+            Function::ClassAgreement => Some(Variable::Classification),
+            Function::Spacing => Some(Variable::ReturnNumber),
+            _ => None,
+        }
+    }
+}
+
+/// The bands of absolute elevations in the output, and of heights (e.g. a canopy height model),
+/// in band order.
+fn elevation_bands(
+    command: &Commands,
+    funcs: &[Function],
+    var: Variable,
+    carry: Option<Variable>,
+    carried: &[usize],
+) -> (Vec<usize>, Vec<usize>) {
+    match command {
+        Commands::Bin { .. } => {
+            let mut elevations = Vec::new();
+            let mut heights = Vec::new();
+
+            for (i, func) in funcs.iter().enumerate() {
+                match (func, func.variable().unwrap_or(var)) {
+                    (Function::Count, _) => {}
+                    (Function::Range(_), Variable::Z) => heights.push(i),
+                    (_, Variable::Z) => elevations.push(i),
+                    _ => {}
+                }
+            }
+            if carry == Some(Variable::Z) {
+                elevations.extend(funcs.len()..funcs.len() + carried.len());
+            }
+
+            (elevations, heights)
+        }
+        Commands::Triangulate { .. } | Commands::Spline { .. } | Commands::Idw { .. }
+            if var == Variable::Z =>
+        {
+            (vec![0], Vec::new())
+        }
+        Commands::TreeTops { .. } => (Vec::new(), vec![0]),
+        _ => (Vec::new(), Vec::new()),
+    }
+}
+
+fn function_parser(s: &str) -> Result<Function, String> {
+    let (name, arg) = match s.split_once(':') {
+        Some((name, arg)) => (name, Some(arg)),
+        None => (s, None),
+    };
+
+    let percentile = |arg: Option<&str>| -> Result<f64, String> {
+        let p: f64 = arg
+            .ok_or(format!("'{name}' requires a percentile, e.g. '{name}:1'"))?
+            .parse()
+            .map_err(|e: <f64 as FromStr>::Err| e.to_string())?;
+
+        match (0.0..=100.0).contains(&p) {
+            true => Ok(p),
+            false => Err(format!("Percentile {p} is outside of 0-100")),
+        }
+    };
+
+    match name {
+        "mean" => Ok(Function::Mean),
+        "intensity-weighted-mean" => Ok(Function::IntensityWeightedMean),
+        "median" => Ok(Function::Median),
+        "percentile" => Ok(Function::Percentile(percentile(arg)?)),
+        "min" => Ok(Function::Min),
+        "max" => Ok(Function::Max),
+        "min-percentile" => Ok(Function::MinPercentile(percentile(arg)?)),
+        "count" => Ok(Function::Count),
+        "range" => match arg {
+            Some(class) => Ok(Function::Range(Some(
+                class
+                    .parse()
+                    .map_err(|e: <u8 as FromStr>::Err| e.to_string())?,
+            ))),
+            None => Ok(Function::Range(None)),
+        },
+        "mode" => Ok(Function::Mode),
+        "time-min" => Ok(Function::TimeMin),
+        "time-max" => Ok(Function::TimeMax),
+        "time-span" => Ok(Function::TimeSpan),
+        "flightlines" => Ok(Function::Flightlines),
+        "class-agreement" => Ok(Function::ClassAgreement),
+        "spacing" => Ok(Function::Spacing),
+        _ => Err(format!("Unknown function '{s}'")),
+    }
+}
+
+fn fraction_parser(s: &str) -> Result<f64, String> {
+    let fraction: f64 = s
+        .parse()
+        .map_err(|e: <f64 as FromStr>::Err| e.to_string())?;
+
+    match fraction > 0.0 && fraction < 1.0 {
+        true => Ok(fraction),
+        false => Err(format!("{fraction} is not between 0 and 1")),
+    }
+}
+
+fn percent_parser(s: &str) -> Result<f64, String> {
+    let percent: f64 = s
+        .parse()
+        .map_err(|e: <f64 as FromStr>::Err| e.to_string())?;
+
+    match (0.0..50.0).contains(&percent) {
+        true => Ok(percent),
+        false => Err(format!("{percent} is not a percentage below 50")),
+    }
+}
+
+/// An `--extent`, possibly in another CRS than the point cloud's.
+#[derive(Clone)]
+struct Extent {
+    bounds: Bounds,
+    /// Definition of the CRS of `bounds`, e.g. EPSG:4326.
+    crs: Option<String>,
+}
+
+fn extent_parser(s: &str) -> Result<Extent, String> {
+    let (bounds, crs) = match s.split_once('@') {
+        Some((bounds, crs)) => (bounds, Some(crs.to_string())),
+        None => (s, None),
+    };
+
+    Ok(Extent {
+        bounds: bounds_parser(bounds)?,
+        crs,
+    })
+}
+
+fn bounds_parser(s: &str) -> Result<Bounds, String> {
+    let split: Vec<&str> = s.split(',').collect();
+
+    if !(split.len() == 6 || split.len() == 4) {
+        return Err(format!("'{s}' has an invalid number of coordinates"));
+    }
+
+    let use_z: bool = split.len() == 6;
+
+    fn parse(num: &str) -> Result<f64, String> {
+        num.parse()
+            .map_err(|e: <f64 as FromStr>::Err| e.to_string())
+    }
+
+    fn check_min_max(min: &[f64; 3], max: &[f64; 3]) -> Result<(), String> {
+        for i in 0..3 {
+            if min[i] > max[i] {
+                return Err(format!(
+                    "Invalid extent, {} is greater than {}",
+                    min[i], max[i]
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    let min_x = parse(split[0])?;
+    let min_y = parse(split[1])?;
+
+    let min_z = match use_z {
+        true => parse(split[2])?,
+        false => f64::MIN,
+    };
+
+    let max_x = match use_z {
+        true => parse(split[3]),
+        false => parse(split[2]),
+    }?;
+
+    let max_y = match use_z {
+        true => parse(split[4]),
+        false => parse(split[3]),
+    }?;
+
+    let max_z = match use_z {
+        true => parse(split[5])?,
+        false => f64::MAX,
+    };
+
+    let min: [f64; 3] = [min_x, min_y, min_z];
+
+    let max: [f64; 3] = [max_x, max_y, max_z];
+
+    check_min_max(&min, &max)?;
+
+    Ok(Bounds {
+        min: Vector {
+            x: min[0],
+            y: min[1],
+            z: min[2],
+        },
+        max: Vector {
+            x: max[0],
+            y: max[1],
+            z: max[2],
+        },
+    })
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Use raw point cloud values via binning.
+    Bin {
+        /// Binning function [mean, intensity-weighted-mean, median, percentile:<p>, min, max,
+        /// min-percentile:<p>, count, range[:<class>], mode, time-min, time-max, time-span,
+        /// flightlines, class-agreement, spacing].
+        /// Repeat or comma separate for one band per function.
+        /// Default: median
+        #[arg(short, long, value_parser = function_parser, value_delimiter = ',')]
+        func: Vec<Function>,
+
+        /// Weight points by 1 / number of returns in mean (also intensity-weighted), median and
+        /// percentile functions.
+        #[arg(long)]
+        weight_by_returns: bool,
+
+        /// Breaklines as a vector file. Points on the opposite side of a breakline from the cell
+        /// center are left out of that cell, preserving hard edges.
+        #[arg(long)]
+        breaklines: Option<PathBuf>,
+
+        /// Cells whose points all have a scan angle of at least this many degrees, i.e. cells
+        /// only covered by swath edges, are set to NODATA.
+        #[arg(long)]
+        mask_swath_edges: Option<f64>,
+
+        /// Flag swath edge cells in an extra 'SwathEdge' band (1 for swath edges, 0 otherwise)
+        /// rather than setting them to NODATA.
+        #[arg(long, requires = "mask_swath_edges")]
+        swath_edge_band: bool,
+
+        /// Aggregate every point within this distance of each cell's center, rather than the
+        /// points within the cell, for smooth point-based focal statistics (e.g. a focal mean,
+        /// p95 or count).
+        #[arg(long)]
+        focal_radius: Option<f64>,
+
+        /// Variable of the point each min or max function picks to carry along, in a band after
+        /// the functions' bands, e.g. '--func max --carry intensity' for the intensity of the
+        /// highest return [see --var].
+        #[arg(long, value_parser = variable_parser)]
+        carry: Option<Variable>,
+
+        /// Cells with fewer points than this are set to NODATA, rather than a statistically
+        /// meaningless value of one or two points.
+        /// Default: 1
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        min_count: Option<u64>,
+    },
+    Triangulate {
+        /// Triangles past the buffer will be 'frozen' if all three edges are less than this
+        /// distance, blocking any points below from the triangulation.
+        #[arg(short, long, required_unless_present = "load_tin")]
+        freeze_distance: Option<f64>,
+
+        /// The insertion_buffer will block triangles from freezing for a time blocking any
+        /// premature freezing. Either a constant or a schedule [start..end] which changes
+        /// linearly as points are inserted, e.g. 3.0..0.5
+        #[arg(short, long, value_parser = schedule_parser, required_unless_present = "load_tin")]
+        insertion_buffer: Option<Schedule>,
+
+        /// Pixels inside triangles with any edge longer than this distance are set to NODATA.
+        #[arg(long)]
+        max_edge: Option<f64>,
+
+        /// Pixels inside triangles with an area larger than this are set to NODATA. Catches
+        /// elongated slivers along flightline edges which pass the edge length test.
+        #[arg(long)]
+        max_area: Option<f64>,
+
+        /// Surveyed control points inserted as mandatory vertices, as a vector file with an
+        /// optional numeric Z field [path[:z_field]]. Default Z: the geometry's Z.
+        #[arg(long, value_parser = path_field_parser)]
+        seed_points: Option<(PathBuf, Option<String>)>,
+
+        /// Insert line features from --seed-points as constraint edges (e.g. breaklines).
+        #[arg(long, requires = "seed_points")]
+        seed_constraints: bool,
+
+        /// Stop inserting points once the triangulation reaches this many vertices, bounding
+        /// memory and time for very large clouds. Points are inserted highest first.
+        #[arg(long)]
+        max_vertices: Option<usize>,
+
+        /// Greedy insertion, only inserting points deviating more than this vertically from the
+        /// current surface. Produces compact triangulations within a vertical error bound.
+        #[arg(long)]
+        tin_error: Option<f64>,
+
+        /// Points sorted in memory at a time. Larger clouds are sorted in chunks spilled to
+        /// temporary files and merged, bounding memory to the chunk and the inserted vertices
+        /// (see --max-vertices). Default: all points.
+        #[arg(long)]
+        chunk_size: Option<usize>,
+
+        /// Thin the points to one per cubic voxel of this size before triangulating, for
+        /// better-behaved triangulations than random thinning and predictable memory. The
+        /// voxels are held in memory until thinned, one point each (every point with
+        /// '--voxel-keep centroid'), so --chunk-size doesn't bound memory then.
+        #[arg(long)]
+        voxel_thin: Option<f64>,
+
+        /// Point kept per voxel of --voxel-thin. Default: highest
+        #[arg(long, requires = "voxel_thin")]
+        voxel_keep: Option<VoxelKeep>,
+
+        /// Save the triangulation, to rasterize it again with --load-tin.
+        #[arg(long)]
+        save_tin: Option<PathBuf>,
+
+        /// Rasterize a triangulation saved with --save-tin instead of triangulating the input,
+        /// e.g. at another resolution or extent. Needs no input, the extent defaults to the
+        /// triangulation's.
+        #[arg(long, conflicts_with = "save_tin")]
+        load_tin: Option<PathBuf>,
+
+        /// Checkpoint the run next to the output (<output>.checkpoint), saving the triangulation
+        /// once built and then the rows written, and carry on from the checkpoint of an
+        /// interrupted run with the same arguments, if there is one. A run interrupted while
+        /// reading and triangulating the points starts over.
+        #[arg(long, conflicts_with = "load_tin")]
+        resume: bool,
+    },
+    /// Minimum curvature gridding in tension (as GMT surface), for smooth surfaces from sparse
+    /// data such as bathymetric soundings or ground points, where triangles look angular.
+    Spline {
+        /// Tension (0-1). 0 gives the smoothest surface, which may overshoot between data, higher
+        /// values follow the data more tightly. 0.25 suits most topography, 0.35 steep terrain.
+        /// Default: 0.25
+        #[arg(short, long)]
+        tension: Option<f64>,
+
+        /// Maximum number of iterations. Default: 500
+        #[arg(long)]
+        max_iterations: Option<usize>,
+
+        /// Stop iterating once no cell changes by more than this. Default: 0.001
+        #[arg(long)]
+        convergence: Option<f64>,
+
+        /// Cells further than this distance from any data are set to NODATA.
+        #[arg(long)]
+        max_distance: Option<f64>,
+    },
+    /// Inverse distance weighted interpolation of cell centers, optionally anisotropic so linear
+    /// features (e.g. beach profiles or channels) interpolate along their orientation.
+    Idw {
+        /// Search radius, along the major axis with --ratio.
+        #[arg(long)]
+        radius: f64,
+
+        /// Power of the inverse distance weights. Default: 2
+        #[arg(short, long)]
+        power: Option<f64>,
+
+        /// Direction of the major axis of anisotropy, in degrees clockwise from north.
+        /// Default: 0
+        #[arg(short, long)]
+        azimuth: Option<f64>,
+
+        /// Anisotropy ratio (major / minor axis, at least 1). Distances across the major axis
+        /// weigh this many times more than along it. Default: 1
+        #[arg(long)]
+        ratio: Option<f64>,
+    },
+    /// Detect tree tops as local maxima of a canopy height model, which is written as the output
+    /// raster.
+    TreeTops {
+        /// Vector file for the detected tree tops (x, y, height).
+        #[arg(short, long)]
+        tree_tops: PathBuf,
+
+        /// Classification code of ground points used for the terrain model. Default: 2
+        #[arg(short, long)]
+        ground_class: Option<u8>,
+
+        /// Minimum canopy height of a tree top. Default: 2.0
+        #[arg(short, long)]
+        min_height: Option<f64>,
+
+        /// Local maxima window size as a function of canopy height [base[,slope]]; the window
+        /// diameter is base + slope * height. Default: 3.0
+        #[arg(short, long, value_parser = window_parser)]
+        window: Option<Window>,
+
+        /// Raster of crown IDs from marker-controlled watershed segmentation of the canopy
+        /// height model, seeded by the tree tops.
+        #[arg(long)]
+        crowns: Option<PathBuf>,
+
+        /// Crown polygons (vector file) from the watershed segmentation.
+        #[arg(long)]
+        crown_polygons: Option<PathBuf>,
+    },
+    /// Build several products (e.g. DTM, DSM, density, intensity), each with its own output,
+    /// method, variable and class, from a single read of the input. Products with the same
+    /// triangulation share it. Needs no output path.
+    Products {
+        /// TOML manifest of [[product]] tables with an `output` and optionally a `method` (bin or
+        /// triangulate), `var`, `class` and the options of the method's subcommand, e.g. `func`.
+        manifest: PathBuf,
+    },
+    /// Benchmark binning and triangulation on a synthetic point cloud at --res (default: 1), to
+    /// compare machines and settings before large jobs. Needs no input or output.
+    Bench {
+        /// Approximate number of synthetic points. Default: 1000000
+        #[arg(short, long)]
+        points: Option<u64>,
+    },
+    /// Self-test of the GDAL build: rasterizes points at known locations, reads them back out
+    /// through GDAL and checks they land within half a pixel, with the right values. Written to
+    /// the output path if given, to test its driver, and removed afterwards. Needs no input.
+    Verify,
+    /// Write a LASindex (`.lax`) spatial index next to each local LAS/LAZ input, compatible with
+    /// LAStools' lasindex. Later runs with an --extent only read the points within it. Needs no
+    /// output.
+    Index {
+        /// Side length of the finest quadtree cells, in map units. Default: 10
+        #[arg(long)]
+        cell_size: Option<f64>,
+    },
+    /// Check each local LAS/LAZ input for truncated point records, point counts disagreeing with
+    /// the header, coordinates outside of the header bounds and corrupt LAZ chunks. A JSON
+    /// summary is written to the output path, or stdout. Exits with 4 if any input has problems.
+    Validate,
+    /// Write a synthetic LAS of terrain with buildings and trees to the output path, for demos
+    /// and trying out parameters. Needs no input.
+    Generate {
+        /// Side length of the square scene in map units. Default: 500
+        #[arg(long)]
+        size: Option<f64>,
+
+        /// Laser pulses per square map unit. Default: 10
+        #[arg(short, long)]
+        density: Option<f64>,
+
+        /// Standard deviation of the vertical noise. Default: 0.05
+        #[arg(long)]
+        noise: Option<f64>,
+
+        /// Number of buildings. Default: 10
+        #[arg(short, long)]
+        buildings: Option<usize>,
+
+        /// Number of trees. Default: 200
+        #[arg(short, long)]
+        trees: Option<usize>,
+
+        /// Random seed, the same seed gives the same cloud. Default: 0
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Bin a live feed of text points (see --csv-columns) from stdin or UDP into --extent,
+    /// rewriting the output as points arrive, e.g. for monitoring a survey in progress.
+    Stream {
+        /// Binning function, as with bin, of those kept up to date point by point: mean, min,
+        /// max, count, range (of all classes) and the time functions. Default: max
+        #[arg(short, long, value_parser = function_parser, value_delimiter = ',')]
+        func: Vec<Function>,
+
+        /// UDP address to listen on for datagrams of one or more lines of points, e.g.
+        /// 0.0.0.0:5600. Default: read stdin until it ends
+        #[arg(long)]
+        listen: Option<SocketAddr>,
+
+        /// Seconds between updates of the output. Default: 5
+        #[arg(long)]
+        flush_every: Option<f64>,
+    },
+}
+
+#[derive(Parser)]
+#[command(version, about = "Generates a raster from a las/laz file", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+
+    /// Path to las/laz file, a directory of them, a file name pattern (e.g. 'tiles/*.laz') or an
+    /// EPT dataset's ept.json. '-' reads a LAS/LAZ stream from stdin, e.g. piped from pdal, and
+    /// http(s):// and s3:// URLs (credentials from AWS_* variables) are read with range requests,
+    /// only fetching the parts of COPC files within --extent.
+    /// Text point files (.xyz, .csv, .txt, .pts) are read with --csv-columns, PLY and PCD
+    /// files' vertices/points as points. These are read whole into memory, briefly twice over
+    /// while they're converted to LAS.
+    /// Repeat for several, all files (e.g. tiles) are merged into a single raster.
+    #[arg(short, long)]
+    input: Vec<PathBuf>,
+
+    /// Columns (from 1) of the variables of text inputs, e.g. 'x=1,y=2,z=3,intensity=4'.
+    /// Fields are separated by commas, semicolons or whitespace. Default: x=1,y=2,z=3
+    #[arg(long, value_parser = text_columns_parser)]
+    csv_columns: Option<TextColumns>,
+
+    /// Resolution of the outut raster.
+    #[arg(short, long)]
+    res: Option<f64>,
+
+    /// Optional LAS classification code filter [see LAS specification: https://www.asprs.org/wp-content/uploads/2019/03/LAS_1_4_r14.pdf#page=22]
+    /// A comma list of codes and ranges, e.g. '2,6,9-11'. Equivalent to a first
+    /// `--filter class=<codes>`.
+    #[arg(short, long, value_parser = class_set_parser)]
+    class: Option<ClassSet>,
+
+    /// Returns to keep, e.g. first returns for a DSM or last returns for terrain. Equivalent to
+    /// a `--filter returns=<returns>` after --class. Default: all
+    #[arg(long)]
+    returns: Option<Returns>,
+
+    /// Drop points below this elevation, whatever the extent. Equivalent to a
+    /// `--filter zrange=<min>:<max>` after --returns.
+    #[arg(long)]
+    zmin: Option<f64>,
+
+    /// Drop points above this elevation, whatever the extent.
+    #[arg(long)]
+    zmax: Option<f64>,
+
+    /// Drop points with an intensity below this, e.g. low-intensity atmospheric returns.
+    /// Equivalent to a `--filter intensity=<min>:<max>` after --zmin/--zmax. Default: 0
+    #[arg(long)]
+    intensity_min: Option<u16>,
+
+    /// Drop points with an intensity above this. Default: 65535
+    #[arg(long)]
+    intensity_max: Option<u16>,
+
+    /// Drop points scanned further than this many degrees off nadir, which are noisier and
+    /// geometrically weaker. Equivalent to a `--filter scan-angle=<max>` after
+    /// --intensity-min/--intensity-max.
+    #[arg(long)]
+    max_scan_angle: Option<f64>,
+
+    /// Drop points with a GPS time before this, e.g. to rasterize a single flight pass. Points
+    /// without a GPS time are dropped too. Equivalent to a `--filter gps-time=<min>:<max>` after
+    /// --max-scan-angle.
+    #[arg(long)]
+    gps_time_min: Option<f64>,
+
+    /// Drop points with a GPS time after this.
+    #[arg(long)]
+    gps_time_max: Option<f64>,
+
+    /// Point source IDs (flightlines) to keep, as a comma list of IDs and ranges, e.g. 103,104
+    /// for per-strip QA rasters. Equivalent to a `--filter point-source=<ids>` after
+    /// --gps-time-min/--gps-time-max.
+    #[arg(long, value_parser = source_set_parser)]
+    point_source: Option<SourceSet>,
+
+    /// User data values to keep, as a comma list of values and ranges, e.g. 5 for the points of
+    /// one channel where vendors store channel or sensor IDs there. Equivalent to a
+    /// `--filter user-data=<values>` after --point-source.
+    #[arg(long, value_parser = user_data_parser)]
+    user_data: Option<ClassSet>,
+
+    /// Drop points with an NDVI, (NIR - red) / (NIR + red), below this, e.g. 0.3 to keep
+    /// vegetation. Points without NIR and color (point formats other than 8 and 10) are dropped
+    /// too. Equivalent to a `--filter ndvi=<min>:<max>` after --user-data.
+    #[arg(long)]
+    ndvi_min: Option<f64>,
+
+    /// Drop points with an NDVI above this, e.g. 0.1 to keep bare ground and buildings.
+    #[arg(long)]
+    ndvi_max: Option<f64>,
+
+    /// Keep withheld points. They're dropped by default, ahead of any other filter, as the LAS
+    /// specification marks them deleted.
+    #[arg(long)]
+    keep_withheld: bool,
+
+    /// Keep high noise points (class 18) in triangulations, by triangulate and the triangulated
+    /// products of products. They're dropped by default there, after withheld points, as a
+    /// single spike distorts the surface around it. Binned outputs keep them either way.
+    #[arg(long)]
+    keep_noise: bool,
+
+    /// Classes to drop, as a comma list of codes and ranges, e.g. '7,18' for low and high noise.
+    /// Equivalent to a `--filter drop-class=<codes>` after --ndvi-min/--ndvi-max.
+    #[arg(long, value_parser = class_set_parser)]
+    drop_class: Option<ClassSet>,
+
+    /// Drop points flagged (or in point formats 0-5, classified) as flightline overlap.
+    /// Equivalent to a `--filter drop=overlap` after --drop-class.
+    #[arg(long)]
+    drop_overlap: bool,
+
+    /// Drop synthetic points, e.g. digitized rather than scanned. Equivalent to a
+    /// `--filter drop=synthetic` after --drop-overlap.
+    #[arg(long)]
+    drop_synthetic: bool,
+
+    /// Drop model key points. Equivalent to a `--filter drop=keypoint` after --drop-synthetic.
+    #[arg(long)]
+    drop_keypoints: bool,
+
+    /// Point filter, applied in the order given, in every command. Repeatable
+    /// [class=<codes>, zrange=<min>:<max>, intensity=<min>:<max>,
+    /// returns=<first|last|intermediate|single|all>, scan-angle=<max>, gps-time=<min>:<max>,
+    /// point-source=<ids>, user-data=<values>, ndvi=<min>:<max>, drop-class=<codes>,
+    /// drop=<withheld|synthetic|overlap|keypoint>, where=<expression>],
+    /// e.g. '--filter class=2,9 --filter zrange=0:500'.
+    #[arg(long = "filter", value_parser = filter_parser)]
+    filters: Vec<Filter>,
+
+    /// Keep points an expression holds for, over the point dimensions [X, Y, Z, Intensity,
+    /// ReturnNumber, NumberOfReturns, Classification, ScanAngle, UserData, PointSourceId,
+    /// GpsTime, ScanDirectionFlag, EdgeOfFlightLine, Withheld, Synthetic, KeyPoint, Overlap,
+    /// Red, Green, Blue, Infrared], with ==, !=, <, <=, >, >=, &&, ||, !, +, -, *, / and
+    /// parentheses, e.g. 'Classification == 2 && Intensity > 100 && ReturnNumber ==
+    /// NumberOfReturns'. Equivalent to a `--filter where=<expression>` after --filter.
+    /// Repeatable
+    #[arg(long = "where", value_parser = expr_parser)]
+    wheres: Vec<Expr>,
+
+    /// Filter chain and exclusion polygons saved with --save-profile, applied before any
+    /// other filters given.
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Save the filter chain (--profile, --class, --returns, --zmin/max, --intensity-min/max,
+    /// --max-scan-angle, --gps-time-min/max, --point-source, --user-data, --ndvi-min/max,
+    /// --drop-class, --drop-*, --filter, --where) and
+    /// --exclude-polygons under a name, in the user's config directory, for reuse with --profile.
+    #[arg(long)]
+    save_profile: Option<String>,
+
+    /// Polygons (vector file) whose points are dropped before rasterization, e.g. water bodies,
+    /// stockpiles or vehicles, keeping the rest. Cells entirely inside are written as NODATA.
+    #[arg(long, visible_alias = "mask-out")]
+    exclude_polygons: Option<PathBuf>,
+
+    /// Polygons (vector file, e.g. GeoJSON, GeoPackage or Shapefile) outside of which points are
+    /// dropped, in the output's CRS. Cells outside are written as NODATA, and the extent
+    /// defaults to the input's bounds within the polygons'.
+    #[arg(long)]
+    clip: Option<PathBuf>,
+
+    /// Variable to rasterize [x, y, z, intensity, gps-time, point-source-id, user-data,
+    /// classification, extra:<name>], extra:<name> being a LAS 1.4 extra-bytes dimension. Default: z
+    #[arg(short, long, value_parser = variable_parser)]
+    var: Option<Variable>,
+
+    /// Extent of the output raster, optionally in another CRS, transformed to the point cloud's,
+    /// e.g. '10.1,59.9,10.2,60.0@EPSG:4326'. Default: bounds of the source las/laz
+    /// [min x, y, z, max x, y, z[@crs]]
+    #[arg(short, long, value_parser = extent_parser)]
+    extent: Option<Extent>,
+
+    /// Copy the extent and resolution of an existing raster, from its geotransform, e.g. to
+    /// align with imagery. It has to be in the output's CRS, with square pixels. --res overrides
+    /// its resolution.
+    #[arg(long, conflicts_with_all = ["extent", "aoi", "each_tile"])]
+    extent_like: Option<PathBuf>,
+
+    /// Rasterize each feature's extent of a vector file separately, to the output path with the
+    /// feature's value of a field appended (default: its FID), e.g. 'parcels.gpkg:name' writes
+    /// dem_<name>.tif for each parcel. The features have to be in the output's CRS.
+    #[arg(long, value_parser = path_field_parser, conflicts_with = "extent")]
+    aoi: Option<(PathBuf, Option<String>)>,
+
+    /// Rasterize each input tile separately, to the output path with the tile's file name
+    /// appended, e.g. dem_<tile>.tif. Combine with --tile-buffer for seamless tiles.
+    #[arg(long, conflicts_with_all = ["aoi", "extent"])]
+    each_tile: bool,
+
+    /// Read points up to this distance around the extent, from every input, so interpolation
+    /// near the edges of tiles (--each-tile), areas of interest (--aoi) or the --extent isn't
+    /// distorted. The output is clipped back to the extent. Points further out are skipped.
+    #[arg(long)]
+    tile_buffer: Option<f64>,
+
+    /// CRS of the input point cloud (e.g. EPSG:25833, WKT or a PROJ string), overriding any in
+    /// its header. The output is written in it. Default: the CRS of the first input with a WKT
+    /// CRS VLR
+    #[arg(long)]
+    a_srs: Option<String>,
+
+    /// Detect whether each input's intensities use 8, 12 or 16 bits and normalize them to
+    /// 0-255 (byte, or auto) or 0-1 (unit), so mixed-vendor mosaics are comparable. Products
+    /// and filters see 16 bit intensities.
+    #[arg(long)]
+    intensity_scale: Option<IntensityScale>,
+
+    /// Match each input's intensity histogram to that of a reference input (by path or file
+    /// name) or of every input pooled ('ensemble'), removing seams in multi-file intensity
+    /// mosaics.
+    #[arg(long, value_parser = harmonize_parser)]
+    harmonize_intensity: Option<Harmonize>,
+
+    /// Reproject the points' XY to this CRS before rasterizing, producing the output in it.
+    /// --extent is then in this CRS.
+    #[arg(long)]
+    t_srs: Option<String>,
+
+    /// Shrink the extent to the <pct> to 100 - <pct> percentiles of the points' XY, from a
+    /// sample of the input, so a handful of outlier coordinates don't produce a huge, mostly
+    /// empty raster, e.g. '--trim-extent 1'.
+    #[arg(long, value_parser = percent_parser)]
+    trim_extent: Option<f64>,
+
+    /// Specific NODATA value. Default: -9999.0
+    #[arg(short, long)]
+    nodata: Option<f64>,
+
+    /// Number of undecodable points (e.g. from a truncated LAZ download) to skip, along with the
+    /// rest of their LAZ chunk, before aborting. Default: 0
+    #[arg(long)]
+    skip_corrupt_points: Option<u64>,
+
+    /// Cache an index of where the points of each input file are (<file>.lrx) on the first run,
+    /// so later runs with an --extent only read the parts of the file within it.
+    #[arg(long)]
+    index_cache: bool,
+
+    /// How NODATA combines over the bands of multiband outputs: a pixel is NODATA in every band
+    /// if any band lacks data, only if all do, or per band. Default: per-band
+    #[arg(long)]
+    nodata_policy: Option<NodataPolicy>,
+
+    /// Add an 'Outlier' band flagging pixels of the first band which deviate more than this many
+    /// median absolute deviations from the median of their 3x3 neighbourhood (1 for outliers, 0
+    /// otherwise), as an overlay of suspicious pixels for review.
+    #[arg(long)]
+    outlier_band: Option<f64>,
+
+    /// Fill the NODATA pixels the method leaves (e.g. outside of the TIN or past --max-edge)
+    /// from the pixels with data within a radius, <method>:<radius> with idw (inverse distance
+    /// weighted) or nearest. Repeat or comma separate for a chain, each fallback filling what
+    /// the previous ones left, e.g. '--fallback idw:10,nearest:50'. See --provenance for the
+    /// pixels each filled.
+    #[arg(long, value_parser = fallback_parser, value_delimiter = ',')]
+    fallback: Vec<Fallback>,
+
+    /// Write the method of each pixel of the first band to a byte raster at this path, to weight
+    /// or mask pixels by provenance [0: NODATA, 1: bin, 2: TIN, 3: IDW, 4: spline, 5: idw
+    /// --fallback, 6: nearest --fallback], with a raster attribute table naming them.
+    #[arg(long)]
+    provenance: Option<PathBuf>,
+
+    /// Write the point accounting (points read, filtered by each criterion, used and skipped)
+    /// as JSON next to the output, to <output>.report.json.
+    #[arg(long)]
+    report: bool,
+
+    /// Fail on points outside of the raster extent instead of skipping them.
+    #[arg(long)]
+    strict: bool,
+
+    /// Write a coarse version of the raster first, then refine it tile by tile in place, so the
+    /// output can be opened early during long runs.
+    #[arg(long)]
+    progressive: bool,
+
+    /// Output compression. Compression runs on a separate thread, overlapping with the
+    /// interpolation of the next block.
+    #[arg(long)]
+    compress: Option<Compression>,
+
+    /// Exit with status 3 instead of writing an all-NODATA raster when no points remain within
+    /// the extent.
+    #[arg(long)]
+    fail_empty: bool,
+
+    /// Withhold this fraction (0-1) of the points from rasterization and report the RMSE, MAE
+    /// and bias of the raster (its first band) at them, to compare methods and settings.
+    #[arg(long, value_parser = fraction_parser)]
+    cross_validate: Option<f64>,
+
+    /// Keep only every Nth point, before rasterization, e.g. for quick looks at a coarse
+    /// resolution.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(2..), conflicts_with = "thin_fraction")]
+    thin_every: Option<u64>,
+
+    /// Keep only a random fraction (0-1) of the points, before rasterization.
+    #[arg(long, value_parser = fraction_parser)]
+    thin_fraction: Option<f64>,
+
+    /// Random seed of --thin-fraction, the same seed keeps the same points. Default: 0
+    #[arg(long, requires = "thin_fraction")]
+    seed: Option<u64>,
+
+    /// Remove statistical outliers (<k>,<stddev>) before rasterization: points whose mean
+    /// distance to their <k> nearest neighbours is more than <stddev> standard deviations above
+    /// the mean, e.g. birds and noise spiking min/max surfaces. Reads the input twice, holding
+    /// every point's position in memory. e.g. '--sor 8,2'
+    #[arg(long, value_parser = sor_parser)]
+    sor: Option<Sor>,
+
+    /// Remove isolated points (<radius>,<min>) before rasterization: points with fewer than
+    /// <min> other points within <radius>, e.g. noise spikes dominating max rasters. Cheaper
+    /// than --sor, also reading the input twice. e.g. '--radius-filter 1,3'
+    #[arg(long, value_parser = radius_filter_parser)]
+    radius_filter: Option<RadiusFilter>,
+
+    /// Proceed even if the extent, resolution and Z range look like a unit mismatch.
+    #[arg(long)]
+    force: bool,
+
+    /// Output raster path
+    output: Option<PathBuf>,
+
+    /// The command line as given, the run's identity for checkpoints and its recorded history.
+    #[arg(skip)]
+    invocation: Vec<OsString>,
+}
+
+impl Cli {
+    /// Parses the command line `args`, the first being the program name, keeping them.
+    fn parse_args<I, T>(args: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        let invocation: Vec<OsString> = args.into_iter().map(Into::into).collect();
+
+        let mut cli = Cli::try_parse_from(invocation.iter().cloned())?;
+        cli.invocation = invocation;
+
+        Ok(cli)
+    }
+
+    /// The command line, with any non UTF-8 arguments lossily converted.
+    fn command_line(&self) -> String {
+        self.invocation
+            .iter()
+            .map(|arg| arg.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Values of a point, for the point representations of the different inputs.
+trait PointValues {
+    /// The point's value of `var`, NaN if it has none.
+    fn value(&self, var: &Variable) -> f64;
+}
+
+impl PointValues for Point {
+    fn value(&self, var: &Variable) -> f64 {
+        match *var {
+            Variable::X => self.x,
+            Variable::Y => self.y,
+            Variable::Z => self.z,
+            Variable::Intensity => self.intensity as f64,
+            Variable::GpsTime => self.gps_time.unwrap_or(f64::NAN),
+            Variable::PointSourceId => self.point_source_id as f64,
+            Variable::UserData => self.user_data as f64,
+            Variable::Classification => u8::from(self.classification) as f64,
+            Variable::ReturnNumber => self.return_number as f64,
+            Variable::Extra(extra) => extra.value(&self.extra_bytes),
+            Variable::ScaledIntensity(scale) => {
+                let max = match scale {
+                    IntensityScale::Byte => 255.0,
+                    IntensityScale::Unit => 1.0,
+                };
+
+                self.intensity as f64 / u16::MAX as f64 * max
+            }
+        }
+    }
+}
+
+fn get_var<P: PointValues>(var: &Variable, point: &P) -> f64 {
+    point.value(var)
+}
+
+pub const NODATA: f64 = -9999.0;
+
+/// Points sampled for `--trim-extent`.
+const TRIM_SAMPLES: u64 = 100_000;
+
+/// ASPRS class of high noise points, dropped when triangulating unless `--keep-noise`.
+const HIGH_NOISE: u8 = 18;
+
+/// The filter dropping high noise points from triangulations.
+fn high_noise() -> Filter {
+    Filter::DropClass(ClassSet::new(vec![HIGH_NOISE]))
+}
+
+/// Runs the command line `args`, the first being the program name.
+pub fn run<I, T>(args: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    execute(Cli::parse_args(args)?, None)
+}
+
+fn execute(mut cli: Cli, observer: Option<&Observer>) -> Result<(), Error> {
+    match &cli.command {
+        Commands::Bench { points } => {
+            return bench::run(points.unwrap_or(1_000_000), cli.res.unwrap_or(1.0));
+        }
+        Commands::Verify => {
+            return verify::run(
+                &cli.output
+                    .clone()
+                    .unwrap_or(env::temp_dir().join("las-rasterizer-verify.tif")),
+            );
+        }
+        Commands::Index { cell_size } => {
+            if cli.input.is_empty() {
+                return Err(Cli::command()
+                    .error(ErrorKind::MissingRequiredArgument, "--input is required")
+                    .into());
+            }
+
+            for path in expand_paths(&cli.input)? {
+                if is_url(&path) || !is_point_cloud(&path) {
+                    warn!(
+                        "Not indexing {}, only local LAS/LAZ files are",
+                        path.display()
+                    );
+                    continue;
+                }
+
+                write_lax(&path, cell_size.unwrap_or(10.0))?;
+            }
+
+            info!("Done!");
+            return Ok(());
+        }
+        Commands::Validate => {
+            if cli.input.is_empty() {
+                return Err(Cli::command()
+                    .error(ErrorKind::MissingRequiredArgument, "--input is required")
+                    .into());
+            }
+
+            let mut validations = Vec::new();
+            for path in expand_paths(&cli.input)? {
+                if is_url(&path) || !is_point_cloud(&path) {
+                    warn!(
+                        "Not validating {}, only local LAS/LAZ files are",
+                        path.display()
+                    );
+                    continue;
+                }
+
+                validations.push(validate(&path)?);
+            }
+
+            let json = serde_json::to_string_pretty(&validations)
+                .map_err(|e| Error::ShouldntHappen(e.to_string()))?;
+            match &cli.output {
+                Some(output) => fs::write(output, json)?,
+                None => println!("{json}"),
+            }
+
+            let invalid = validations.iter().filter(|v| !v.valid).count();
+            if invalid > 0 {
+                return Err(Error::Invalid(format!(
+                    "{invalid} of {} inputs have problems",
+                    validations.len()
+                )));
+            }
+
+            info!("All {} inputs are valid", validations.len());
+            return Ok(());
+        }
+        Commands::Generate {
+            size,
+            density,
+            noise,
+            buildings,
+            trees,
+            seed,
+        } => {
+            let Some(output) = &cli.output else {
+                return Err(Cli::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        "the output path is required",
+                    )
+                    .into());
+            };
+
+            let scene = Scene {
+                size: size.unwrap_or(500.0),
+                density: density.unwrap_or(10.0),
+                noise: noise.unwrap_or(0.05),
+                buildings: buildings.unwrap_or(10),
+                trees: trees.unwrap_or(200),
+                seed: seed.unwrap_or(0),
+            };
+
+            info!(
+                "Generating {} pulses over {}x{}...",
+                scene.num_pulses(),
+                scene.size,
+                scene.size
+            );
+            scene.write(BufWriter::new(File::create(output)?))?;
+
+            info!("Done!");
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if let Some(path) = &cli.extent_like {
+        let (bounds, res) = raster_extent(path)?;
+        info!(
+            "Extent of {}: {}, {} - {}, {} at {res}",
+            path.display(),
+            bounds.min.x,
+            bounds.min.y,
+            bounds.max.x,
+            bounds.max.y
+        );
+
+        cli.extent = Some(Extent { bounds, crs: None });
+        cli.res = cli.res.or(Some(res));
+    }
+
+    // The input's CRS, only looked up if something is transformed.
+    let transforms =
+        cli.t_srs.is_some() || matches!(&cli.extent, Some(Extent { crs: Some(_), .. }));
+    let input_crs = match (&cli.a_srs, transforms) {
+        (Some(definition), _) => Some(definition.clone()),
+        (None, true) => peek_wkt(&cli.input)?,
+        (None, false) => None,
+    };
+
+    let extent = match &cli.extent {
+        Some(Extent {
+            bounds,
+            crs: Some(crs),
+        }) => {
+            let output_crs =
+                cli.t_srs
+                    .as_ref()
+                    .or(input_crs.as_ref())
+                    .ok_or(Error::Crs(format!(
+                        "the extent is in {crs}, but the input has no CRS to transform it to"
+                    )))?;
+            let bounds = transform_bounds(bounds, &spatial_ref(crs)?, &spatial_ref(output_crs)?)?;
+
+            info!(
+                "Extent in the output's CRS: {}, {} - {}, {}",
+                bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y
+            );
+            Some(bounds)
+        }
+        Some(extent) => Some(extent.bounds),
+        None => None,
+    };
+
+    if let Commands::Stream {
+        func,
+        listen,
+        flush_every,
+    } = &cli.command
+    {
+        let (Some(output), Some(bounds), Some(res)) = (&cli.output, extent, cli.res) else {
+            return Err(Cli::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "the output path, --extent and --res are required",
+                )
+                .into());
+        };
+        let flush_every = flush_every.unwrap_or(5.0);
+        if !flush_every.is_finite() || flush_every <= 0.0 {
+            return Err(Cli::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    "--flush-every has to be a positive number of seconds",
+                )
+                .into());
+        }
+        if cli.t_srs.is_some() {
+            return Err(Error::Unsupported(
+                "--t-srs with stream, streamed points aren't reprojected".to_string(),
+            ));
+        }
+        if cli.sor.is_some() || cli.radius_filter.is_some() {
+            return Err(Error::Unsupported(
+                "--sor or --radius-filter with stream, streamed points are binned as they arrive"
+                    .to_string(),
+            ));
+        }
+
+        let crs = match &cli.a_srs {
+            Some(definition) => Some(spatial_ref(definition)?.to_wkt()?),
+            None => None,
+        };
+        let filter = point_filter(&cli)?;
+        let mut report = Report::for_filter(&filter);
+
+        stream(
+            output,
+            bounds,
+            res,
+            &filter,
+            cli.var.unwrap_or(Variable::Z),
+            match func.is_empty() {
+                true => &[Function::Max],
+                false => func.as_slice(),
+            },
+            &StreamParams {
+                listen: *listen,
+                columns: &cli.csv_columns.clone().unwrap_or_default(),
+                flush_every: Duration::from_secs_f64(flush_every),
+                nodata: cli.nodata.unwrap_or(NODATA),
+                compress: cli.compress,
+                info: RasterInfo {
+                    crs: crs.as_deref(),
+                    history: Some(&cli.command_line()),
+                    units: &[],
+                },
+                strict: cli.strict,
+            },
+            &mut report,
+        )?;
+
+        report.log();
+        info!("Done!");
+        return Ok(());
+    }
+
+    let areas = match (&cli.aoi, cli.each_tile) {
+        (Some((path, field)), _) => read_named_extents(path, field.as_deref())?,
+        (None, true) => {
+            let tiles = tile_extents(&cli.input)?;
+
+            match (&cli.t_srs, &input_crs) {
+                (Some(target), Some(source)) => {
+                    let (source, target) = (spatial_ref(source)?, spatial_ref(target)?);
+
+                    tiles
+                        .into_iter()
+                        .map(|(name, b)| Ok((name, transform_bounds(&b, &source, &target)?)))
+                        .collect::<Result<Vec<_>, Error>>()?
+                }
+                _ => tiles,
+            }
+        }
+        (None, false) => {
+            rasterize(
+                &cli,
+                extent,
+                cli.output.as_deref(),
+                input_crs.as_deref(),
+                observer,
+            )?;
+
+            info!("Done!");
+            return Ok(());
+        }
+    };
+
+    let Some(output) = &cli.output else {
+        return Err(Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the output path is required",
+            )
+            .into());
+    };
+
+    if let Commands::Products { .. } = &cli.command {
+        return Err(Error::Unsupported(
+            "--aoi or --each-tile with products, which have their own outputs".to_string(),
+        ));
+    }
+
+    let areas_len = areas.len();
+    info!("Rasterizing {areas_len} areas");
+
+    for (i, (name, bounds)) in areas.into_iter().enumerate() {
+        let output = aoi_output(output, &name);
+        info!("{name} ({} of {areas_len}) -> {}", i + 1, output.display());
+
+        rasterize(
+            &cli,
+            Some(bounds),
+            Some(&output),
+            input_crs.as_deref(),
+            observer,
+        )?;
+    }
+
+    info!("Done!");
+    Ok(())
+}
+
+/// `output` with `name` appended to its file name, e.g. dem.tif -> dem_parcel-12.tif. Characters
+/// other than letters, digits, '-' and '_' are replaced.
+fn aoi_output(output: &Path, name: &str) -> PathBuf {
+    let name: String = name
+        .chars()
+        .map(|c| match c.is_alphanumeric() || c == '-' || c == '_' {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+
+    let mut file_name = format!("{stem}_{name}");
+    if let Some(ext) = output.extension() {
+        file_name.push('.');
+        file_name.push_str(&ext.to_string_lossy());
+    }
+
+    output.with_file_name(file_name)
+}
+
+/// The filter chain of the profile and the filter options, in that order. Saves it with
+/// `--save-profile`.
+fn point_filter(cli: &Cli) -> Result<PointFilter, Error> {
+    let profile = match &cli.profile {
+        Some(name) => Profile::load(name)?,
+        None => Profile::default(),
+    };
+
+    let chain: Vec<Filter> = profile
+        .chain()?
+        .into_iter()
+        .chain(cli.class.clone().map(Filter::Class))
+        .chain(cli.returns.map(Filter::Returns))
+        .chain(match (cli.zmin, cli.zmax) {
+            (None, None) => None,
+            (min, max) => Some(Filter::ZRange(
+                min.unwrap_or(f64::NEG_INFINITY),
+                max.unwrap_or(f64::INFINITY),
+            )),
+        })
+        .chain(match (cli.intensity_min, cli.intensity_max) {
+            (None, None) => None,
+            (min, max) => Some(Filter::Intensity(min.unwrap_or(0), max.unwrap_or(u16::MAX))),
+        })
+        .chain(cli.max_scan_angle.map(Filter::ScanAngle))
+        .chain(match (cli.gps_time_min, cli.gps_time_max) {
+            (None, None) => None,
+            (min, max) => Some(Filter::GpsTime(
+                min.unwrap_or(f64::NEG_INFINITY),
+                max.unwrap_or(f64::INFINITY),
+            )),
+        })
+        .chain(cli.point_source.clone().map(Filter::PointSource))
+        .chain(cli.user_data.clone().map(Filter::UserData))
+        .chain(match (cli.ndvi_min, cli.ndvi_max) {
+            (None, None) => None,
+            (min, max) => Some(Filter::Ndvi(min.unwrap_or(-1.0), max.unwrap_or(1.0))),
+        })
+        .chain(cli.drop_class.clone().map(Filter::DropClass))
+        .chain(cli.drop_overlap.then_some(Filter::Drop(Flag::Overlap)))
+        .chain(cli.drop_synthetic.then_some(Filter::Drop(Flag::Synthetic)))
+        .chain(cli.drop_keypoints.then_some(Filter::Drop(Flag::Keypoint)))
+        .chain(cli.filters.iter().cloned())
+        .chain(cli.wheres.iter().cloned().map(Filter::Where))
+        .collect();
+    let exclude_polygons = cli.exclude_polygons.clone().or(profile.exclude_polygons);
+
+    if let Some(name) = &cli.save_profile {
+        Profile::new(&chain, exclude_polygons.clone()).save(name)?;
+    }
+
+    // Not saved with the profile, they're defaults rather than a choice of filters.
+    let withheld = (!cli.keep_withheld).then_some(Filter::Drop(Flag::Withheld));
+    let noise = match (&cli.command, cli.keep_noise) {
+        // Products bin and triangulate the same points, they drop noise from their TINs only.
+        (Commands::Triangulate { .. }, false) => Some(high_noise()),
+        _ => None,
+    };
+
+    Ok(PointFilter::new(
+        withheld.into_iter().chain(noise).chain(chain).collect(),
+        match &exclude_polygons {
+            Some(path) => Some(Polygons::from_path(path)?),
+            None => None,
+        },
+        match &cli.clip {
+            Some(path) => Some(Polygons::from_path(path)?),
+            None => None,
+        },
+    ))
+}
+
+/// Reads the input within `extent` (default: the input's bounds) and writes the command's
+/// raster to `output`. `extent` is in the output's CRS, `input_crs` is needed to reproject.
+/// `observer` is told of the progress reading, triangulating and rendering, and can cancel it.
+#[instrument(skip_all, fields(output = ?output))]
+fn rasterize(
+    cli: &Cli,
+    extent: Option<Bounds>,
+    output: Option<&Path>,
+    input_crs: Option<&str>,
+    observer: Option<&Observer>,
+) -> Result<(), Error> {
+    let mut loaded_tin = match &cli.command {
+        Commands::Triangulate {
+            load_tin: Some(path),
+            max_edge,
+            max_area,
+            ..
+        } => Some(Surface::load(path, *max_edge, *max_area)?),
+        _ => None,
+    };
+
+    let (false, Some(res)) = (cli.input.is_empty() && loaded_tin.is_none(), cli.res) else {
+        return Err(Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--input and --res are required",
+            )
+            .into());
+    };
+
+    let reprojection = match (&cli.t_srs, input_crs) {
+        (Some(target), Some(source)) => Some((spatial_ref(source)?, spatial_ref(target)?)),
+        (Some(_), None) => {
+            return Err(Error::Crs(
+                "the input has no CRS to reproject from, use --a-srs".to_string(),
+            ))
+        }
+        (None, _) => None,
+    };
+
+    // Points around the extent, for interpolating up to its edges.
+    let buffered = match (extent, cli.tile_buffer) {
+        (Some(mut extent), Some(buffer)) => {
+            extent.min.x -= buffer;
+            extent.min.y -= buffer;
+            extent.max.x += buffer;
+            extent.max.y += buffer;
+            Some(extent)
+        }
+        _ => extent,
+    };
+
+    // The input is read in its own CRS.
+    let read_extent = match (&reprojection, buffered) {
+        (Some((source, target)), Some(extent)) => Some(transform_bounds(&extent, target, source)?),
+        _ => buffered,
+    };
+
+    let mut input = Input::from_paths(
+        &cli.input,
+        cli.skip_corrupt_points.unwrap_or(0),
+        read_extent.as_ref(),
+        res,
+        cli.index_cache,
+        &cli.csv_columns.clone().unwrap_or_default(),
+    )?;
+    if let Some(observer) = observer {
+        input.observe(observer.clone());
+    }
+
+    match (cli.thin_every, cli.thin_fraction) {
+        (Some(n), _) => input.thin(Thinning::Every { n, seen: 0 }),
+        (_, Some(fraction)) => input.thin(Thinning::Fraction(
+            fraction,
+            Rng::new(cli.seed.unwrap_or(0)),
+        )),
+        _ => {}
+    }
+
+    if let Some((source, target)) = &reprojection {
+        input.reproject(source, target)?;
+    }
+
+    if cli.intensity_scale.is_some() {
+        input.normalize_intensity()?;
+    }
+
+    if let Some(reference) = &cli.harmonize_intensity {
+        input.harmonize_intensity(reference)?;
+    }
+
+    if let (Some(buffered), Some(_)) = (buffered, cli.tile_buffer) {
+        input.clip(buffered);
+    }
+
+    let removals: Vec<OutlierRemoval> = cli
+        .sor
+        .map(OutlierRemoval::Statistical)
+        .into_iter()
+        .chain(cli.radius_filter.map(OutlierRemoval::Radius))
+        .collect();
+    let filter = point_filter(cli)?;
+    if !removals.is_empty() {
+        input.remove_outliers(&removals, &filter)?;
+    }
+
+    let mut bounds = extent
+        .or(loaded_tin.as_ref().map(|t| t.bounds()))
+        .unwrap_or(match filter.clip.as_ref().and_then(|clip| clip.bounds()) {
+            Some(clip) => clip_bounds(&input.bounds(), &clip),
+            None => input.bounds(),
+        });
+
+    if let Some(percent) = cli.trim_extent {
+        info!("Sampling points to trim the extent...");
+        bounds = trim_bounds(&bounds, &input.sample_xy(TRIM_SAMPLES)?, percent);
+        info!(
+            "Trimmed the extent to {}, {} - {}, {}",
+            bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y
+        );
+    }
+
+    let problems = unit_diagnostics(&bounds, &input.headers().collect::<Vec<_>>(), res);
+    if !problems.is_empty() {
+        match cli.force {
+            true => problems.iter().for_each(|p| warn!("{p}")),
+            false => return Err(Error::UnitMismatch(problems.join("; "))),
+        }
+    }
+
+    let nodata = cli.nodata.unwrap_or(NODATA);
+
+    let crs = match cli.t_srs.as_ref().or(cli.a_srs.as_ref()) {
+        Some(definition) => Some(spatial_ref(definition)?.to_wkt()?),
+        None => input.headers().find_map(header_wkt),
+    };
+    if crs.is_none() {
+        warn!("The input has no CRS, the output won't have one either. Use --a-srs to assign one");
+    }
+    let command_line = cli.command_line();
+    let mut info = RasterInfo {
+        crs: crs.as_deref(),
+        history: Some(&command_line),
+        units: &[],
+    };
+
+    if let Commands::Products { manifest } = &cli.command {
+        return run_products(
+            manifest,
+            input,
+            bounds,
+            res,
+            &filter,
+            nodata,
+            cli.compress,
+            cli.strict,
+            (!cli.keep_noise).then(high_noise),
+            &info,
+            observer,
+        );
+    }
+
+    let Some(output) = output else {
+        return Err(Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the output path is required",
+            )
+            .into());
+    };
+
+    let holdout = match (&cli.command, cli.cross_validate) {
+        (Commands::TreeTops { .. }, Some(_)) => {
+            warn!("Canopy heights can't be cross-validated, ignoring --cross-validate");
+            None
+        }
+        (_, fraction) => fraction.map(Holdout::new),
+    };
+
+    if let Some(holdout) = &holdout {
+        input.withhold(holdout.clone());
+    }
+
+    let var = match (cli.var.unwrap_or(Variable::Z), cli.intensity_scale) {
+        (Variable::Intensity, Some(scale)) => Variable::ScaledIntensity(scale),
+        (var, _) => var.resolve(&input.headers().collect::<Vec<_>>())?,
+    };
+    let funcs = match &cli.command {
+        Commands::Bin { func, .. } if !func.is_empty() => func.clone(),
+        _ => vec![Function::Median],
+    };
+    let carry = match &cli.command {
+        Commands::Bin {
+            carry: Some(carry), ..
+        } => Some(carry.resolve(&input.headers().collect::<Vec<_>>())?),
+        _ => None,
+    };
+    // The coarsest Z resolution of the input, the vertical precision of elevation bands.
+    let z_scale = input
+        .headers()
+        .map(|h| h.transforms().z.scale)
+        .reduce(f64::max);
+    // Functions whose picked points carry `carry`, a band each.
+    let carried: Vec<usize> = match carry {
+        Some(_) => (0..funcs.len())
+            .filter(|f| funcs[*f].picks_highest().is_some())
+            .collect(),
+        None => Vec::new(),
+    };
+    if carry.is_some() && carried.is_empty() {
+        return Err(Error::Unsupported(
+            "--carry without a min or max function".to_string(),
+        ));
+    }
+
+    let mut band_names: Vec<String> = match &cli.command {
+        Commands::Bin {
+            swath_edge_band, ..
+        } => funcs
+            .iter()
+            .map(|f| format!("{f:?}"))
+            .chain(
+                carried
+                    .iter()
+                    .map(|f| format!("{:?}{:?}", funcs[*f], carry.unwrap())),
+            )
+            .chain(swath_edge_band.then(|| "SwathEdge".to_string()))
+            .collect(),
+        Commands::Triangulate { .. } | Commands::Spline { .. } | Commands::Idw { .. } => {
+            vec![format!("{var:?}")]
+        }
+        Commands::TreeTops { .. } => vec!["CanopyHeight".to_string()],
+        Commands::Products { .. }
+        | Commands::Bench { .. }
+        | Commands::Generate { .. }
+        | Commands::Verify
+        | Commands::Index { .. }
+        | Commands::Validate
+        | Commands::Stream { .. } => {
+            unreachable!()
+        }
+    };
+    // Bands of the method's values, rather than flags.
+    let value_bands = match &cli.command {
+        Commands::Bin { .. } => funcs.len() + carried.len(),
+        _ => 1,
+    };
+    if cli.outlier_band.is_some() {
+        band_names.push("Outlier".to_string());
+    }
+    if let (Commands::Triangulate { resume: true, .. }, true) = (&cli.command, cli.progressive) {
+        return Err(Error::Unsupported(
+            "--resume with --progressive".to_string(),
+        ));
+    }
+
+    let (unit, vertical_crs) = match &crs {
+        Some(crs) => {
+            let vertical = vertical_units(crs)?;
+            (vertical.unit, vertical.crs)
+        }
+        None => (None, None),
+    };
+    let (elevations, heights) = elevation_bands(&cli.command, &funcs, var, carry, &carried);
+    let units = |vertical_crs: Option<String>| Units {
+        unit: unit.clone(),
+        vertical_crs,
+        precision: z_scale,
+    };
+    let band_units: Vec<(usize, Units)> = elevations
+        .into_iter()
+        .map(|band| (band, units(vertical_crs.clone())))
+        // Heights are differences of elevations, not in the vertical CRS.
+        .chain(heights.into_iter().map(|band| (band, units(None))))
+        .collect();
+    info.units = &band_units;
+
+    let grid = GridDefinition::new(bounds, res);
+    preflight(output, &grid, band_names.len(), BandType::F64, cli.compress)?;
+    if let Some(path) = &cli.provenance {
+        preflight(path, &grid, 1, BandType::U8, cli.compress)?;
+    }
+
+    let mut writer = match cli.progressive {
+        true => Some(RasterWriter::create(
+            output,
+            &grid,
+            nodata,
+            &band_names,
+            BandType::F64,
+            cli.compress,
+            &info,
+        )?),
+        false => None,
+    };
+
+    let mut report = Report::for_filter(&filter);
+
+    let mut bands = match &cli.command {
+        Commands::Bin {
+            breaklines,
+            weight_by_returns,
+            mask_swath_edges,
+            swath_edge_band,
+            focal_radius,
+            min_count,
+            ..
+        } => {
+            let breaklines = match breaklines {
+                Some(path) => Some(Breaklines::new(&read_features(path, None)?, &grid)),
+                None => None,
+            };
+
+            let bins = bin_points(
+                input,
+                grid,
+                &filter,
+                var,
+                &BinningParams {
+                    funcs: funcs.clone(),
+                    breaklines: breaklines.as_ref(),
+                    weight_by_returns: *weight_by_returns,
+                    swath_edge_angle: *mask_swath_edges,
+                    strict: cli.strict,
+                    focal_radius: *focal_radius,
+                    carry,
+                    min_count: min_count.unwrap_or(1) as usize,
+                },
+                &mut report,
+            )?;
+
+            if let Some((density, spacing)) = bins.pulse_density() {
+                report.nominal_pulse_density = Some(density);
+                report.nominal_pulse_spacing = Some(spacing);
+            }
+
+            let mut bands = Vec::with_capacity(band_names.len());
+
+            for (i, func) in funcs.iter().enumerate() {
+                let data = render(&grid, writer.as_mut().map(|w| (w, i)), observer, |x, y| {
+                    let i = grid.index(x, y);
+
+                    match !swath_edge_band && bins.swath_edge(i) == Some(true) {
+                        true => NODATA,
+                        false => bins.collapse(func, i),
+                    }
+                })?;
+
+                bands.push(Band::new(band_names[i].clone(), data));
+            }
+
+            for (n, func) in carried.iter().enumerate() {
+                let i = funcs.len() + n;
+                let data = render(&grid, writer.as_mut().map(|w| (w, i)), observer, |x, y| {
+                    let i = grid.index(x, y);
+
+                    match !swath_edge_band && bins.swath_edge(i) == Some(true) {
+                        true => NODATA,
+                        false => bins.carried(*func, i),
+                    }
+                })?;
+
+                bands.push(Band::new(band_names[i].clone(), data));
+            }
+
+            if *swath_edge_band {
+                let i = funcs.len() + carried.len();
+                let data =
+                    render(
+                        &grid,
+                        writer.as_mut().map(|w| (w, i)),
+                        observer,
+                        |x, y| match bins.swath_edge(grid.index(x, y)) {
+                            Some(true) => 1.0,
+                            Some(false) => 0.0,
+                            None => NODATA,
+                        },
+                    )?;
+
+                bands.push(Band::new(band_names[i].clone(), data));
+            }
+
+            bands
+        }
+        Commands::Triangulate {
+            freeze_distance,
+            insertion_buffer,
+            max_edge,
+            max_area,
+            seed_points,
+            seed_constraints,
+            max_vertices,
+            tin_error,
+            chunk_size,
+            voxel_thin,
+            voxel_keep,
+            save_tin,
+            resume,
+            ..
+        } => {
+            let run = cli.command_line();
+            let checkpoint = match *resume {
+                true => Checkpoint::load(output, &run, &grid)?,
+                false => None,
+            };
+
+            let surface = match (loaded_tin.take(), &checkpoint) {
+                (Some(surface), _) => surface,
+                (None, Some(_)) => {
+                    Surface::load(&Checkpoint::tin_path(output), *max_edge, *max_area)?
+                }
+                (None, None) => {
+                    let seeds = match seed_points {
+                        Some((path, field)) => read_features(path, field.as_deref())?,
+                        None => Vec::new(),
+                    };
+
+                    let missing = |arg: &str| Error::ShouldntHappen(format!("{arg} is missing"));
+
+                    triangulate(
+                        input,
+                        &filter,
+                        var,
+                        &TriangulationParams {
+                            freeze_distance: freeze_distance
+                                .ok_or_else(|| missing("--freeze-distance"))?,
+                            insertion_buffer: insertion_buffer
+                                .ok_or_else(|| missing("--insertion-buffer"))?,
+                            max_edge: *max_edge,
+                            max_area: *max_area,
+                            seeds,
+                            seed_constraints: *seed_constraints,
+                            max_vertices: *max_vertices,
+                            tin_error: *tin_error,
+                            chunk_size: *chunk_size,
+                            voxel: voxel_thin.map(|size| (size, voxel_keep.unwrap_or_default())),
+                        },
+                        &mut report,
+                        observer,
+                    )?
+                }
+            };
+
+            if let Some(path) = save_tin {
+                info!("Saving the triangulation...");
+                surface.save(path)?;
+            }
+
+            if *resume && checkpoint.is_none() {
+                info!("Checkpointing the triangulation...");
+                surface.save(&Checkpoint::tin_path(output))?;
+                Checkpoint::new(run.clone(), grid).save(output)?;
+            }
+
+            info!("Triangulating...");
+            let data = match *resume {
+                true => {
+                    let mut checkpoint = checkpoint.unwrap_or(Checkpoint::new(run, grid));
+                    let start = checkpoint.rows_done;
+
+                    let (mut data, strip_writer) = match start {
+                        0 => (
+                            Vec::new(),
+                            writer.insert(RasterWriter::create(
+                                output,
+                                &grid,
+                                nodata,
+                                &band_names,
+                                BandType::F64,
+                                cli.compress,
+                                &info,
+                            )?),
+                        ),
+                        _ => (
+                            read_rows(output, 0, start)?,
+                            writer.insert(RasterWriter::open(output, cli.compress)?),
+                        ),
+                    };
+
+                    data.extend(render_strips(
+                        &grid,
+                        start,
+                        CHECKPOINT_ROWS,
+                        strip_writer,
+                        0,
+                        observer,
+                        |x, y| surface.pixel(&grid, x, y),
+                        |rows| {
+                            checkpoint.rows_done = rows;
+                            checkpoint.save(output)
+                        },
+                    )?);
+
+                    data
+                }
+                false => render(&grid, writer.as_mut().map(|w| (w, 0)), observer, |x, y| {
+                    surface.pixel(&grid, x, y)
+                })?,
+            };
+
+            vec![Band::new(band_names[0].clone(), data)]
+        }
+        Commands::Spline {
+            tension,
+            max_iterations,
+            convergence,
+            max_distance,
+        } => {
+            let values = spline_surface(
+                input,
+                bounds,
+                res,
+                &filter,
+                var,
+                &SplineParams {
+                    tension: tension.unwrap_or(0.25),
+                    max_iterations: max_iterations.unwrap_or(500),
+                    convergence: convergence.unwrap_or(0.001),
+                    max_distance: *max_distance,
+                    strict: cli.strict,
+                },
+                &mut report,
+            )?;
+
+            let data = render(&grid, writer.as_mut().map(|w| (w, 0)), observer, |x, y| {
+                values[grid.index(x, y)]
+            })?;
+
+            vec![Band::new(band_names[0].clone(), data)]
+        }
+        Commands::Idw {
+            radius,
+            power,
+            azimuth,
+            ratio,
+        } => {
+            let values = idw(
+                input,
+                bounds,
+                res,
+                &filter,
+                var,
+                &IdwParams {
+                    power: power.unwrap_or(2.0),
+                    radius: *radius,
+                    azimuth: azimuth.unwrap_or(0.0),
+                    ratio: ratio.unwrap_or(1.0),
+                    strict: cli.strict,
+                },
+                &mut report,
+            )?;
+
+            let data = render(&grid, writer.as_mut().map(|w| (w, 0)), observer, |x, y| {
+                values[grid.index(x, y)]
+            })?;
+
+            vec![Band::new(band_names[0].clone(), data)]
+        }
+        Commands::TreeTops {
+            tree_tops,
+            ground_class,
+            min_height,
+            window,
+            crowns,
+            crown_polygons,
+        } => {
+            let chm = canopy_height(
+                input,
+                &grid,
+                &filter,
+                ground_class.unwrap_or(2),
+                cli.strict,
+                &mut report,
+            )?;
+
+            info!("Detecting tree tops...");
+            let min_height = min_height.unwrap_or(2.0);
+            let tops = local_maxima(
+                &chm,
+                &grid,
+                min_height,
+                window.unwrap_or(Window {
+                    base: 3.0,
+                    slope: 0.0,
+                }),
+            );
+
+            info!("Found {} tree tops", tops.len());
+            write_tree_tops(tree_tops, &tops)?;
+
+            if crowns.is_some() || crown_polygons.is_some() {
+                info!("Segmenting crowns...");
+                let segments = segment_crowns(&chm, &grid, min_height, &tops);
+
+                if let Some(path) = crown_polygons {
+                    write_crown_polygons(path, &grid, segments.clone())?;
+                }
+
+                if let Some(path) = crowns {
+                    write_crowns(path, &grid, segments, crs.as_deref())?;
+                }
+            }
+
+            vec![Band::new(band_names[0].clone(), chm)]
+        }
+        Commands::Products { .. }
+        | Commands::Bench { .. }
+        | Commands::Generate { .. }
+        | Commands::Verify
+        | Commands::Index { .. }
+        | Commands::Validate
+        | Commands::Stream { .. } => {
+            unreachable!()
+        }
+    };
+
+    let fills = fill_nodata(&mut bands[..value_bands], &grid, &cli.fallback, NODATA);
+
+    if let Some(exclude) = &filter.exclude {
+        for band in bands.iter_mut() {
+            exclude.mask(&mut band.data, &grid, NODATA);
+        }
+    }
+
+    if let Some(clip) = &filter.clip {
+        for band in bands.iter_mut() {
+            clip.clip(&mut band.data, &grid, NODATA);
+        }
+    }
+
+    let provenance_codes = cli.provenance.as_ref().map(|path| {
+        let method = match &cli.command {
+            Commands::Triangulate { .. } => Provenance::Tin,
+            Commands::Idw { .. } => Provenance::Idw,
+            Commands::Spline { .. } => Provenance::Spline,
+            _ => Provenance::Bin,
+        };
+
+        (path, provenance(&bands[0].data, method, &fills, NODATA))
+    });
+
+    if let Some(mads) = cli.outlier_band {
+        let flags = outlier_flags(&bands[0].data, &grid, mads, NODATA);
+        bands.push(Band::new("Outlier".to_string(), flags));
+    }
+
+    if let (Some(holdout), Some(band)) = (&holdout, bands.first()) {
+        report.accuracy = Some(holdout.accuracy(&band.data, &grid, &filter, var));
+    }
+
+    apply_nodata_policy(&mut bands, cli.nodata_policy.unwrap_or_default(), NODATA);
+
+    report.empty = bands
+        .iter()
+        .all(|band| band.data.iter().all(|v| *v == NODATA));
+
+    if report.empty {
+        match cli.fail_empty {
+            true => {
+                // A progressive or resumed run has already written part of the output.
+                if let Some(partial) = writer.take() {
+                    drop(partial);
+                    match fs::remove_file(output) {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    }
+                }
+                if let Commands::Triangulate { resume: true, .. } = &cli.command {
+                    Checkpoint::remove(output)?;
+                }
+
+                report.log();
+                if cli.report {
+                    report.save(output)?;
+                }
+                return Err(Error::Empty);
+            }
+            false => warn!("No data within the extent after filtering, the raster is all NODATA."),
+        }
+    }
+
+    match writer.as_mut() {
+        Some(writer) => {
+            writer.write_bands(bands)?;
+            writer.finish()?;
+        }
+        None => write_raster(
+            output,
+            &grid,
+            nodata,
+            bands,
+            BandType::F64,
+            cli.compress,
+            &info,
+        )?,
+    }
+
+    if let Commands::Triangulate { resume: true, .. } = &cli.command {
+        Checkpoint::remove(output)?;
+    }
+
+    if let Some((path, codes)) = provenance_codes {
+        info!("Writing the provenance to {}", path.display());
+        write_raster(
+            path,
+            &grid,
+            Provenance::Nodata as u8 as f64,
+            vec![Band::new("Provenance".to_string(), codes)],
+            BandType::U8,
+            cli.compress,
+            &RasterInfo { units: &[], ..info },
+        )?;
+        write_categories(path, 0, PROVENANCE)?;
+    }
+
+    // Bands of classification codes.
+    if let Commands::Bin { .. } = &cli.command {
+        for (i, func) in funcs.iter().enumerate() {
+            if func.is_categorical() && func.variable().unwrap_or(var) == Variable::Classification {
+                write_categories(output, i, LAS_CLASSES)?;
+            }
+        }
+    }
+
+    report.log();
+    if cli.report {
+        report.save(output)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extents() {
+        let corners = |b: Bounds| [b.min.x, b.min.y, b.min.z, b.max.x, b.max.y, b.max.z];
+
+        assert_eq!(
+            corners(bounds_parser("1,2,3,4").unwrap()),
+            [1.0, 2.0, f64::MIN, 3.0, 4.0, f64::MAX]
+        );
+        assert_eq!(
+            corners(bounds_parser("1,2,-5,3,4,10").unwrap()),
+            [1.0, 2.0, -5.0, 3.0, 4.0, 10.0]
+        );
+
+        assert!(bounds_parser("3,2,1,4").is_err());
+        assert!(bounds_parser("1,2,3").is_err());
+        assert!(bounds_parser("1,2,a,4").is_err());
+    }
+}
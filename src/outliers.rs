@@ -0,0 +1,343 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use tracing::info;
+
+/// Outlier removal from the positions of every point, ahead of rasterization.
+#[derive(Debug, Clone, Copy)]
+pub enum OutlierRemoval {
+    Statistical(Sor),
+    Radius(RadiusFilter),
+}
+
+impl OutlierRemoval {
+    /// Criterion of the points it drops.
+    pub fn criterion(&self) -> &'static str {
+        match self {
+            OutlierRemoval::Statistical(_) => "statistical outlier",
+            OutlierRemoval::Radius(_) => "isolated point",
+        }
+    }
+
+    /// Whether each of `points` is an outlier.
+    pub fn outliers(&self, points: &[[f64; 3]]) -> Vec<bool> {
+        match self {
+            OutlierRemoval::Statistical(sor) => sor.outliers(points),
+            OutlierRemoval::Radius(filter) => filter.outliers(points),
+        }
+    }
+}
+
+/// Statistical outlier removal: points whose mean distance to their `k` nearest neighbours is
+/// more than `std_ratio` standard deviations above the mean over every point.
+#[derive(Debug, Clone, Copy)]
+pub struct Sor {
+    pub k: usize,
+    pub std_ratio: f64,
+}
+
+pub fn sor_parser(s: &str) -> core::result::Result<Sor, String> {
+    let (k, std_ratio) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected <k>,<stddev>, got '{s}'"))?;
+
+    let k = k.trim().parse::<usize>().map_err(|e| e.to_string())?;
+    let std_ratio = std_ratio.trim().parse::<f64>().map_err(|e| e.to_string())?;
+
+    if k == 0 {
+        return Err("k must be at least 1".to_string());
+    }
+    if !std_ratio.is_finite() {
+        return Err(format!("invalid standard deviation multiple '{std_ratio}'"));
+    }
+
+    Ok(Sor { k, std_ratio })
+}
+
+impl Sor {
+    fn outliers(&self, points: &[[f64; 3]]) -> Vec<bool> {
+        info!(
+            "Finding the {} nearest neighbours of {} points...",
+            self.k,
+            points.len()
+        );
+
+        let tree = KdTree::new(points);
+        let mut nearest = BinaryHeap::with_capacity(self.k);
+
+        let distances: Vec<f64> = (0..points.len())
+            .map(|i| {
+                nearest.clear();
+                tree.search(&tree.order, 0, i, self.k, &mut nearest);
+
+                let sum: f64 = nearest.iter().map(|d: &Distance| d.0.sqrt()).sum();
+                sum / nearest.len().max(1) as f64
+            })
+            .collect();
+
+        let n = distances.len().max(1) as f64;
+        let mean = distances.iter().sum::<f64>() / n;
+        let std = (distances.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / n).sqrt();
+        let threshold = mean + self.std_ratio * std;
+
+        info!("Mean neighbour distance {mean:.3}, dropping points past {threshold:.3}");
+
+        distances.into_iter().map(|d| d > threshold).collect()
+    }
+}
+
+/// Radius outlier removal: points with fewer than `min_neighbors` other points within `radius`.
+#[derive(Debug, Clone, Copy)]
+pub struct RadiusFilter {
+    pub radius: f64,
+    pub min_neighbors: usize,
+}
+
+pub fn radius_filter_parser(s: &str) -> core::result::Result<RadiusFilter, String> {
+    let (radius, min_neighbors) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected <radius>,<min neighbours>, got '{s}'"))?;
+
+    let radius = radius.trim().parse::<f64>().map_err(|e| e.to_string())?;
+    let min_neighbors = min_neighbors
+        .trim()
+        .parse::<usize>()
+        .map_err(|e| e.to_string())?;
+
+    if radius <= 0.0 || !radius.is_finite() {
+        return Err(format!("the radius has to be positive, got {radius}"));
+    }
+
+    Ok(RadiusFilter {
+        radius,
+        min_neighbors,
+    })
+}
+
+impl RadiusFilter {
+    /// Counts neighbours over a hash grid of `radius` sized cubes, so each point only visits the
+    /// 27 cubes around it, and stops counting at `min_neighbors`.
+    fn outliers(&self, points: &[[f64; 3]]) -> Vec<bool> {
+        info!(
+            "Counting the neighbours within {} of {} points...",
+            self.radius,
+            points.len()
+        );
+
+        let cube = |p: &[f64; 3]| p.map(|v| (v / self.radius).floor() as i64);
+
+        let mut cubes: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        for (i, p) in points.iter().enumerate() {
+            cubes.entry(cube(p)).or_default().push(i);
+        }
+
+        let r_2 = self.radius * self.radius;
+
+        points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let [cx, cy, cz] = cube(p);
+                let neighbors = (-1..=1)
+                    .flat_map(|dx| {
+                        (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [dx, dy, dz]))
+                    })
+                    .filter_map(|[dx, dy, dz]| cubes.get(&[cx + dx, cy + dy, cz + dz]))
+                    .flatten()
+                    .filter(|j| {
+                        let q = points[**j];
+                        **j != i && (0..3).map(|a| (p[a] - q[a]).powi(2)).sum::<f64>() <= r_2
+                    })
+                    .take(self.min_neighbors)
+                    .count();
+
+                neighbors < self.min_neighbors
+            })
+            .collect()
+    }
+}
+
+/// A squared distance, ordered for a max-heap of the nearest neighbours found so far.
+struct Distance(f64);
+
+impl PartialEq for Distance {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Distance {}
+
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A static 3D k-d tree over point positions. Each subtree is a slice of `order`, split on the
+/// axis of its depth at the median in its middle.
+struct KdTree<'a> {
+    points: &'a [[f64; 3]],
+    order: Vec<usize>,
+}
+
+impl<'a> KdTree<'a> {
+    fn new(points: &'a [[f64; 3]]) -> Self {
+        fn build(points: &[[f64; 3]], order: &mut [usize], depth: usize) {
+            if order.len() <= 1 {
+                return;
+            }
+
+            let axis = depth % 3;
+            let mid = order.len() / 2;
+            order.select_nth_unstable_by(mid, |a, b| points[*a][axis].total_cmp(&points[*b][axis]));
+
+            let (below, above) = order.split_at_mut(mid);
+            build(points, below, depth + 1);
+            build(points, &mut above[1..], depth + 1);
+        }
+
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        build(points, &mut order, 0);
+
+        Self { points, order }
+    }
+
+    /// Keeps the squared distances of the `k` points of the subtree `order` nearest to point
+    /// `i`, other than itself, in `nearest`.
+    fn search(
+        &self,
+        order: &[usize],
+        depth: usize,
+        i: usize,
+        k: usize,
+        nearest: &mut BinaryHeap<Distance>,
+    ) {
+        if order.is_empty() {
+            return;
+        }
+
+        let (axis, mid) = (depth % 3, order.len() / 2);
+        let (query, split) = (self.points[i], self.points[order[mid]]);
+
+        if order[mid] != i {
+            let d = (0..3).map(|a| (query[a] - split[a]).powi(2)).sum::<f64>();
+
+            match nearest.len() < k {
+                true => nearest.push(Distance(d)),
+                false => {
+                    if nearest.peek().is_some_and(|far| d < far.0) {
+                        nearest.pop();
+                        nearest.push(Distance(d));
+                    }
+                }
+            }
+        }
+
+        let offset = query[axis] - split[axis];
+        let (near, far) = match offset < 0.0 {
+            true => (&order[..mid], &order[mid + 1..]),
+            false => (&order[mid + 1..], &order[..mid]),
+        };
+
+        self.search(near, depth + 1, i, k, nearest);
+
+        // The other side can only hold nearer points within the offset to the split.
+        if nearest.len() < k || nearest.peek().is_some_and(|far| offset * offset < far.0) {
+            self.search(far, depth + 1, i, k, nearest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::Rng;
+
+    /// A 10 x 10 x 2 lattice of 1 m, and a point 50 m above it.
+    fn lattice_and_bird() -> Vec<[f64; 3]> {
+        let mut points: Vec<[f64; 3]> = (0..200)
+            .map(|i| [(i % 10) as f64, (i / 10 % 10) as f64, (i / 100) as f64])
+            .collect();
+        points.push([5.0, 5.0, 50.0]);
+
+        points
+    }
+
+    #[test]
+    fn parsers() {
+        let sor = sor_parser("8, 2.5").unwrap();
+        assert_eq!((sor.k, sor.std_ratio), (8, 2.5));
+        assert!(sor_parser("0,1").is_err());
+        assert!(sor_parser("8").is_err());
+        assert!(sor_parser("8,inf").is_err());
+
+        let filter = radius_filter_parser("1.5,4").unwrap();
+        assert_eq!((filter.radius, filter.min_neighbors), (1.5, 4));
+        assert!(radius_filter_parser("0,4").is_err());
+        assert!(radius_filter_parser("1,-1").is_err());
+    }
+
+    #[test]
+    fn statistical() {
+        let points = lattice_and_bird();
+        let sor = Sor {
+            k: 4,
+            std_ratio: 2.0,
+        };
+
+        let outliers = OutlierRemoval::Statistical(sor).outliers(&points);
+        assert_eq!(outliers.iter().filter(|o| **o).count(), 1);
+        assert!(outliers[200]);
+    }
+
+    #[test]
+    fn radius() {
+        let points = lattice_and_bird();
+        let filter = RadiusFilter {
+            radius: 1.0,
+            min_neighbors: 2,
+        };
+
+        let outliers = OutlierRemoval::Radius(filter).outliers(&points);
+        assert_eq!(outliers.iter().filter(|o| **o).count(), 1);
+        assert!(outliers[200]);
+    }
+
+    #[test]
+    fn nearest_neighbours() {
+        let mut rng = Rng::new(1);
+        let points: Vec<[f64; 3]> = (0..500)
+            .map(|_| {
+                [
+                    rng.uniform() * 100.0,
+                    rng.uniform() * 100.0,
+                    rng.uniform() * 10.0,
+                ]
+            })
+            .collect();
+        let tree = KdTree::new(&points);
+        let k = 5;
+
+        for i in (0..points.len()).step_by(37) {
+            let mut nearest = BinaryHeap::new();
+            tree.search(&tree.order, 0, i, k, &mut nearest);
+            let mut found: Vec<f64> = nearest.into_iter().map(|d| d.0).collect();
+            found.sort_by(f64::total_cmp);
+
+            let mut expected: Vec<f64> = (0..points.len())
+                .filter(|j| *j != i)
+                .map(|j| (0..3).map(|a| (points[i][a] - points[j][a]).powi(2)).sum())
+                .collect();
+            expected.sort_by(f64::total_cmp);
+
+            assert_eq!(found, expected[..k]);
+        }
+    }
+}
@@ -0,0 +1,840 @@
+use std::ffi::{CString, NulError};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use clap::ValueEnum;
+use gdal::cpl::CslStringList;
+use gdal::raster::{Buffer, GdalType, RasterBand};
+use gdal::{Dataset, DatasetOptions, Driver, GdalOpenFlags, Metadata};
+use gdal_sys::{CPLErr, GDALRATFieldType, GDALRATFieldUsage};
+use las::{Bounds, Vector};
+use tracing::{info, instrument, warn};
+
+use crate::drivers::{create_driver, DriverKind};
+use crate::error::{Error, Result};
+use crate::service::{Observer, Stage};
+use crate::util::{get_raster_size, GridDefinition};
+
+/// Finds a raster driver with creation support for the extension of `path`.
+pub fn raster_driver(path: &Path) -> Result<Driver> {
+    create_driver(path, DriverKind::Raster)
+}
+
+/// A named output band. Its values are f64 whatever the output's `BandType`, converted as
+/// they're written.
+pub struct Band {
+    pub name: String,
+    pub data: Vec<f64>,
+}
+
+impl Band {
+    pub fn new(name: String, data: Vec<f64>) -> Self {
+        Self { name, data }
+    }
+}
+
+/// Value type of the bands of an output raster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandType {
+    F64,
+    /// Bytes, for codes rather than values, e.g. the --provenance raster. NODATA doesn't fit.
+    U8,
+}
+
+impl BandType {
+    /// Bytes per value.
+    fn size(self) -> usize {
+        match self {
+            BandType::F64 => std::mem::size_of::<f64>(),
+            BandType::U8 => std::mem::size_of::<u8>(),
+        }
+    }
+}
+
+/// A value of a categorical band, with its name and display color.
+pub struct Category {
+    pub value: i32,
+    pub name: &'static str,
+    pub color: [u8; 3],
+}
+
+const fn category(value: i32, name: &'static str, color: [u8; 3]) -> Category {
+    Category { value, name, color }
+}
+
+/// The ASPRS standard LAS classes.
+pub const LAS_CLASSES: &[Category] = &[
+    category(0, "Never classified", [160, 160, 160]),
+    category(1, "Unclassified", [200, 200, 200]),
+    category(2, "Ground", [166, 97, 26]),
+    category(3, "Low vegetation", [186, 228, 179]),
+    category(4, "Medium vegetation", [116, 196, 118]),
+    category(5, "High vegetation", [35, 139, 69]),
+    category(6, "Building", [227, 26, 28]),
+    category(7, "Low point (noise)", [255, 0, 255]),
+    category(8, "Model key-point", [255, 255, 0]),
+    category(9, "Water", [33, 113, 181]),
+    category(10, "Rail", [84, 39, 143]),
+    category(11, "Road surface", [99, 99, 99]),
+    category(12, "Overlap", [253, 174, 107]),
+    category(13, "Wire - guard", [255, 237, 160]),
+    category(14, "Wire - conductor", [254, 217, 118]),
+    category(15, "Transmission tower", [128, 0, 38]),
+    category(16, "Wire-structure connector", [252, 78, 42]),
+    category(17, "Bridge deck", [140, 81, 10]),
+    category(18, "High noise", [255, 0, 128]),
+];
+
+/// Output compression, for drivers which support a COMPRESS creation option.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Deflate,
+    Zstd,
+    Lzw,
+}
+
+impl Compression {
+    fn name(&self) -> &'static str {
+        match self {
+            Compression::Deflate => "DEFLATE",
+            Compression::Zstd => "ZSTD",
+            Compression::Lzw => "LZW",
+        }
+    }
+}
+
+/// How NODATA combines over the bands of a pixel.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NodataPolicy {
+    /// A pixel is NODATA in every band if any band lacks data.
+    Any,
+    /// A pixel is NODATA only if every band lacks data, missing values elsewhere are written as
+    /// 0.
+    All,
+    /// Each band's NODATA is independent.
+    #[default]
+    PerBand,
+}
+
+/// Applies `policy` to the `nodata` pixels of `bands`.
+pub fn apply_nodata_policy(bands: &mut [Band], policy: NodataPolicy, nodata: f64) {
+    if policy == NodataPolicy::PerBand || bands.len() < 2 {
+        return;
+    }
+
+    for i in 0..bands[0].data.len() {
+        let missing = bands.iter().filter(|b| b.data[i] == nodata).count();
+
+        if missing == 0 || missing == bands.len() {
+            continue;
+        }
+
+        for band in bands.iter_mut() {
+            match policy {
+                NodataPolicy::Any => band.data[i] = nodata,
+                NodataPolicy::All if band.data[i] == nodata => band.data[i] = 0.0,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Median of `values`, which it sorts. NaN if empty.
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    match values.len() {
+        0 => f64::NAN,
+        len if len % 2 == 0 => (values[len / 2 - 1] + values[len / 2]) / 2.0,
+        len => values[len / 2],
+    }
+}
+
+/// Flags the pixels of a band of `grid` deviating more than `mads` median absolute deviations
+/// from the median of their 3x3 neighbourhood: 1 for outliers, 0 otherwise, `nodata` where the
+/// band has none. Pixels with fewer than 3 neighbours with data aren't flagged.
+pub fn outlier_flags(data: &[f64], grid: &GridDefinition, mads: f64, nodata: f64) -> Vec<f64> {
+    let (width, height) = (grid.width, grid.height);
+    let mut neighbours = Vec::with_capacity(8);
+
+    (0..grid.len())
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            if data[i] == nodata {
+                return nodata;
+            }
+
+            neighbours.clear();
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let value = data[grid.index(nx, ny)];
+                    if (nx, ny) != (x, y) && value != nodata {
+                        neighbours.push(value);
+                    }
+                }
+            }
+
+            if neighbours.len() < 3 {
+                return 0.0;
+            }
+
+            let center = median(&mut neighbours);
+            let deviation = (data[i] - center).abs();
+            let mad = median(
+                &mut neighbours
+                    .iter()
+                    .map(|v| (v - center).abs())
+                    .collect::<Vec<f64>>(),
+            );
+
+            // A flat neighbourhood has a MAD of 0, any deviation from it is an outlier.
+            match deviation > mads * mad {
+                true => 1.0,
+                false => 0.0,
+            }
+        })
+        .collect()
+}
+
+/// Classic TIFF offsets are 32 bit, leave some headroom for tiles, overviews and metadata.
+const CLASSIC_TIFF_LIMIT: u64 = 4_000_000_000;
+
+/// Creation options for a raster of the given size. GeoTIFFs that would exceed the classic TIFF
+/// size limit are switched to BigTIFF.
+fn creation_options(
+    driver: &Driver,
+    width: usize,
+    height: usize,
+    bands: usize,
+    band_type: BandType,
+    compress: Option<Compression>,
+) -> Result<CslStringList> {
+    let mut options = CslStringList::new();
+
+    let option_list = driver
+        .metadata_item("DMD_CREATIONOPTIONLIST", "")
+        .unwrap_or_default();
+
+    if let Some(compress) = compress {
+        if !option_list.contains("COMPRESS") || !option_list.contains(compress.name()) {
+            return Err(Error::Unsupported(format!(
+                "{} compression with the {} driver",
+                compress.name(),
+                driver.short_name()
+            )));
+        }
+
+        options.set_name_value("COMPRESS", compress.name())?;
+        // Compressed GeoTIFFs are far smaller and quicker to read back when tiled.
+        if driver.short_name() == "GTiff" {
+            options.set_name_value("TILED", "YES")?;
+        }
+    }
+
+    // NetCDF-4 for compression and outputs past 2 GB, GDAL writes the CF coordinate variables
+    // and grid mapping either way.
+    if driver.short_name() == "netCDF" && option_list.contains("NC4") {
+        options.set_name_value("FORMAT", "NC4")?;
+    }
+
+    let size = width as u64 * height as u64 * bands as u64 * band_type.size() as u64;
+
+    if driver.short_name() == "GTiff" && size > CLASSIC_TIFF_LIMIT {
+        if !option_list.contains("BIGTIFF") {
+            return Err(Error::TooLarge(format!(
+                "the output would be ~{} GB, but this GDAL build can't write BigTIFF. Use \
+                 another format (e.g. .img), a coarser --res or a smaller --extent",
+                size / 1_000_000_000
+            )));
+        }
+
+        info!("Output is ~{} GB, writing BigTIFF", size / 1_000_000_000);
+        options.set_name_value("BIGTIFF", "YES")?;
+    }
+
+    Ok(options)
+}
+
+/// Resolves the driver and creation options ahead of processing, so problems with the output
+/// surface before a long run rather than at the very end.
+pub fn preflight(
+    path: &Path,
+    grid: &GridDefinition,
+    bands: usize,
+    band_type: BandType,
+    compress: Option<Compression>,
+) -> Result<()> {
+    let driver = raster_driver(path)?;
+
+    creation_options(&driver, grid.width, grid.height, bands, band_type, compress)?;
+
+    Ok(())
+}
+
+/// Coarse pass block size in pixels for progressive rendering.
+const COARSE_BLOCK: usize = 16;
+/// Refinement tile size in pixels for progressive rendering.
+const TILE: usize = 256;
+
+/// A block of a band (0 based) with its origin at `offset`.
+struct Block {
+    band: usize,
+    offset: (usize, usize),
+    size: (usize, usize),
+    data: Vec<f64>,
+}
+
+enum Message {
+    Block(Block),
+    Flush,
+}
+
+fn write_to(ds: &mut Dataset, block: Block) -> Result<()> {
+    let mut rb = ds.rasterband(block.band + 1)?;
+    rb.write(
+        (block.offset.0 as isize, block.offset.1 as isize),
+        block.size,
+        &mut Buffer::new(block.size, block.data),
+    )?;
+
+    Ok(())
+}
+
+/// CF attributes describing a NetCDF output and its variables.
+fn cf_attributes(ds: &mut Dataset, names: &[String], history: Option<&str>) -> Result<()> {
+    ds.set_metadata_item("NC_GLOBAL#title", "Rasterized point cloud", "")?;
+    ds.set_metadata_item(
+        "NC_GLOBAL#source",
+        concat!("las-rasterizer ", env!("CARGO_PKG_VERSION")),
+        "",
+    )?;
+    if let Some(history) = history {
+        ds.set_metadata_item("NC_GLOBAL#history", history, "")?;
+    }
+
+    for (i, name) in names.iter().enumerate() {
+        ds.rasterband(i + 1)?
+            .set_metadata_item("long_name", name, "")?;
+    }
+
+    Ok(())
+}
+
+/// Where blocks go. Compressed outputs are written from a separate thread, so GDAL compresses
+/// the previous block while the next one is being computed.
+enum Sink {
+    Direct(Dataset),
+    Threaded {
+        tx: SyncSender<Message>,
+        handle: JoinHandle<Result<()>>,
+    },
+}
+
+/// What a raster records about itself besides its bands' values.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RasterInfo<'a> {
+    /// WKT of the CRS.
+    pub crs: Option<&'a str>,
+    /// The command line which made the raster, for the NetCDF history.
+    pub history: Option<&'a str>,
+    /// Units of bands (0 based), set as the bands are created.
+    pub units: &'a [(usize, Units)],
+}
+
+/// An output raster of a grid, one raster band per band of the `band_type`, described by a
+/// `RasterInfo`.
+pub struct RasterWriter {
+    sink: Option<Sink>,
+    width: usize,
+    height: usize,
+}
+
+impl RasterWriter {
+    pub fn create(
+        path: &Path,
+        grid: &GridDefinition,
+        nodata: f64,
+        names: &[String],
+        band_type: BandType,
+        compress: Option<Compression>,
+        info: &RasterInfo,
+    ) -> Result<Self> {
+        let driver = raster_driver(path)?;
+
+        info!("Writing {:?} ...", driver.short_name());
+
+        let (width, height) = (grid.width, grid.height);
+
+        let options = creation_options(&driver, width, height, names.len(), band_type, compress)?;
+
+        fn create<T: GdalType>(
+            driver: &Driver,
+            path: &Path,
+            (width, height, bands): (usize, usize, usize),
+            options: &CslStringList,
+        ) -> Result<Dataset> {
+            Ok(driver
+                .create_with_band_type_with_options::<T, _>(path, width, height, bands, options)?)
+        }
+
+        // Blocks are written as f64, GDAL converts them to the band type.
+        let size = (width, height, names.len());
+        let mut ds = match band_type {
+            BandType::F64 => create::<f64>(&driver, path, size, &options)?,
+            BandType::U8 => create::<u8>(&driver, path, size, &options)?,
+        };
+
+        ds.set_geo_transform(&grid.geo_transform())?;
+
+        if let Some(wkt) = info.crs {
+            ds.set_projection(wkt)?;
+        }
+
+        for (i, name) in names.iter().enumerate() {
+            let mut rb = ds.rasterband(i + 1)?;
+            rb.set_description(name)?;
+            rb.set_no_data_value(Some(nodata))?;
+        }
+
+        for (band, units) in info.units {
+            set_units(
+                &mut ds.rasterband(band + 1)?,
+                units,
+                driver.short_name().as_str(),
+            );
+        }
+
+        if driver.short_name() == "netCDF" {
+            cf_attributes(&mut ds, names, info.history)?;
+        }
+
+        Ok(Self {
+            sink: Some(Self::sink(ds, compress)),
+            width,
+            height,
+        })
+    }
+
+    /// Opens a raster written by `create` to write more blocks to it, e.g. to carry on with an
+    /// interrupted run.
+    pub fn open(path: &Path, compress: Option<Compression>) -> Result<Self> {
+        let ds = Dataset::open_ex(
+            path,
+            DatasetOptions {
+                open_flags: GdalOpenFlags::GDAL_OF_RASTER | GdalOpenFlags::GDAL_OF_UPDATE,
+                ..Default::default()
+            },
+        )?;
+        let (width, height) = ds.raster_size();
+
+        Ok(Self {
+            sink: Some(Self::sink(ds, compress)),
+            width,
+            height,
+        })
+    }
+
+    fn sink(mut ds: Dataset, compress: Option<Compression>) -> Sink {
+        match compress {
+            Some(_) => {
+                // One block in flight while the next is computed, i.e. double buffered.
+                let (tx, rx) = sync_channel::<Message>(1);
+
+                let handle = thread::spawn(move || -> Result<()> {
+                    for message in rx {
+                        match message {
+                            Message::Block(block) => write_to(&mut ds, block)?,
+                            Message::Flush => ds.flush_cache()?,
+                        }
+                    }
+
+                    ds.flush_cache()?;
+                    Ok(())
+                });
+
+                Sink::Threaded { tx, handle }
+            }
+            None => Sink::Direct(ds),
+        }
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        match self.sink.as_mut() {
+            Some(Sink::Direct(ds)) => match message {
+                Message::Block(block) => write_to(ds, block),
+                Message::Flush => Ok(ds.flush_cache()?),
+            },
+            Some(Sink::Threaded { tx, .. }) => match tx.send(message) {
+                Ok(()) => Ok(()),
+                // The writer thread only hangs up on an error, surface it.
+                Err(_) => {
+                    self.finish()?;
+                    Err(Error::ShouldntHappen(
+                        "The raster writer thread stopped early".to_string(),
+                    ))
+                }
+            },
+            None => Err(Error::ShouldntHappen(
+                "Writing to a finished raster".to_string(),
+            )),
+        }
+    }
+
+    /// Writes a block of `band` (0 based) with its origin at `offset`.
+    pub fn write_block(
+        &mut self,
+        band: usize,
+        offset: (usize, usize),
+        size: (usize, usize),
+        data: Vec<f64>,
+    ) -> Result<()> {
+        self.send(Message::Block(Block {
+            band,
+            offset,
+            size,
+            data,
+        }))
+    }
+
+    pub fn write_bands(&mut self, bands: Vec<Band>) -> Result<()> {
+        let size = (self.width, self.height);
+
+        for (i, band) in bands.into_iter().enumerate() {
+            self.write_block(i, (0, 0), size, band.data)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.send(Message::Flush)
+    }
+
+    /// Flushes and closes the raster, waiting for any pending writes.
+    pub fn finish(&mut self) -> Result<()> {
+        match self.sink.take() {
+            Some(Sink::Direct(mut ds)) => Ok(ds.flush_cache()?),
+            Some(Sink::Threaded { tx, handle }) => {
+                drop(tx);
+
+                handle.join().map_err(|_| {
+                    Error::ShouldntHappen("The raster writer thread panicked".to_string())
+                })?
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Tells `observer` of the `rows` rendered so far, erroring once the job is cancelled.
+fn rendered(observer: Option<&Observer>, rows: usize, height: usize) -> Result<()> {
+    match observer {
+        Some(observer) => observer.observe(Stage::Rendering, rows as u64, height as u64),
+        None => Ok(()),
+    }
+}
+
+/// Evaluates `f` for every pixel (x, y) of a band of `grid`. With a writer, a coarse version of
+/// the band is written first and then refined tile by tile, flushing as it goes so the output
+/// can be opened while it fills in. `observer` is told of the rows done, and can cancel.
+#[instrument(name = "write", skip_all)]
+pub fn render(
+    grid: &GridDefinition,
+    writer: Option<(&mut RasterWriter, usize)>,
+    observer: Option<&Observer>,
+    mut f: impl FnMut(usize, usize) -> f64,
+) -> Result<Vec<f64>> {
+    let (width, height) = (grid.width, grid.height);
+    let Some((writer, band)) = writer else {
+        let mut data = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            data.extend((0..width).map(|x| f(x, y)));
+            rendered(observer, y + 1, height)?;
+        }
+
+        return Ok(data);
+    };
+
+    // Coarse pass, one sample per block.
+    let mut data = vec![0.0; width * height];
+
+    for by in (0..height).step_by(COARSE_BLOCK) {
+        rendered(observer, 0, height)?;
+
+        for bx in (0..width).step_by(COARSE_BLOCK) {
+            let value = f(
+                (bx + COARSE_BLOCK / 2).min(width - 1),
+                (by + COARSE_BLOCK / 2).min(height - 1),
+            );
+
+            for y in by..(by + COARSE_BLOCK).min(height) {
+                data[y * width + bx..y * width + (bx + COARSE_BLOCK).min(width)].fill(value);
+            }
+        }
+    }
+
+    writer.write_block(band, (0, 0), (width, height), data.clone())?;
+    writer.flush()?;
+
+    // Refinement, tile by tile, flushing after each row of tiles.
+    for ((tx, ty), tile) in grid.tiles(TILE) {
+        rendered(observer, ty, height)?;
+
+        let (tw, th) = (tile.width, tile.height);
+        let mut values = Vec::with_capacity(tile.len());
+
+        for y in ty..ty + th {
+            for x in tx..tx + tw {
+                let value = f(x, y);
+                data[grid.index(x, y)] = value;
+                values.push(value);
+            }
+        }
+
+        writer.write_block(band, (tx, ty), (tw, th), values)?;
+
+        if tx + tw == width {
+            writer.flush()?;
+            rendered(observer, ty + th, height)?;
+        }
+    }
+
+    Ok(data)
+}
+
+/// Evaluates `f` for every pixel (x, y) of the rows from `start` of a band of `grid`, writing
+/// them to `band` of `writer` `strip` rows at a time and calling `done` with the number of rows
+/// written after each strip. `observer` is told of the rows done, and can cancel. Returns the
+/// pixels of the rows from `start`.
+#[instrument(name = "write", skip_all, fields(start))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_strips(
+    grid: &GridDefinition,
+    start: usize,
+    strip: usize,
+    writer: &mut RasterWriter,
+    band: usize,
+    observer: Option<&Observer>,
+    mut f: impl FnMut(usize, usize) -> f64,
+    mut done: impl FnMut(usize) -> Result<()>,
+) -> Result<Vec<f64>> {
+    let (width, height) = (grid.width, grid.height);
+    let mut data = Vec::with_capacity(width * (height - start.min(height)));
+
+    for sy in (start..height).step_by(strip) {
+        let rows = strip.min(height - sy);
+        let mut values = Vec::with_capacity(width * rows);
+
+        for y in sy..sy + rows {
+            values.extend((0..width).map(|x| f(x, y)));
+            rendered(observer, y + 1, height)?;
+        }
+
+        data.extend_from_slice(&values);
+        writer.write_block(band, (0, sy), (width, rows), values)?;
+        writer.flush()?;
+
+        done(sy + rows)?;
+    }
+
+    Ok(data)
+}
+
+/// Reads the first `rows` rows of `band` (0 based) of the raster at `path`.
+pub fn read_rows(path: &Path, band: usize, rows: usize) -> Result<Vec<f64>> {
+    let ds = Dataset::open(path)?;
+    let (width, _) = ds.raster_size();
+
+    let buffer =
+        ds.rasterband(band + 1)?
+            .read_as::<f64>((0, 0), (width, rows), (width, rows), None)?;
+
+    Ok(buffer.data().to_vec())
+}
+
+/// Extent and resolution of the raster at `path`, from its geotransform, e.g. to align an
+/// output with it. It has to have square, unrotated pixels.
+pub fn raster_extent(path: &Path) -> Result<(Bounds, f64)> {
+    let ds = Dataset::open(path)?;
+    let [x0, dx, rx, y0, ry, dy] = ds.geo_transform()?;
+    let (width, height) = ds.raster_size();
+
+    if rx != 0.0 || ry != 0.0 || (dx.abs() - dy.abs()).abs() > dx.abs() * 1e-9 {
+        return Err(Error::Unsupported(format!(
+            "the extent of {}, its pixels are rotated or not square",
+            path.display()
+        )));
+    }
+
+    let res = dx.abs();
+    let (x1, y1) = (x0 + dx * width as f64, y0 + dy * height as f64);
+    let mut bounds = Bounds {
+        min: Vector {
+            x: x0.min(x1),
+            y: y0.min(y1),
+            z: f64::MIN,
+        },
+        max: Vector {
+            x: x0.max(x1),
+            y: y0.max(y1),
+            z: f64::MAX,
+        },
+    };
+
+    // Rounding can leave the far edges a hair past the last cells, adding a column or row.
+    if get_raster_size(&bounds, res) != (width, height) {
+        bounds.max.x -= res * 1e-6;
+        bounds.max.y -= res * 1e-6;
+    }
+
+    Ok((bounds, res))
+}
+
+/// Writes a raster attribute table naming and coloring the `categories` of `band` (0 based) of
+/// the raster at `path`, so GIS display them without manual styling. Drivers without native
+/// attribute tables keep it in a `.aux.xml` sidecar.
+pub fn write_categories(path: &Path, band: usize, categories: &[Category]) -> Result<()> {
+    let ds = Dataset::open_ex(
+        path,
+        DatasetOptions {
+            open_flags: GdalOpenFlags::GDAL_OF_RASTER | GdalOpenFlags::GDAL_OF_UPDATE,
+            ..Default::default()
+        },
+    )?;
+    let rb = ds.rasterband(band + 1)?;
+
+    let columns = [
+        (
+            "Value",
+            GDALRATFieldType::GFT_Integer,
+            GDALRATFieldUsage::GFU_MinMax,
+        ),
+        (
+            "Class",
+            GDALRATFieldType::GFT_String,
+            GDALRATFieldUsage::GFU_Name,
+        ),
+        (
+            "Red",
+            GDALRATFieldType::GFT_Integer,
+            GDALRATFieldUsage::GFU_Red,
+        ),
+        (
+            "Green",
+            GDALRATFieldType::GFT_Integer,
+            GDALRATFieldUsage::GFU_Green,
+        ),
+        (
+            "Blue",
+            GDALRATFieldType::GFT_Integer,
+            GDALRATFieldUsage::GFU_Blue,
+        ),
+    ];
+    let columns = columns
+        .into_iter()
+        .map(|(name, field, usage)| Ok((CString::new(name)?, field, usage)))
+        .collect::<core::result::Result<Vec<_>, NulError>>()
+        .map_err(|e| Error::ShouldntHappen(e.to_string()))?;
+    let names = categories
+        .iter()
+        .map(|c| CString::new(c.name))
+        .collect::<core::result::Result<Vec<_>, NulError>>()
+        .map_err(|e| Error::ShouldntHappen(e.to_string()))?;
+
+    let err = unsafe {
+        let rat = gdal_sys::GDALCreateRasterAttributeTable();
+
+        for (name, field, usage) in columns.iter() {
+            gdal_sys::GDALRATCreateColumn(rat, name.as_ptr(), *field, *usage);
+        }
+
+        gdal_sys::GDALRATSetRowCount(rat, categories.len() as i32);
+        for (row, (category, name)) in categories.iter().zip(names.iter()).enumerate() {
+            let row = row as i32;
+            let [red, green, blue] = category.color.map(i32::from);
+
+            gdal_sys::GDALRATSetValueAsInt(rat, row, 0, category.value);
+            gdal_sys::GDALRATSetValueAsString(rat, row, 1, name.as_ptr());
+            gdal_sys::GDALRATSetValueAsInt(rat, row, 2, red);
+            gdal_sys::GDALRATSetValueAsInt(rat, row, 3, green);
+            gdal_sys::GDALRATSetValueAsInt(rat, row, 4, blue);
+        }
+
+        // The band keeps a copy.
+        let err = gdal_sys::GDALSetDefaultRAT(rb.c_rasterband(), rat);
+        gdal_sys::GDALDestroyRasterAttributeTable(rat);
+
+        err
+    };
+
+    if err != CPLErr::CE_None {
+        return Err(Error::Unsupported(format!(
+            "raster attribute tables with the {} driver",
+            ds.driver().short_name()
+        )));
+    }
+
+    Ok(())
+}
+
+/// What the values of bands measure, so downstream users know their units and numeric
+/// significance.
+#[derive(Debug, Clone, Default)]
+pub struct Units {
+    /// Unit name, e.g. 'metre'.
+    pub unit: Option<String>,
+    /// Name of the vertical CRS, for absolute elevations.
+    pub vertical_crs: Option<String>,
+    /// Smallest difference the values resolve, the coarsest LAS Z scale factor of the input.
+    pub precision: Option<f64>,
+}
+
+/// Sets `units` on `rb`, as the band unit type and the VERTICAL_CRS and VERTICAL_PRECISION
+/// metadata items. They're descriptive, what the driver can't keep is left out with a warning.
+fn set_units(rb: &mut RasterBand, units: &Units, driver: &str) {
+    if let Some(unit) = &units.unit {
+        let kept = CString::new(unit.as_str()).is_ok_and(|unit| {
+            let err = unsafe { gdal_sys::GDALSetRasterUnitType(rb.c_rasterband(), unit.as_ptr()) };
+            err == CPLErr::CE_None
+        });
+
+        if !kept {
+            warn!("Couldn't set the band unit '{unit}' with the {driver} driver, leaving it out");
+        }
+    }
+
+    let items = [
+        ("VERTICAL_CRS", units.vertical_crs.clone()),
+        ("VERTICAL_PRECISION", units.precision.map(|p| p.to_string())),
+    ];
+
+    for (key, value) in items {
+        if let Some(Err(e)) = value.map(|value| rb.set_metadata_item(key, &value, "")) {
+            warn!("Couldn't set {key} with the {driver} driver, leaving it out: {e}");
+        }
+    }
+}
+
+/// Writes a raster of `grid`, one raster band per band of the `band_type`, described by
+/// `info`.
+#[instrument(name = "write", skip_all, fields(path = %path.display()))]
+pub fn write_raster(
+    path: &Path,
+    grid: &GridDefinition,
+    nodata: f64,
+    bands: Vec<Band>,
+    band_type: BandType,
+    compress: Option<Compression>,
+    info: &RasterInfo,
+) -> Result<()> {
+    let names = bands
+        .iter()
+        .map(|b| b.name.clone())
+        .collect::<Vec<String>>();
+
+    let mut writer = RasterWriter::create(path, grid, nodata, &names, band_type, compress, info)?;
+    writer.write_bands(bands)?;
+    writer.finish()
+}
@@ -0,0 +1,304 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use las::{Color, Point, Reader};
+
+use crate::error::{Error, Result};
+use crate::input::buffer_points;
+use crate::ply::{Encoding, Scalar};
+
+/// A field of a PCD record, with `count` values of `scalar`.
+struct Field {
+    name: String,
+    scalar: Scalar,
+    count: usize,
+}
+
+/// Whether `path` is a PCD (Point Cloud Library) file.
+pub fn is_pcd(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("pcd"))
+}
+
+fn scalar(kind: &str, size: &str) -> Option<Scalar> {
+    match (kind, size) {
+        ("I", "1") => Some(Scalar::I8),
+        ("U", "1") => Some(Scalar::U8),
+        ("I", "2") => Some(Scalar::I16),
+        ("U", "2") => Some(Scalar::U16),
+        ("I", "4") => Some(Scalar::I32),
+        ("U", "4") => Some(Scalar::U32),
+        ("F", "4") => Some(Scalar::F32),
+        ("F", "8") => Some(Scalar::F64),
+        _ => None,
+    }
+}
+
+/// Reads the header up to and including the DATA line. Returns the fields, the number of points
+/// and whether the data is binary.
+fn read_header<R: BufRead>(read: &mut R, name: &str) -> Result<(Vec<Field>, u64, bool)> {
+    let invalid = |e: String| Error::Pcd(format!("{name}: {e}"));
+
+    let mut names = Vec::new();
+    let mut sizes = Vec::new();
+    let mut kinds = Vec::new();
+    let mut counts = Vec::new();
+    let mut points = None;
+
+    let mut line = String::new();
+
+    let binary = loop {
+        line.clear();
+        if read.read_line(&mut line)? == 0 {
+            return Err(invalid("missing DATA".to_string()));
+        }
+
+        let mut words = line.split_whitespace();
+        let values = |words: std::str::SplitWhitespace| -> Vec<String> {
+            words.map(|w| w.to_string()).collect()
+        };
+
+        match words.next() {
+            Some("FIELDS") => names = values(words),
+            Some("SIZE") => sizes = values(words),
+            Some("TYPE") => kinds = values(words),
+            Some("COUNT") => counts = values(words),
+            Some("POINTS") => points = words.next().and_then(|p| p.parse().ok()),
+            Some("DATA") => match words.next() {
+                Some("ascii") => break false,
+                Some("binary") => break true,
+                Some(data) => {
+                    return Err(Error::Unsupported(format!("PCD data '{data}' ({name})")))
+                }
+                None => return Err(invalid("missing the DATA type".to_string())),
+            },
+            // Comments, VERSION, WIDTH, HEIGHT, VIEWPOINT
+            _ => {}
+        }
+    };
+
+    if sizes.len() != names.len() || kinds.len() != names.len() {
+        return Err(invalid(
+            "FIELDS, SIZE and TYPE differ in length".to_string(),
+        ));
+    }
+
+    let fields = names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let scalar = scalar(&kinds[i], &sizes[i])
+                .ok_or_else(|| invalid(format!("unknown type {} {}", kinds[i], sizes[i])))?;
+
+            Ok(Field {
+                name,
+                scalar,
+                count: counts.get(i).and_then(|c| c.parse().ok()).unwrap_or(1),
+            })
+        })
+        .collect::<Result<Vec<Field>>>()?;
+
+    Ok((
+        fields,
+        points.ok_or(invalid("missing POINTS".to_string()))?,
+        binary,
+    ))
+}
+
+/// Reads an ASCII or binary PCD file, e.g. from mobile mapping or robotics, as points with x,
+/// y, z, intensity, GPS time (`timestamp`, `time` or `t`) and color (`rgb` or `rgba`, packed).
+/// Points without coordinates (NaN, in organized clouds) are skipped. The points are buffered
+/// in memory as LAS.
+pub fn read_pcd(path: &Path) -> Result<Reader> {
+    let name = path.display().to_string();
+    let mut read = BufReader::new(File::open(path)?);
+
+    let (fields, count, binary) = read_header(&mut read, &name)?;
+
+    // Index of the first value of each field within a record.
+    let mut starts = Vec::with_capacity(fields.len());
+    let mut values_per_record = 0;
+    for field in fields.iter() {
+        starts.push(values_per_record);
+        values_per_record += field.count;
+    }
+
+    let column = |names: &[&str]| {
+        fields
+            .iter()
+            .position(|f| names.contains(&f.name.as_str()))
+            .map(|i| (starts[i], fields[i].scalar))
+    };
+
+    let (Some((x, _)), Some((y, _)), Some((z, _))) =
+        (column(&["x"]), column(&["y"]), column(&["z"]))
+    else {
+        return Err(Error::Pcd(format!("{name}: points without x, y and z")));
+    };
+
+    let intensity = column(&["intensity"]);
+    let gps_time = column(&["timestamp", "time", "t"]);
+    let rgb = column(&["rgb", "rgba"]);
+
+    let record_size: usize = fields.iter().map(|f| f.scalar.size() * f.count).sum();
+    let mut record = vec![0u8; record_size];
+    let mut values = vec![0.0; values_per_record];
+    let mut line = String::new();
+
+    let mut points = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        match binary {
+            true => {
+                read.read_exact(&mut record)?;
+
+                let mut offset = 0;
+                let mut value = values.iter_mut();
+
+                for field in fields.iter() {
+                    let size = field.scalar.size();
+
+                    for _ in 0..field.count {
+                        if let Some(value) = value.next() {
+                            *value = field
+                                .scalar
+                                .decode(&record[offset..offset + size], Encoding::LittleEndian);
+                        }
+                        offset += size;
+                    }
+                }
+            }
+            false => {
+                line.clear();
+                if read.read_line(&mut line)? == 0 {
+                    break;
+                }
+
+                let mut words = line.split_whitespace();
+                for value in values.iter_mut() {
+                    *value = words
+                        .next()
+                        .and_then(|w| w.parse().ok())
+                        .unwrap_or(f64::NAN);
+                }
+            }
+        }
+
+        if [values[x], values[y], values[z]]
+            .iter()
+            .any(|v| !v.is_finite())
+        {
+            continue;
+        }
+
+        // 0x00RRGGBB, usually stored in the bits of a float.
+        let color = rgb.map(|(i, scalar)| {
+            let packed = match scalar {
+                Scalar::F32 => (values[i] as f32).to_bits(),
+                _ => values[i] as u32,
+            };
+            let channel = |shift: u32| ((packed >> shift) & 0xff) as u16 * 257;
+
+            Color {
+                red: channel(16),
+                green: channel(8),
+                blue: channel(0),
+            }
+        });
+
+        points.push(Point {
+            x: values[x],
+            y: values[y],
+            z: values[z],
+            intensity: intensity.map_or(0, |(i, _)| values[i] as u16),
+            gps_time: gps_time.map(|(i, _)| values[i]),
+            color,
+            ..Default::default()
+        });
+    }
+
+    buffer_points(&name, points)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::util::temp_path;
+
+    const HEADER: &str = "# .PCD v0.7\nVERSION 0.7\nFIELDS x y z intensity rgb\n\
+        SIZE 4 4 4 2 4\nTYPE F F F U U\nCOUNT 1 1 1 1 1\nWIDTH 3\nHEIGHT 1\n\
+        VIEWPOINT 0 0 0 1 0 0 0\nPOINTS 3\n";
+
+    fn header_error(text: &str) -> Error {
+        read_header(&mut Cursor::new(text), "test.pcd")
+            .err()
+            .expect("an invalid header")
+    }
+
+    #[test]
+    fn headers() {
+        let (fields, points, binary) = read_header(
+            &mut Cursor::new(format!("{HEADER}DATA binary\n")),
+            "test.pcd",
+        )
+        .unwrap();
+
+        assert_eq!(points, 3);
+        assert!(binary);
+        assert_eq!(
+            fields.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(),
+            ["x", "y", "z", "intensity", "rgb"]
+        );
+        assert_eq!(fields[3].scalar, Scalar::U16);
+        assert_eq!(fields[4].count, 1);
+
+        assert!(matches!(
+            header_error("FIELDS x y z\nSIZE 4 4\nTYPE F F F\nPOINTS 1\nDATA ascii\n"),
+            Error::Pcd(e) if e == "test.pcd: FIELDS, SIZE and TYPE differ in length"
+        ));
+        assert!(matches!(
+            header_error("FIELDS x\nSIZE 4\nTYPE F\nDATA ascii\n"),
+            Error::Pcd(e) if e == "test.pcd: missing POINTS"
+        ));
+        assert!(matches!(
+            header_error("FIELDS x\nSIZE 4\nTYPE F\nPOINTS 1\n"),
+            Error::Pcd(e) if e == "test.pcd: missing DATA"
+        ));
+        assert!(matches!(
+            header_error("FIELDS x\nSIZE 4\nTYPE F\nPOINTS 1\nDATA binary_compressed\n"),
+            Error::Unsupported(_)
+        ));
+    }
+
+    #[test]
+    fn read() {
+        let path = temp_path("read.pcd");
+        std::fs::write(
+            &path,
+            format!("{HEADER}DATA ascii\n1 2 3 100 16744448\nnan nan nan 0 0\n4 5 6 200 255\n"),
+        )
+        .unwrap();
+
+        let mut reader = read_pcd(&path).unwrap();
+        let points: Vec<Point> = reader.points().map(|p| p.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].x, points[0].y, points[0].z), (1.0, 2.0, 3.0));
+        assert_eq!(points[0].intensity, 100);
+        assert_eq!(
+            points[0].color,
+            Some(Color {
+                red: 255 * 257,
+                green: 128 * 257,
+                blue: 0,
+            })
+        );
+        assert_eq!(points[1].intensity, 200);
+        assert_eq!(points[1].color.map(|c| c.blue), Some(255 * 257));
+    }
+}
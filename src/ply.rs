@@ -0,0 +1,434 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use las::{Color, Point, Reader};
+
+use crate::error::{Error, Result};
+use crate::input::buffer_points;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// A binary number type, shared with other binary point formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::I64 | Scalar::U64 | Scalar::F64 => 8,
+        }
+    }
+
+    /// Decodes a binary value of this type.
+    pub fn decode(&self, bytes: &[u8], encoding: Encoding) -> f64 {
+        macro_rules! decode {
+            ($t:ty) => {{
+                let bytes = bytes.try_into().unwrap_or_default();
+                match encoding {
+                    Encoding::BigEndian => <$t>::from_be_bytes(bytes) as f64,
+                    _ => <$t>::from_le_bytes(bytes) as f64,
+                }
+            }};
+        }
+
+        match self {
+            Scalar::I8 => decode!(i8),
+            Scalar::U8 => decode!(u8),
+            Scalar::I16 => decode!(i16),
+            Scalar::U16 => decode!(u16),
+            Scalar::I32 => decode!(i32),
+            Scalar::U32 => decode!(u32),
+            Scalar::I64 => decode!(i64),
+            Scalar::U64 => decode!(u64),
+            Scalar::F32 => decode!(f32),
+            Scalar::F64 => decode!(f64),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    /// A list with its count and item types, e.g. the vertex indices of a face.
+    List(Scalar, Scalar),
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: u64,
+    properties: Vec<Property>,
+}
+
+/// Whether `path` is a PLY file.
+pub fn is_ply(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("ply"))
+}
+
+/// Reads the header, up to and including `end_header`.
+fn read_header<R: BufRead>(read: &mut R, name: &str) -> Result<(Encoding, Vec<Element>)> {
+    let invalid = |e: &str| Error::Ply(format!("{name}: {e}"));
+
+    let mut line = String::new();
+    read.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid("not a PLY file"));
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    loop {
+        line.clear();
+        if read.read_line(&mut line)? == 0 {
+            return Err(invalid("missing end_header"));
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words[..] {
+            ["end_header"] => break,
+            ["format", format, _] => {
+                encoding = Some(match format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    _ => return Err(invalid(&format!("unknown format '{format}'"))),
+                })
+            }
+            ["element", element, count] => elements.push(Element {
+                name: element.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid(&format!("invalid element count '{count}'")))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, _] => {
+                let (Some(count), Some(item)) = (Scalar::parse(count), Scalar::parse(item)) else {
+                    return Err(invalid(&format!("unknown list type '{}'", line.trim())));
+                };
+
+                elements
+                    .last_mut()
+                    .ok_or(invalid("property before any element"))?
+                    .properties
+                    .push(Property::List(count, item));
+            }
+            ["property", scalar, property] => {
+                let scalar = Scalar::parse(scalar)
+                    .ok_or_else(|| invalid(&format!("unknown property type '{scalar}'")))?;
+
+                elements
+                    .last_mut()
+                    .ok_or(invalid("property before any element"))?
+                    .properties
+                    .push(Property::Scalar(property.to_string(), scalar));
+            }
+            // comment, obj_info
+            _ => {}
+        }
+    }
+
+    Ok((encoding.ok_or(invalid("missing format"))?, elements))
+}
+
+/// Reads the scalar properties of one element, lists are skipped.
+fn read_element<R: BufRead>(
+    read: &mut R,
+    encoding: Encoding,
+    element: &Element,
+    values: &mut Vec<f64>,
+) -> Result<()> {
+    values.clear();
+
+    if encoding == Encoding::Ascii {
+        let mut line = String::new();
+        read.read_line(&mut line)?;
+        let mut words = line.split_whitespace();
+
+        for property in element.properties.iter() {
+            let mut next = || -> f64 {
+                words
+                    .next()
+                    .and_then(|w| w.parse().ok())
+                    .unwrap_or(f64::NAN)
+            };
+
+            match property {
+                Property::Scalar(..) => values.push(next()),
+                Property::List(..) => {
+                    for _ in 0..next() as usize {
+                        next();
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    let mut buf = [0u8; 8];
+    let mut read_scalar = |scalar: &Scalar| -> Result<f64> {
+        let bytes = &mut buf[..scalar.size()];
+        read.read_exact(bytes)?;
+        Ok(scalar.decode(bytes, encoding))
+    };
+
+    for property in element.properties.iter() {
+        match property {
+            Property::Scalar(_, scalar) => values.push(read_scalar(scalar)?),
+            Property::List(count, item) => {
+                for _ in 0..read_scalar(count)? as usize {
+                    read_scalar(item)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the vertices of an ASCII or binary PLY file, e.g. from photogrammetry, as points with
+/// x, y, z, intensity (`intensity` or `scalar_intensity`) and color (`red`, `green`, `blue`).
+/// Other elements are skipped. The points are buffered in memory as LAS.
+pub fn read_ply(path: &Path) -> Result<Reader> {
+    let name = path.display().to_string();
+    let mut read = BufReader::new(File::open(path)?);
+
+    let (encoding, elements) = read_header(&mut read, &name)?;
+
+    let mut points = Vec::new();
+    let mut values = Vec::new();
+
+    for element in elements.iter() {
+        if element.name != "vertex" {
+            for _ in 0..element.count {
+                read_element(&mut read, encoding, element, &mut values)?;
+            }
+            continue;
+        }
+
+        let scalars: Vec<(&str, Scalar)> = element
+            .properties
+            .iter()
+            .filter_map(|p| match p {
+                Property::Scalar(name, scalar) => Some((name.as_str(), *scalar)),
+                Property::List(..) => None,
+            })
+            .collect();
+        let column = |names: &[&str]| scalars.iter().position(|(n, _)| names.contains(n));
+
+        let (Some(x), Some(y), Some(z)) = (column(&["x"]), column(&["y"]), column(&["z"])) else {
+            return Err(Error::Ply(format!("{name}: vertices without x, y and z")));
+        };
+
+        let intensity = column(&["intensity", "scalar_intensity"]);
+        let colors = [
+            column(&["red", "diffuse_red", "r"]),
+            column(&["green", "diffuse_green", "g"]),
+            column(&["blue", "diffuse_blue", "b"]),
+        ];
+
+        // 8 bit colors are scaled to the 16 bits of LAS.
+        let color_scale = match colors[0].map(|c| scalars[c].1) {
+            Some(Scalar::U8) => 257.0,
+            _ => 1.0,
+        };
+
+        for _ in 0..element.count {
+            read_element(&mut read, encoding, element, &mut values)?;
+
+            let color = match colors {
+                [Some(r), Some(g), Some(b)] => Some(Color {
+                    red: (values[r] * color_scale) as u16,
+                    green: (values[g] * color_scale) as u16,
+                    blue: (values[b] * color_scale) as u16,
+                }),
+                _ => None,
+            };
+
+            points.push(Point {
+                x: values[x],
+                y: values[y],
+                z: values[z],
+                intensity: intensity.map_or(0, |i| values[i] as u16),
+                color,
+                ..Default::default()
+            });
+        }
+
+        // Later elements, e.g. faces, aren't needed.
+        break;
+    }
+
+    buffer_points(&name, points)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::util::temp_path;
+
+    const HEADER: &str = "ply\nformat ascii 1.0\ncomment made by hand\nelement vertex 2\n\
+        property float x\nproperty float y\nproperty float z\nproperty uchar red\n\
+        property uchar green\nproperty uchar blue\nelement face 1\n\
+        property list uchar int vertex_indices\nend_header\n";
+
+    fn header_error(text: &str) -> String {
+        match read_header(&mut Cursor::new(text), "test.ply") {
+            Err(Error::Ply(e)) => e,
+            result => panic!("expected a PLY error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn headers() {
+        let (encoding, elements) = read_header(&mut Cursor::new(HEADER), "test.ply").unwrap();
+
+        assert_eq!(encoding, Encoding::Ascii);
+        assert_eq!(elements.len(), 2);
+        assert_eq!(
+            (elements[0].name.as_str(), elements[0].count),
+            ("vertex", 2)
+        );
+        assert_eq!(elements[0].properties.len(), 6);
+        assert!(matches!(
+            elements[1].properties[..],
+            [Property::List(Scalar::U8, Scalar::I32)]
+        ));
+
+        assert_eq!(header_error("PLY\n"), "test.ply: not a PLY file");
+        assert_eq!(
+            header_error("ply\nformat ascii 1.0\n"),
+            "test.ply: missing end_header"
+        );
+        assert_eq!(
+            header_error("ply\nproperty float x\nend_header\n"),
+            "test.ply: property before any element"
+        );
+        assert_eq!(
+            header_error("ply\nelement vertex 1\nend_header\n"),
+            "test.ply: missing format"
+        );
+        assert_eq!(
+            header_error("ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\nend_header\n"),
+            "test.ply: unknown property type 'half'"
+        );
+    }
+
+    #[test]
+    fn elements() {
+        let element = Element {
+            name: "face".to_string(),
+            count: 1,
+            properties: vec![
+                Property::Scalar("a".to_string(), Scalar::F32),
+                Property::List(Scalar::U8, Scalar::I32),
+                Property::Scalar("b".to_string(), Scalar::U16),
+            ],
+        };
+        let mut values = Vec::new();
+
+        read_element(
+            &mut Cursor::new("1.5 3 7 8 9 2\n"),
+            Encoding::Ascii,
+            &element,
+            &mut values,
+        )
+        .unwrap();
+        assert_eq!(values, [1.5, 2.0]);
+
+        let mut binary = 1.5f32.to_be_bytes().to_vec();
+        binary.extend([2, 0, 0, 0, 7, 0, 0, 0, 8]);
+        binary.extend(300u16.to_be_bytes());
+
+        read_element(
+            &mut Cursor::new(binary),
+            Encoding::BigEndian,
+            &element,
+            &mut values,
+        )
+        .unwrap();
+        assert_eq!(values, [1.5, 300.0]);
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(
+            Scalar::I16.decode(&[0xfe, 0xff], Encoding::LittleEndian),
+            -2.0
+        );
+        assert_eq!(
+            Scalar::U16.decode(&[0x01, 0x02], Encoding::BigEndian),
+            258.0
+        );
+        assert_eq!(
+            Scalar::F64.decode(&2.5f64.to_le_bytes(), Encoding::LittleEndian),
+            2.5
+        );
+    }
+
+    #[test]
+    fn read() {
+        let path = temp_path("read.ply");
+        std::fs::write(
+            &path,
+            format!("{HEADER}1.5 2.25 3 255 128 0\n-1 -2 -3 0 0 255\n3 0 1 2\n"),
+        )
+        .unwrap();
+
+        let mut reader = read_ply(&path).unwrap();
+        let points: Vec<Point> = reader.points().map(|p| p.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].x, points[0].y, points[0].z), (1.5, 2.25, 3.0));
+        assert_eq!(
+            points[0].color,
+            Some(Color {
+                red: 255 * 257,
+                green: 128 * 257,
+                blue: 0,
+            })
+        );
+        assert_eq!((points[1].x, points[1].y, points[1].z), (-1.0, -2.0, -3.0));
+    }
+}
@@ -0,0 +1,582 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use las::{Bounds, Header};
+use serde::Deserialize;
+use tracing::{info, instrument};
+
+use crate::binning::{Binner, BinningParams};
+use crate::error::{Error, Result};
+use crate::filter::{class_set_parser, ClassSet, Filter, PointFilter};
+use crate::input::Input;
+use crate::output::{
+    preflight, render, write_categories, write_raster, Band, BandType, Compression, RasterInfo,
+    LAS_CLASSES,
+};
+use crate::report::Report;
+use crate::service::Observer;
+use crate::triangulation::{build, schedule_parser, Schedule, TinPoints, TriangulationParams};
+use crate::util::GridDefinition;
+use crate::{function_parser, variable_parser, Function, Variable, NODATA};
+
+#[derive(Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    #[default]
+    Bin,
+    Triangulate,
+}
+
+/// A product of a manifest, e.g.
+///
+/// ```toml
+/// [[product]]
+/// output = "dtm.tif"
+/// func = ["min"]
+/// class = 2
+///
+/// [[product]]
+/// output = "dsm.tif"
+/// method = "triangulate"
+/// freeze_distance = 1.5
+/// insertion_buffer = "3.0..0.5"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProductSpec {
+    output: PathBuf,
+    #[serde(default)]
+    method: Method,
+    /// Default: z
+    var: Option<String>,
+    /// Classification codes to keep, on top of the global filters, a code or a list as on the
+    /// command line, e.g. "2,9".
+    class: Option<ClassSpec>,
+
+    /// Binning functions as on the command line, one band each. Default: median
+    #[serde(default)]
+    func: Vec<String>,
+    #[serde(default)]
+    weight_by_returns: bool,
+
+    /// Triangulation options, see `Commands::Triangulate`.
+    freeze_distance: Option<f64>,
+    insertion_buffer: Option<String>,
+    max_edge: Option<f64>,
+    max_area: Option<f64>,
+    max_vertices: Option<usize>,
+    tin_error: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ClassSpec {
+    Code(u8),
+    List(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    product: Vec<ProductSpec>,
+}
+
+enum Kind {
+    Bin {
+        funcs: Vec<Function>,
+        params: BinningParams<'static>,
+    },
+    /// A triangulation, by index into the distinct triangulations of the manifest.
+    Tin {
+        tin: usize,
+        max_edge: Option<f64>,
+        max_area: Option<f64>,
+    },
+}
+
+struct Product {
+    output: PathBuf,
+    var: Variable,
+    class: Option<ClassSet>,
+    kind: Kind,
+}
+
+/// What makes a triangulation distinct. Products differing only in the edge/area limits, which
+/// apply when sampling, share a triangulation.
+#[derive(PartialEq)]
+struct TinKey {
+    var: Variable,
+    class: Option<ClassSet>,
+    freeze_distance: f64,
+    insertion_buffer: (f64, f64),
+    max_vertices: Option<usize>,
+    tin_error: Option<f64>,
+}
+
+impl TinKey {
+    fn params(&self) -> TriangulationParams {
+        TriangulationParams {
+            freeze_distance: self.freeze_distance,
+            insertion_buffer: Schedule {
+                start: self.insertion_buffer.0,
+                end: self.insertion_buffer.1,
+            },
+            max_edge: None,
+            max_area: None,
+            seeds: Vec::new(),
+            seed_constraints: false,
+            max_vertices: self.max_vertices,
+            tin_error: self.tin_error,
+            chunk_size: None,
+            voxel: None,
+        }
+    }
+}
+
+impl Product {
+    fn band_count(&self) -> usize {
+        match &self.kind {
+            Kind::Bin { funcs, .. } => funcs.len(),
+            Kind::Tin { .. } => 1,
+        }
+    }
+}
+
+impl Product {
+    /// A product from its spec, adding its triangulation to `tins` unless an identical one is
+    /// already there.
+    fn from_spec(
+        spec: ProductSpec,
+        strict: bool,
+        headers: &[&Header],
+        tins: &mut Vec<TinKey>,
+    ) -> Result<Self> {
+        let invalid = |e: String| Error::Manifest(format!("{}: {e}", spec.output.display()));
+
+        let var = match &spec.var {
+            Some(var) => variable_parser(var).map_err(invalid)?.resolve(headers)?,
+            None => Variable::Z,
+        };
+
+        let class = match &spec.class {
+            Some(ClassSpec::Code(code)) => {
+                Some(class_set_parser(&code.to_string()).map_err(invalid)?)
+            }
+            Some(ClassSpec::List(list)) => Some(class_set_parser(list).map_err(invalid)?),
+            None => None,
+        };
+
+        let kind = match spec.method {
+            Method::Bin => {
+                let mut funcs = spec
+                    .func
+                    .iter()
+                    .map(|f| function_parser(f))
+                    .collect::<core::result::Result<Vec<Function>, String>>()
+                    .map_err(invalid)?;
+
+                if funcs.is_empty() {
+                    funcs.push(Function::Median);
+                }
+
+                Kind::Bin {
+                    params: BinningParams {
+                        funcs: funcs.clone(),
+                        breaklines: None,
+                        weight_by_returns: spec.weight_by_returns,
+                        swath_edge_angle: None,
+                        strict,
+                        focal_radius: None,
+                        carry: None,
+                        min_count: 1,
+                    },
+                    funcs,
+                }
+            }
+            Method::Triangulate => {
+                let required = |field: &str| invalid(format!("'{field}' is required"));
+
+                let insertion_buffer = schedule_parser(
+                    spec.insertion_buffer
+                        .as_deref()
+                        .ok_or_else(|| required("insertion_buffer"))?,
+                )
+                .map_err(invalid)?;
+
+                let key = TinKey {
+                    var,
+                    class: class.clone(),
+                    freeze_distance: spec
+                        .freeze_distance
+                        .ok_or_else(|| required("freeze_distance"))?,
+                    insertion_buffer: (insertion_buffer.start, insertion_buffer.end),
+                    max_vertices: spec.max_vertices,
+                    tin_error: spec.tin_error,
+                };
+
+                let tin = match tins.iter().position(|t| *t == key) {
+                    Some(tin) => tin,
+                    None => {
+                        tins.push(key);
+                        tins.len() - 1
+                    }
+                };
+
+                Kind::Tin {
+                    tin,
+                    max_edge: spec.max_edge,
+                    max_area: spec.max_area,
+                }
+            }
+        };
+
+        Ok(Self {
+            output: spec.output,
+            var,
+            class,
+            kind,
+        })
+    }
+}
+
+/// Criterion of points not of a product's classes.
+const PRODUCT_CLASS: &str = "product class";
+
+/// Whether `point` is of one of `classes`, if given.
+fn class_matches(classes: &Option<ClassSet>, point: &las::Point) -> bool {
+    classes
+        .as_ref()
+        .is_none_or(|c| c.contains(u8::from(point.classification)))
+}
+
+/// Builds every product of the manifest at `path` from a single read of `input`. Products
+/// triangulating the same points with the same parameters share one triangulation.
+#[instrument(name = "products", skip_all, fields(manifest = %path.display()))]
+#[allow(clippy::too_many_arguments)]
+pub fn run_products(
+    path: &Path,
+    mut input: Input,
+    bounds: Bounds,
+    res: f64,
+    filter: &PointFilter,
+    nodata: f64,
+    compress: Option<Compression>,
+    strict: bool,
+    noise: Option<Filter>,
+    info: &RasterInfo,
+    observer: Option<&Observer>,
+) -> Result<()> {
+    let manifest: Manifest =
+        toml::from_str(&fs::read_to_string(path)?).map_err(|e| Error::Manifest(e.to_string()))?;
+
+    let headers: Vec<&Header> = input.headers().collect();
+    let mut tins: Vec<TinKey> = Vec::new();
+    let products = manifest
+        .product
+        .into_iter()
+        .map(|spec| Product::from_spec(spec, strict, &headers, &mut tins))
+        .collect::<Result<Vec<Product>>>()?;
+
+    let grid = GridDefinition::new(bounds, res);
+
+    // Surface output problems before the read rather than after.
+    for product in products.iter() {
+        preflight(
+            &product.output,
+            &grid,
+            product.band_count(),
+            BandType::F64,
+            compress,
+        )?;
+    }
+
+    let mut binners: Vec<Option<(Binner, Report)>> = products
+        .iter()
+        .map(|p| match &p.kind {
+            Kind::Bin { params, .. } => {
+                Some((Binner::new(grid, p.var, params), Report::for_filter(filter)))
+            }
+            Kind::Tin { .. } => None,
+        })
+        .collect();
+
+    let mut tin_points: Vec<(TinPoints, Report)> = tins
+        .iter()
+        .map(|t| {
+            (
+                TinPoints::new(t.var, 0, None, None),
+                Report::for_filter(filter),
+            )
+        })
+        .collect();
+
+    info!(
+        "Building {} products, {} triangulation(s)...",
+        products.len(),
+        tins.len()
+    );
+    let mut report = Report::default();
+    // High noise is left out of the triangulations only, binned products keep it.
+    let noise = noise.map(|noise| {
+        let label = noise.to_string();
+        (noise, label)
+    });
+
+    while let Some(point) = input.next_point(&mut report)? {
+        for (product, binner) in products.iter().zip(binners.iter_mut()) {
+            let Some((binner, product_report)) = binner else {
+                continue;
+            };
+
+            if !class_matches(&product.class, &point) {
+                product_report.filtered(PRODUCT_CLASS);
+                continue;
+            }
+
+            binner.add(&point, filter, product_report)?;
+        }
+
+        for (key, (points, tin_report)) in tins.iter().zip(tin_points.iter_mut()) {
+            if !class_matches(&key.class, &point) {
+                tin_report.filtered(PRODUCT_CLASS);
+                continue;
+            }
+
+            if let Some((noise, label)) = &noise {
+                if !noise.matches(&point) {
+                    tin_report.filtered(label);
+                    continue;
+                }
+            }
+
+            points.add(&point, filter, tin_report)?;
+        }
+    }
+
+    report.log();
+
+    let mut surfaces = Vec::with_capacity(tins.len());
+    for (i, (key, (points, tin_report))) in tins.iter().zip(tin_points).enumerate() {
+        info!(
+            "Triangulation {} of {}, {} points used, {} filtered",
+            i + 1,
+            tins.len(),
+            tin_report.points_used,
+            tin_report.points_filtered
+        );
+
+        surfaces.push(build(points, &key.params(), observer)?);
+    }
+
+    for (product, binner) in products.iter().zip(binners) {
+        info!("Writing {}", product.output.display());
+
+        let mut bands = match (&product.kind, binner) {
+            (Kind::Bin { funcs, .. }, Some((binner, product_report))) => {
+                info!(
+                    "{} points used, {} filtered, {} outside of the extent",
+                    product_report.points_used,
+                    product_report.points_filtered,
+                    product_report.points_out_of_extent
+                );
+
+                let bins = binner.finish();
+
+                funcs
+                    .iter()
+                    .map(|func| {
+                        let data = render(&grid, None, observer, |x, y| {
+                            bins.collapse(func, grid.index(x, y))
+                        })?;
+
+                        Ok(Band::new(format!("{func:?}"), data))
+                    })
+                    .collect::<Result<Vec<Band>>>()?
+            }
+            (
+                Kind::Tin {
+                    tin,
+                    max_edge,
+                    max_area,
+                },
+                _,
+            ) => {
+                let surface = &mut surfaces[*tin];
+                surface.set_limits(*max_edge, *max_area);
+
+                let data = render(&grid, None, observer, |x, y| surface.pixel(&grid, x, y))?;
+
+                vec![Band::new(format!("{:?}", product.var), data)]
+            }
+            (Kind::Bin { .. }, None) => {
+                return Err(Error::ShouldntHappen(
+                    "Binned product without bins".to_string(),
+                ))
+            }
+        };
+
+        if let Some(exclude) = &filter.exclude {
+            for band in bands.iter_mut() {
+                exclude.mask(&mut band.data, &grid, NODATA);
+            }
+        }
+
+        if let Some(clip) = &filter.clip {
+            for band in bands.iter_mut() {
+                clip.clip(&mut band.data, &grid, NODATA);
+            }
+        }
+
+        write_raster(
+            &product.output,
+            &grid,
+            nodata,
+            bands,
+            BandType::F64,
+            compress,
+            info,
+        )?;
+
+        if let Kind::Bin { funcs, .. } = &product.kind {
+            for (i, func) in funcs.iter().enumerate() {
+                if func.is_categorical()
+                    && func.variable().unwrap_or(product.var) == Variable::Classification
+                {
+                    write_categories(&product.output, i, LAS_CLASSES)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use las::point::Classification;
+
+    use super::*;
+
+    /// The products of a manifest, and the distinct triangulations they need.
+    fn products(manifest: &str) -> Result<(Vec<Product>, Vec<TinKey>)> {
+        let manifest: Manifest =
+            toml::from_str(manifest).map_err(|e| Error::Manifest(e.to_string()))?;
+
+        let mut tins = Vec::new();
+        let products = manifest
+            .product
+            .into_iter()
+            .map(|spec| Product::from_spec(spec, false, &[], &mut tins))
+            .collect::<Result<Vec<Product>>>()?;
+
+        Ok((products, tins))
+    }
+
+    #[test]
+    fn manifests() {
+        let (products, tins) = products(
+            r#"
+            [[product]]
+            output = "dtm.tif"
+            func = ["min", "max"]
+            class = 2
+
+            [[product]]
+            output = "intensity.tif"
+            var = "intensity"
+            class = "2,9"
+
+            [[product]]
+            output = "dsm.tif"
+            method = "triangulate"
+            freeze_distance = 1.5
+            insertion_buffer = "3.0..0.5"
+
+            [[product]]
+            output = "dsm_short_edges.tif"
+            method = "triangulate"
+            freeze_distance = 1.5
+            insertion_buffer = "3.0..0.5"
+            max_edge = 10.0
+
+            [[product]]
+            output = "ground.tif"
+            method = "triangulate"
+            class = 2
+            freeze_distance = 1.5
+            insertion_buffer = "3.0..0.5"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            products.iter().map(|p| p.band_count()).collect::<Vec<_>>(),
+            [2, 1, 1, 1, 1]
+        );
+        assert_eq!(products[1].var, Variable::Intensity);
+        assert_eq!(products[1].class.as_ref().unwrap().to_string(), "2,9");
+
+        // The edge limit applies when sampling, so the DSMs share a triangulation.
+        assert_eq!(tins.len(), 2);
+        assert!(matches!(products[2].kind, Kind::Tin { tin: 0, .. }));
+        assert!(matches!(
+            products[3].kind,
+            Kind::Tin {
+                tin: 0,
+                max_edge: Some(_),
+                ..
+            }
+        ));
+        assert!(matches!(products[4].kind, Kind::Tin { tin: 1, .. }));
+    }
+
+    #[test]
+    fn invalid_manifests() {
+        let missing = products(
+            r#"
+            [[product]]
+            output = "dsm.tif"
+            method = "triangulate"
+            insertion_buffer = "3.0"
+            "#,
+        );
+        assert!(
+            matches!(missing, Err(Error::Manifest(e)) if e == "dsm.tif: 'freeze_distance' is required")
+        );
+
+        let unknown = products(
+            r#"
+            [[product]]
+            output = "dtm.tif"
+            funcs = ["min"]
+            "#,
+        );
+        assert!(matches!(unknown, Err(Error::Manifest(_))));
+
+        let func = products(
+            r#"
+            [[product]]
+            output = "dtm.tif"
+            func = ["nope"]
+            "#,
+        );
+        assert!(matches!(func, Err(Error::Manifest(_))));
+    }
+
+    #[test]
+    fn product_classes() {
+        let ground = las::Point {
+            classification: Classification::Ground,
+            ..Default::default()
+        };
+        let water = las::Point {
+            classification: Classification::Water,
+            ..Default::default()
+        };
+
+        assert!(class_matches(&None, &water));
+        let classes = Some(class_set_parser("2").unwrap());
+        assert!(class_matches(&classes, &ground));
+        assert!(!class_matches(&classes, &water));
+    }
+}
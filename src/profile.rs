@@ -0,0 +1,88 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::error::{Error, Result};
+use crate::filter::{filter_parser, Filter};
+
+/// A filter chain saved under a name, for standard recipes shared by a team.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Filter steps in order, as given to `--filter`.
+    #[serde(default)]
+    pub filters: Vec<String>,
+    /// Polygons whose points are dropped.
+    pub exclude_polygons: Option<PathBuf>,
+}
+
+/// Directory of saved profiles: `$LAS_RASTERIZER_PROFILES`, or `las-rasterizer/profiles` in
+/// the user's config directory.
+fn profile_dir() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os("LAS_RASTERIZER_PROFILES") {
+        return Ok(PathBuf::from(dir));
+    }
+
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok_or(Error::Profile(
+            "no config directory, set LAS_RASTERIZER_PROFILES".to_string(),
+        ))?;
+
+    Ok(config.join("las-rasterizer").join("profiles"))
+}
+
+fn profile_path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\', '.']) {
+        return Err(Error::Profile(format!("invalid profile name '{name}'")));
+    }
+
+    Ok(profile_dir()?.join(format!("{name}.toml")))
+}
+
+impl Profile {
+    pub fn new(chain: &[Filter], exclude_polygons: Option<PathBuf>) -> Self {
+        Self {
+            filters: chain.iter().map(|f| f.to_string()).collect(),
+            exclude_polygons,
+        }
+    }
+
+    /// Loads the profile saved as `name`.
+    pub fn load(name: &str) -> Result<Self> {
+        let path = profile_path(name)?;
+
+        let text = fs::read_to_string(&path).map_err(|e| {
+            Error::Profile(format!("couldn't read '{name}' ({}): {e}", path.display()))
+        })?;
+
+        toml::from_str(&text).map_err(|e| Error::Profile(format!("'{name}': {e}")))
+    }
+
+    /// Saves the profile as `name`, replacing any saved before.
+    pub fn save(&self, name: &str) -> Result<()> {
+        let path = profile_path(name)?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let text = toml::to_string(self).map_err(|e| Error::Profile(e.to_string()))?;
+        fs::write(&path, text)?;
+
+        info!("Saved profile '{name}' to {}", path.display());
+        Ok(())
+    }
+
+    /// The filter chain.
+    pub fn chain(&self) -> Result<Vec<Filter>> {
+        self.filters
+            .iter()
+            .map(|f| filter_parser(f).map_err(Error::Profile))
+            .collect()
+    }
+}
@@ -0,0 +1,86 @@
+use crate::fallback::Fallback;
+use crate::output::Category;
+
+/// The method a pixel's value came from, as written to the `--provenance` raster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provenance {
+    Nodata = 0,
+    Bin = 1,
+    Tin = 2,
+    Idw = 3,
+    Spline = 4,
+    /// Filled by an idw `--fallback`.
+    FillIdw = 5,
+    /// Filled by a nearest `--fallback`.
+    FillNearest = 6,
+}
+
+impl From<Fallback> for Provenance {
+    fn from(fallback: Fallback) -> Self {
+        match fallback {
+            Fallback::Idw(_) => Provenance::FillIdw,
+            Fallback::Nearest(_) => Provenance::FillNearest,
+        }
+    }
+}
+
+/// The provenance codes, for the raster attribute table.
+pub const PROVENANCE: &[Category] = &[
+    Category {
+        value: Provenance::Nodata as i32,
+        name: "NODATA",
+        color: [0, 0, 0],
+    },
+    Category {
+        value: Provenance::Bin as i32,
+        name: "Bin",
+        color: [26, 150, 65],
+    },
+    Category {
+        value: Provenance::Tin as i32,
+        name: "TIN",
+        color: [43, 131, 186],
+    },
+    Category {
+        value: Provenance::Idw as i32,
+        name: "IDW",
+        color: [171, 221, 164],
+    },
+    Category {
+        value: Provenance::Spline as i32,
+        name: "Spline",
+        color: [128, 205, 193],
+    },
+    Category {
+        value: Provenance::FillIdw as i32,
+        name: "IDW fill",
+        color: [253, 174, 97],
+    },
+    Category {
+        value: Provenance::FillNearest as i32,
+        name: "Nearest fill",
+        color: [215, 25, 28],
+    },
+];
+
+/// The provenance code of each pixel of `data`, which `method` produced unless `fills` has the
+/// fallback that filled it.
+pub fn provenance(
+    data: &[f64],
+    method: Provenance,
+    fills: &[Option<Fallback>],
+    nodata: f64,
+) -> Vec<f64> {
+    data.iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let provenance = match (*value == nodata, fills.get(i).copied().flatten()) {
+                (true, _) => Provenance::Nodata,
+                (false, Some(fallback)) => fallback.into(),
+                (false, None) => method,
+            };
+
+            provenance as u8 as f64
+        })
+        .collect()
+}
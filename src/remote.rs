@@ -0,0 +1,354 @@
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path as ObjectPath;
+use object_store::ObjectStore;
+use tokio::runtime::Runtime;
+use tracing::debug;
+
+use crate::error::{Error, Result};
+
+/// Bytes fetched per request. Large enough to amortize the request latency over sequential
+/// reads, small enough to not fetch much beyond a COPC node.
+const BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Whether an input path is a remote URL, http(s):// or s3://.
+pub fn is_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|p| {
+        p.starts_with("http://") || p.starts_with("https://") || p.starts_with("s3://")
+    })
+}
+
+/// Somewhere byte ranges of a remote file can be fetched from.
+trait RangeSource: Send + 'static {
+    /// Fetches bytes `start..end`.
+    fn fetch(&self, start: u64, end: u64) -> io::Result<Vec<u8>>;
+}
+
+/// A file served over HTTP(S), with range request support.
+struct Http {
+    url: String,
+}
+
+/// The first and last byte and the total length of a `Content-Range: bytes <first>-<last>/<len>`
+/// header. The length is None if the server doesn't know it (`*`).
+fn content_range(header: &str) -> Option<(u64, u64, Option<u64>)> {
+    let (range, len) = header.strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+
+    Some((
+        first.trim().parse().ok()?,
+        last.trim().parse().ok()?,
+        match len.trim() {
+            "*" => None,
+            len => Some(len.parse().ok()?),
+        },
+    ))
+}
+
+impl Http {
+    /// Requests bytes `start..end`, which the server has to answer with exactly those bytes:
+    /// a server ignoring the range replies 200 with the whole file instead. Returns the
+    /// response and the total length it gives.
+    fn range(&self, start: u64, end: u64) -> core::result::Result<(ureq::Response, u64), String> {
+        let response = ureq::get(&self.url)
+            .set("Range", &format!("bytes={start}-{}", end - 1))
+            .call()
+            .map_err(|e| e.to_string())?;
+
+        if response.status() != 206 {
+            return Err(format!(
+                "the server doesn't support range requests (status {})",
+                response.status()
+            ));
+        }
+
+        match response.header("Content-Range").and_then(content_range) {
+            Some((first, last, Some(len))) if first == start && last == end - 1 => {
+                Ok((response, len))
+            }
+            _ => Err(format!(
+                "the server replied to a request for bytes {start}-{} with {:?}",
+                end - 1,
+                response.header("Content-Range")
+            )),
+        }
+    }
+
+    /// Opens `url` and returns its length, probing range support with a single byte request.
+    fn open(url: &str) -> Result<(Self, u64)> {
+        let http = Self {
+            url: url.to_string(),
+        };
+
+        let (_, len) = http
+            .range(0, 1)
+            .map_err(|e| Error::Remote(format!("{url}: {e}")))?;
+
+        Ok((http, len))
+    }
+}
+
+impl RangeSource for Http {
+    fn fetch(&self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity((end - start) as usize);
+
+        let (response, _) = self
+            .range(start, end)
+            .map_err(|e| io::Error::other(format!("{}: {e}", self.url)))?;
+        response.into_reader().read_to_end(&mut data)?;
+
+        if data.len() as u64 != end - start {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "{}: got {} bytes of the range {start}-{}",
+                    self.url,
+                    data.len(),
+                    end - 1
+                ),
+            ));
+        }
+
+        Ok(data)
+    }
+}
+
+/// An object in an S3 bucket, with credentials and region from the environment (AWS_*).
+struct S3 {
+    store: AmazonS3,
+    path: ObjectPath,
+    /// object_store is async, requests are run to completion one at a time.
+    runtime: Runtime,
+}
+
+impl S3 {
+    /// Opens `s3://bucket/key` and returns its length.
+    fn open(url: &str) -> Result<(Self, u64)> {
+        let invalid = |e: &dyn fmt::Display| Error::Remote(format!("{url}: {e}"));
+
+        let (bucket, key) = url
+            .trim_start_matches("s3://")
+            .split_once('/')
+            .ok_or_else(|| invalid(&"expected s3://bucket/key"))?;
+
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()
+            .map_err(|e| invalid(&e))?;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        let path = ObjectPath::from(key);
+        let meta = runtime
+            .block_on(store.head(&path))
+            .map_err(|e| invalid(&e))?;
+
+        Ok((
+            Self {
+                store,
+                path,
+                runtime,
+            },
+            meta.size as u64,
+        ))
+    }
+}
+
+impl RangeSource for S3 {
+    fn fetch(&self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        let bytes = self
+            .runtime
+            .block_on(
+                self.store
+                    .get_range(&self.path, start as usize..end as usize),
+            )
+            .map_err(io::Error::other)?;
+
+        Ok(bytes.to_vec())
+    }
+}
+
+/// A remote file read with range requests, one block at a time.
+pub struct RemoteReader {
+    url: String,
+    source: Box<dyn RangeSource>,
+    len: u64,
+    pos: u64,
+    /// The most recently fetched block and its offset.
+    block: Option<(u64, Vec<u8>)>,
+}
+
+impl fmt::Debug for RemoteReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteReader")
+            .field("url", &self.url)
+            .field("len", &self.len)
+            .field("pos", &self.pos)
+            .finish()
+    }
+}
+
+impl RemoteReader {
+    /// Opens an http(s):// URL, which has to support range requests, or an s3:// URL.
+    pub fn open(url: &str) -> Result<Self> {
+        let (source, len): (Box<dyn RangeSource>, u64) = match url.starts_with("s3://") {
+            true => {
+                let (source, len) = S3::open(url)?;
+                (Box::new(source), len)
+            }
+            false => {
+                let (source, len) = Http::open(url)?;
+                (Box::new(source), len)
+            }
+        };
+
+        Ok(Self {
+            url: url.to_string(),
+            source,
+            len,
+            pos: 0,
+            block: None,
+        })
+    }
+
+    /// Fetches the block containing `pos`, unless it's the current one.
+    fn fetch(&mut self, pos: u64) -> io::Result<()> {
+        let start = pos / BLOCK_SIZE * BLOCK_SIZE;
+
+        if self
+            .block
+            .as_ref()
+            .is_some_and(|(offset, _)| *offset == start)
+        {
+            return Ok(());
+        }
+
+        let end = (start + BLOCK_SIZE).min(self.len);
+        debug!("Fetching bytes {start}-{end} of {}", self.url);
+
+        self.block = Some((start, self.source.fetch(start, end)?));
+
+        Ok(())
+    }
+}
+
+impl Read for RemoteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.fetch(self.pos)?;
+
+        let Some((offset, data)) = &self.block else {
+            return Ok(0);
+        };
+
+        let from = (self.pos - offset) as usize;
+        let n = buf.len().min(data.len().saturating_sub(from));
+        buf[..n].copy_from_slice(&data[from..from + n]);
+        self.pos += n as u64;
+
+        Ok(n)
+    }
+}
+
+impl Seek for RemoteReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => pos as i64,
+            SeekFrom::End(offset) => self.len as i64 + offset,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+        };
+
+        if pos < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of the file",
+            ));
+        }
+
+        self.pos = pos as u64;
+
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// A file in memory, counting the ranges fetched from it.
+    struct Memory {
+        data: Vec<u8>,
+        fetches: Arc<AtomicUsize>,
+    }
+
+    impl RangeSource for Memory {
+        fn fetch(&self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+
+            Ok(self.data[start as usize..end as usize].to_vec())
+        }
+    }
+
+    #[test]
+    fn urls() {
+        assert!(is_url(Path::new("https://example.com/a.copc.laz")));
+        assert!(is_url(Path::new("s3://bucket/a.laz")));
+        assert!(!is_url(Path::new("tiles/a.laz")));
+        assert!(!is_url(Path::new("file:///a.laz")));
+    }
+
+    #[test]
+    fn content_ranges() {
+        assert_eq!(content_range("bytes 0-0/1234"), Some((0, 0, Some(1234))));
+        assert_eq!(content_range("bytes 10-19/*"), Some((10, 19, None)));
+        assert_eq!(content_range("bytes */1234"), None);
+        assert_eq!(content_range("items 0-0/1"), None);
+    }
+
+    #[test]
+    fn reads_blocks() {
+        let len = BLOCK_SIZE as usize + 10;
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let mut reader = RemoteReader {
+            url: "memory".to_string(),
+            source: Box::new(Memory {
+                data: data.clone(),
+                fetches: fetches.clone(),
+            }),
+            len: len as u64,
+            pos: 0,
+            block: None,
+        };
+
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[..4]);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+
+        // Across the end of the first block.
+        reader.seek(SeekFrom::Start(BLOCK_SIZE - 2)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[len - 12..len - 8]);
+        assert_eq!(fetches.load(Ordering::Relaxed), 2);
+
+        let mut rest = Vec::new();
+        reader.seek(SeekFrom::End(-3)).unwrap();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, data[len - 3..]);
+
+        assert!(reader.seek(SeekFrom::Current(-(len as i64) - 1)).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::filter::PointFilter;
+use crate::validation::Accuracy;
+
+/// Criterion of points without a value for the rasterized variable, e.g. GPS time in a point
+/// format without it.
+pub const MISSING_VALUE: &str = "missing value";
+
+/// Point accounting for a run.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// Points read from the input.
+    pub points_read: u64,
+    /// Points which failed to decode, skipped.
+    pub points_corrupt: u64,
+    /// Points rejected by filters.
+    pub points_filtered: u64,
+    /// Points rejected by each filter criterion, the first they failed.
+    pub points_filtered_by: BTreeMap<String, u64>,
+    /// Points outside of the raster extent, skipped.
+    pub points_out_of_extent: u64,
+    /// Points dropped by thinning.
+    pub points_thinned: u64,
+    /// Points withheld for cross-validation.
+    pub points_withheld: u64,
+    /// Points which contributed to the output.
+    pub points_used: u64,
+    /// Whether the output raster holds no data at all.
+    pub empty: bool,
+    /// First returns per unit area over the cells with any, with the spacing function.
+    pub nominal_pulse_density: Option<f64>,
+    /// 1 / sqrt(nominal pulse density).
+    pub nominal_pulse_spacing: Option<f64>,
+    /// Errors at the withheld points, with --cross-validate.
+    pub accuracy: Option<Accuracy>,
+}
+
+impl Report {
+    /// A report listing every criterion of `filter`, so the filters which rejected no points
+    /// show too.
+    pub fn for_filter(filter: &PointFilter) -> Self {
+        Self {
+            points_filtered_by: filter
+                .criteria()
+                .map(|criterion| (criterion.to_string(), 0))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Counts a point rejected by `criterion`.
+    pub fn filtered(&mut self, criterion: &str) {
+        self.points_filtered += 1;
+
+        match self.points_filtered_by.get_mut(criterion) {
+            Some(count) => *count += 1,
+            None => {
+                self.points_filtered_by.insert(criterion.to_string(), 1);
+            }
+        }
+    }
+
+    /// Counts a point at `x`, `y` outside of the raster extent, or fails on it if `strict`.
+    pub fn out_of_extent(&mut self, x: f64, y: f64, strict: bool) -> Result<()> {
+        match strict {
+            true => Err(Error::OutOfExtent(x, y)),
+            false => {
+                self.points_out_of_extent += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the report as JSON next to `output`, to `<output>.report.json`.
+    pub fn save(&self, output: &Path) -> Result<()> {
+        let mut name = output.file_name().map(OsString::from).unwrap_or_default();
+        name.push(".report.json");
+        let path = output.with_file_name(name);
+
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| Error::ShouldntHappen(e.to_string()))?;
+        fs::write(&path, json)?;
+
+        info!("Wrote the report to {}", path.display());
+
+        Ok(())
+    }
+
+    pub fn log(&self) {
+        info!("Points read: {}", self.points_read);
+        info!("Corrupt points skipped: {}", self.points_corrupt);
+        info!("Points filtered: {}", self.points_filtered);
+        for (criterion, count) in self.points_filtered_by.iter() {
+            info!("  by {criterion}: {count}");
+        }
+        if self.points_thinned > 0 {
+            info!("Points thinned: {}", self.points_thinned);
+        }
+        info!(
+            "Points outside of the extent: {}",
+            self.points_out_of_extent
+        );
+        info!("Points used: {}", self.points_used);
+
+        // Why a raster came out empty.
+        if self.points_used == 0 && self.points_read > 0 {
+            let (cause, count) = self
+                .points_filtered_by
+                .iter()
+                .map(|(criterion, count)| (criterion.as_str(), *count))
+                .chain([("the extent", self.points_out_of_extent)])
+                .max_by_key(|(_, count)| *count)
+                .unwrap_or(("the extent", 0));
+
+            warn!(
+                "No points were used, most of the {} read were dropped by {cause} ({count})",
+                self.points_read
+            );
+        }
+
+        if let (Some(density), Some(spacing)) =
+            (self.nominal_pulse_density, self.nominal_pulse_spacing)
+        {
+            info!("Nominal pulse density: {density:.3} pts/unit², spacing: {spacing:.3}");
+        }
+
+        if let Some(accuracy) = &self.accuracy {
+            info!("Points withheld: {}", self.points_withheld);
+            accuracy.log();
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Async entry points for running rasterizations inside a service, e.g. a web server. Jobs run
+//! on tokio's blocking thread pool, report their progress over a watch channel and can be
+//! cancelled. For a timeout, wrap `Job::wait` in `tokio::time::timeout`: dropping the job
+//! cancels it.
+
+use std::ffi::OsString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::error::{Error, Result};
+use crate::{execute, Cli};
+
+/// Points read or inserted between progress updates.
+const PROGRESS_EVERY: u64 = 100_000;
+
+/// What a job is busy with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading the input, counting points out of the total the input's headers claim.
+    #[default]
+    Reading,
+    /// Inserting points into a triangulation, counting points out of those collected.
+    Triangulating,
+    /// Evaluating the raster, counting rows out of its height.
+    Rendering,
+}
+
+/// How far the job is through its current stage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub stage: Stage,
+    pub done: u64,
+    pub total: u64,
+}
+
+/// The job's side of its progress and cancellation.
+#[derive(Clone)]
+pub(crate) struct Observer {
+    progress: watch::Sender<Progress>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Observer {
+    /// Publishes the progress every `PROGRESS_EVERY` points, or every row while rendering,
+    /// errors once cancelled.
+    pub(crate) fn observe(&self, stage: Stage, done: u64, total: u64) -> Result<()> {
+        self.check()?;
+
+        let every = match stage {
+            Stage::Reading | Stage::Triangulating => PROGRESS_EVERY,
+            Stage::Rendering => 1,
+        };
+
+        if done % every == 0 || done == total {
+            self.progress.send_replace(Progress { stage, done, total });
+        }
+
+        Ok(())
+    }
+
+    /// Errors once cancelled, for loops without a meaningful count.
+    pub(crate) fn check(&self) -> Result<()> {
+        match self.cancel.load(Ordering::Relaxed) {
+            true => Err(Error::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+/// A rasterization running on tokio's blocking thread pool.
+pub struct Job {
+    progress: watch::Receiver<Progress>,
+    cancel: Arc<AtomicBool>,
+    /// Cancels the job when dropped, e.g. along with a timed out `wait`.
+    _guard: CancelOnDrop,
+    handle: JoinHandle<Result<()>>,
+}
+
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Starts the command line `args` (the first being the program name) as a job. Invalid
+/// arguments are returned right away, as `Error::Usage`. Has to be called within a tokio
+/// runtime.
+pub fn spawn<I, T>(args: I) -> Result<Job>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let cli = Cli::parse_args(args)?;
+
+    let (progress, receiver) = watch::channel(Progress::default());
+    let cancel = Arc::new(AtomicBool::new(false));
+    let observer = Observer {
+        progress,
+        cancel: cancel.clone(),
+    };
+
+    let handle = tokio::task::spawn_blocking(move || execute(cli, Some(&observer)));
+
+    Ok(Job {
+        progress: receiver,
+        cancel: cancel.clone(),
+        _guard: CancelOnDrop(cancel),
+        handle,
+    })
+}
+
+impl Job {
+    /// The job's progress, updated as it goes.
+    pub fn progress(&self) -> watch::Receiver<Progress> {
+        self.progress.clone()
+    }
+
+    /// Asks the job to stop. It's checked for while reading the input, triangulating and
+    /// rendering, writing the output runs to completion.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Waits for the job to finish, with `Error::Cancelled` if it was cancelled.
+    pub async fn wait(mut self) -> Result<()> {
+        (&mut self.handle)
+            .await
+            .map_err(|e| Error::ShouldntHappen(format!("The job panicked: {e}")))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use las::{Builder, Point, Writer};
+    use tokio::runtime::Runtime;
+
+    use super::*;
+    use crate::util::temp_path;
+
+    /// Points in the input of test jobs, enough for several progress updates while reading.
+    const POINTS: u64 = 5 * PROGRESS_EVERY;
+
+    fn runtime() -> Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    /// Writes a LAS file of `POINTS` points, 10 per metre over 100 by 50 metres.
+    fn input(name: &str) -> PathBuf {
+        let path = temp_path(name);
+        let mut writer =
+            Writer::from_path(&path, Builder::from((1, 4)).into_header().unwrap()).unwrap();
+
+        for i in 0..POINTS {
+            writer
+                .write_point(Point {
+                    x: (i % 1000) as f64 / 10.0,
+                    y: (i / 1000) as f64 / 10.0,
+                    z: (i % 7) as f64,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        writer.close().unwrap();
+
+        path
+    }
+
+    fn bin(input: &Path, output: &Path) -> Job {
+        let args: Vec<OsString> = vec![
+            "las-rasterizer".into(),
+            "-i".into(),
+            input.into(),
+            "-r".into(),
+            "1".into(),
+            "bin".into(),
+            output.into(),
+        ];
+
+        spawn(args).unwrap()
+    }
+
+    fn observer() -> (Observer, watch::Receiver<Progress>) {
+        let (progress, receiver) = watch::channel(Progress::default());
+        let observer = Observer {
+            progress,
+            cancel: Arc::new(AtomicBool::new(false)),
+        };
+
+        (observer, receiver)
+    }
+
+    #[test]
+    fn observer_publishes_and_cancels() {
+        let (observer, receiver) = observer();
+        let progress = |stage, done, total| Progress { stage, done, total };
+
+        observer.observe(Stage::Reading, 1, 250_000).unwrap();
+        assert_eq!(*receiver.borrow(), Progress::default());
+
+        observer.observe(Stage::Reading, 200_000, 250_000).unwrap();
+        assert_eq!(
+            *receiver.borrow(),
+            progress(Stage::Reading, 200_000, 250_000)
+        );
+
+        observer.observe(Stage::Reading, 250_000, 250_000).unwrap();
+        assert_eq!(
+            *receiver.borrow(),
+            progress(Stage::Reading, 250_000, 250_000)
+        );
+
+        // Every row while rendering.
+        observer.observe(Stage::Rendering, 3, 50).unwrap();
+        assert_eq!(*receiver.borrow(), progress(Stage::Rendering, 3, 50));
+
+        observer.cancel.store(true, Ordering::Relaxed);
+        assert!(matches!(
+            observer.observe(Stage::Rendering, 4, 50),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(observer.check(), Err(Error::Cancelled)));
+        assert_eq!(*receiver.borrow(), progress(Stage::Rendering, 3, 50));
+    }
+
+    #[test]
+    fn job_reports_progress() {
+        let (input, output) = (input("progress.las"), temp_path("progress.tif"));
+
+        runtime().block_on(async {
+            let job = bin(&input, &output);
+            let mut progress = job.progress();
+
+            progress
+                .wait_for(|p| p.stage == Stage::Reading && p.done >= PROGRESS_EVERY)
+                .await
+                .unwrap();
+            assert_eq!(progress.borrow().total, POINTS);
+
+            job.wait().await.unwrap();
+
+            let last = *progress.borrow();
+            assert_eq!(last.stage, Stage::Rendering);
+            assert_eq!((last.done, last.total), (50, 50));
+        });
+
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn cancelled_job() {
+        let (input, output) = (input("cancelled.las"), temp_path("cancelled.tif"));
+
+        runtime().block_on(async {
+            let job = bin(&input, &output);
+
+            job.progress()
+                .wait_for(|p| p.done >= PROGRESS_EVERY)
+                .await
+                .unwrap();
+            job.cancel();
+
+            assert!(matches!(job.wait().await, Err(Error::Cancelled)));
+        });
+
+        std::fs::remove_file(&input).unwrap();
+        let _ = std::fs::remove_file(&output);
+    }
+
+    #[test]
+    fn dropped_job_is_cancelled() {
+        let (input, output) = (input("dropped.las"), temp_path("dropped.tif"));
+
+        runtime().block_on(async {
+            let job = bin(&input, &output);
+            let mut progress = job.progress();
+
+            progress
+                .wait_for(|p| p.done >= PROGRESS_EVERY)
+                .await
+                .unwrap();
+            drop(job);
+
+            // The job drops its end of the channel once it stops.
+            while progress.changed().await.is_ok() {}
+
+            let last = *progress.borrow();
+            assert_eq!(last.stage, Stage::Reading);
+            assert!(last.done < POINTS);
+        });
+
+        std::fs::remove_file(&input).unwrap();
+        let _ = std::fs::remove_file(&output);
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::VecDeque;
+
+use las::Bounds;
+use spade::{DelaunayTriangulation, HasPosition, Point2, Triangulation};
+use tracing::{info, instrument};
+
+use crate::error::Result;
+use crate::filter::PointFilter;
+use crate::input::Input;
+use crate::report::{Report, MISSING_VALUE};
+use crate::util::GridDefinition;
+use crate::{get_var, Variable, NODATA};
+
+/// Over-relaxation factor of the Gauss-Seidel iterations.
+const OVER_RELAXATION: f64 = 1.4;
+
+pub struct SplineParams {
+    /// Tension (0-1). 0 is the pure minimum curvature surface, 1 a harmonic surface without
+    /// overshoots between data.
+    pub tension: f64,
+    pub max_iterations: usize,
+    /// Largest change of an iteration at which the surface counts as converged.
+    pub convergence: f64,
+    /// Cells further than this from any data are set to NODATA.
+    pub max_distance: Option<f64>,
+    /// Error on points outside of the extent rather than skipping them.
+    pub strict: bool,
+}
+
+/// A cell with data, to interpolate the starting surface from.
+struct Constraint {
+    position: Point2<f64>,
+    value: f64,
+}
+
+impl HasPosition for Constraint {
+    type Scalar = f64;
+
+    fn position(&self) -> Point2<Self::Scalar> {
+        self.position
+    }
+}
+
+/// Grids the input with a minimum curvature surface in tension, as GMT's `surface`. Cells with
+/// points are fixed to their mean, the rest are relaxed towards a solution of
+/// (1 - T) * ∇⁴z - T * ∇²z = 0, starting from a linear interpolation of the fixed cells.
+/// Gives smooth surfaces from sparse data (e.g. bathymetric soundings or ground points) where
+/// triangulation leaves visible facets.
+#[instrument(name = "spline", skip_all, fields(res))]
+pub fn spline_surface(
+    mut input: Input,
+    bounds: Bounds,
+    res: f64,
+    filter: &PointFilter,
+    var: Variable,
+    params: &SplineParams,
+    report: &mut Report,
+) -> Result<Vec<f64>> {
+    let grid = GridDefinition::new(bounds, res);
+    let (width, height) = (grid.width, grid.height);
+    let mut sums = vec![(0.0, 0u32); width * height];
+
+    while let Some(point) = input.next_point(report)? {
+        if let Some(criterion) = filter.rejects(&point) {
+            report.filtered(criterion);
+            continue;
+        }
+
+        let value = get_var(&var, &point);
+        if value.is_nan() {
+            report.filtered(MISSING_VALUE);
+            continue;
+        }
+
+        let Some((x_idx, y_idx)) = grid.cell(point.x, point.y) else {
+            report.out_of_extent(point.x, point.y, params.strict)?;
+            continue;
+        };
+
+        report.points_used += 1;
+
+        let (sum, count) = &mut sums[y_idx * width + x_idx];
+        *sum += value;
+        *count += 1;
+    }
+
+    let fixed: Vec<Option<f64>> = sums
+        .into_iter()
+        .map(|(sum, count)| (count > 0).then(|| sum / count as f64))
+        .collect();
+
+    let constraints: Vec<Constraint> = fixed
+        .iter()
+        .enumerate()
+        .filter_map(|(i, value)| {
+            Some(Constraint {
+                position: Point2::new((i % width) as f64, (i / width) as f64),
+                value: (*value)?,
+            })
+        })
+        .collect();
+
+    if constraints.is_empty() {
+        return Ok(vec![NODATA; width * height]);
+    }
+
+    info!("Gridding {} cells with data...", constraints.len());
+
+    // Starting surface: linear within the hull of the data, the mean outside of it.
+    let mean = constraints.iter().map(|c| c.value).sum::<f64>() / constraints.len() as f64;
+    let t = DelaunayTriangulation::<Constraint>::bulk_load(constraints)?;
+    let interp = t.barycentric();
+
+    let mut values: Vec<f64> = fixed
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            value.unwrap_or_else(|| {
+                let position = Point2::new((i % width) as f64, (i / width) as f64);
+                interp
+                    .interpolate(|v| v.data().value, position)
+                    .unwrap_or(mean)
+            })
+        })
+        .collect();
+
+    relax(&mut values, &fixed, width, height, params);
+
+    if let Some(max_distance) = params.max_distance {
+        let distances = cell_distances(&fixed, width, height);
+
+        for (value, distance) in values.iter_mut().zip(distances) {
+            if distance as f64 * res > max_distance {
+                *value = NODATA;
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+/// Gauss-Seidel iterations of the finite difference equations on the cells without data. Edges
+/// are handled by clamping to the nearest cell.
+fn relax(
+    grid: &mut [f64],
+    fixed: &[Option<f64>],
+    width: usize,
+    height: usize,
+    params: &SplineParams,
+) {
+    let tension = params.tension.clamp(0.0, 1.0);
+    let denominator = 20.0 * (1.0 - tension) + 4.0 * tension;
+
+    let at = |grid: &[f64], x: isize, y: isize| {
+        let x = x.clamp(0, width as isize - 1) as usize;
+        let y = y.clamp(0, height as isize - 1) as usize;
+        grid[y * width + x]
+    };
+
+    for iteration in 1..=params.max_iterations {
+        let mut max_change: f64 = 0.0;
+
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let i = y as usize * width + x as usize;
+
+                if fixed[i].is_some() {
+                    continue;
+                }
+
+                let sides = at(grid, x - 1, y)
+                    + at(grid, x + 1, y)
+                    + at(grid, x, y - 1)
+                    + at(grid, x, y + 1);
+                let diagonals = at(grid, x - 1, y - 1)
+                    + at(grid, x + 1, y - 1)
+                    + at(grid, x - 1, y + 1)
+                    + at(grid, x + 1, y + 1);
+                let outer = at(grid, x - 2, y)
+                    + at(grid, x + 2, y)
+                    + at(grid, x, y - 2)
+                    + at(grid, x, y + 2);
+
+                let target = ((1.0 - tension) * (8.0 * sides - 2.0 * diagonals - outer)
+                    + tension * sides)
+                    / denominator;
+
+                let change = OVER_RELAXATION * (target - grid[i]);
+                grid[i] += change;
+                max_change = max_change.max(change.abs());
+            }
+        }
+
+        if max_change < params.convergence {
+            info!("Converged after {iteration} iterations");
+            return;
+        }
+
+        if iteration == params.max_iterations {
+            info!("Stopped after {iteration} iterations, the largest change was {max_change:.4}");
+        }
+    }
+}
+
+/// Distance in cells (chessboard) from every cell to the nearest cell with data.
+fn cell_distances(fixed: &[Option<f64>], width: usize, height: usize) -> Vec<usize> {
+    let mut distances = vec![usize::MAX; fixed.len()];
+    let mut queue = VecDeque::new();
+
+    for (i, value) in fixed.iter().enumerate() {
+        if value.is_some() {
+            distances[i] = 0;
+            queue.push_back(i);
+        }
+    }
+
+    while let Some(i) = queue.pop_front() {
+        let (x, y) = ((i % width) as isize, (i / width) as isize);
+
+        for (dx, dy) in [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ] {
+            let (nx, ny) = (x + dx, y + dy);
+
+            if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                continue;
+            }
+
+            let n = ny as usize * width + nx as usize;
+            if distances[n] == usize::MAX {
+                distances[n] = distances[i] + 1;
+                queue.push_back(n);
+            }
+        }
+    }
+
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use las::{Point, Vector};
+
+    use super::*;
+    use crate::input::buffer_points;
+
+    fn params(tension: f64, max_distance: Option<f64>) -> SplineParams {
+        SplineParams {
+            tension,
+            max_iterations: 10_000,
+            convergence: 1e-9,
+            max_distance,
+            strict: false,
+        }
+    }
+
+    /// A row of 5 cells of 1 m, gridded from Z 0 at the first cell and 4 at the last.
+    fn row(params: &SplineParams) -> Vec<f64> {
+        let at = |x: f64, z: f64| Point {
+            x,
+            y: 0.5,
+            z,
+            ..Default::default()
+        };
+        let points = vec![at(0.5, 0.0), at(4.5, 4.0)];
+        let input = Input::new(
+            vec![("test".to_string(), buffer_points("test", points).unwrap())],
+            0,
+        );
+
+        let bounds = Bounds {
+            min: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: 5.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        };
+
+        spline_surface(
+            input,
+            bounds,
+            1.0,
+            &PointFilter::new(Vec::new(), None, None),
+            Variable::Z,
+            params,
+            &mut Report::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn harmonic_between_data() {
+        let values = row(&params(1.0, None));
+
+        for (value, expected) in values.iter().zip([0.0, 1.0, 2.0, 3.0, 4.0]) {
+            assert!((value - expected).abs() < 1e-6, "{values:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_the_data() {
+        let values = row(&params(0.0, None));
+
+        assert_eq!((values[0], values[4]), (0.0, 4.0));
+        assert!(values.iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn nodata_far_from_data() {
+        let values = row(&params(1.0, Some(1.0)));
+
+        assert_eq!(values[2], NODATA);
+        assert_ne!(values[1], NODATA);
+        assert_ne!(values[3], NODATA);
+    }
+
+    #[test]
+    fn distances() {
+        let fixed = [Some(1.0), None, None, None, None];
+        assert_eq!(cell_distances(&fixed, 5, 1), [0, 1, 2, 3, 4]);
+
+        let mut fixed = [None; 9];
+        fixed[4] = Some(1.0);
+        assert_eq!(cell_distances(&fixed, 3, 3), [1, 1, 1, 1, 0, 1, 1, 1, 1]);
+    }
+}
@@ -0,0 +1,428 @@
+use std::fs;
+use std::io::{self, BufRead, ErrorKind, StdinLock};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use las::{Bounds, Point};
+use tracing::{info, instrument, warn};
+
+use crate::error::{Error, Result};
+use crate::filter::PointFilter;
+use crate::output::{write_raster, Band, BandType, Compression, RasterInfo};
+use crate::report::Report;
+use crate::text::{has_xyz, parse_line, TextColumns};
+use crate::util::GridDefinition;
+use crate::{get_var, Function, Variable, NODATA};
+
+/// Largest UDP datagram.
+const MAX_DATAGRAM: usize = 65_507;
+
+/// A live feed of text points, a point per line as in text point files.
+enum Feed {
+    Stdin(StdinLock<'static>),
+    /// Datagrams of one or more lines.
+    Udp(UdpSocket, Vec<u8>),
+}
+
+impl Feed {
+    /// Reads the next line, or datagram of lines, into `buf`. False once the feed has ended,
+    /// which a UDP feed never does: it gives up waiting after the socket's read timeout with
+    /// nothing read, so flushes stay on schedule while it's quiet.
+    fn read(&mut self, buf: &mut String) -> Result<bool> {
+        buf.clear();
+
+        match self {
+            Feed::Stdin(stdin) => Ok(stdin.read_line(buf)? > 0),
+            Feed::Udp(socket, datagram) => match socket.recv(datagram) {
+                Ok(len) => {
+                    buf.push_str(&String::from_utf8_lossy(&datagram[..len]));
+                    Ok(true)
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    Ok(true)
+                }
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+}
+
+/// How a stream is read and written.
+pub struct StreamParams<'a> {
+    /// UDP address to listen on, stdin if None.
+    pub listen: Option<SocketAddr>,
+    pub columns: &'a TextColumns,
+    /// Time between raster updates.
+    pub flush_every: Duration,
+    pub nodata: f64,
+    pub compress: Option<Compression>,
+    pub info: RasterInfo<'a>,
+    /// Fail on points outside of the extent, rather than counting them.
+    pub strict: bool,
+}
+
+/// Running statistics of every cell for a variable, so the feed's points needn't be kept.
+struct Accumulator {
+    var: Variable,
+    count: Vec<u32>,
+    sum: Vec<f64>,
+    min: Vec<f64>,
+    max: Vec<f64>,
+}
+
+impl Accumulator {
+    fn new(var: Variable, len: usize) -> Self {
+        Self {
+            var,
+            count: vec![0; len],
+            sum: vec![0.0; len],
+            min: vec![f64::INFINITY; len],
+            max: vec![f64::NEG_INFINITY; len],
+        }
+    }
+
+    fn add(&mut self, i: usize, value: f64) {
+        self.count[i] = self.count[i].saturating_add(1);
+        self.sum[i] += value;
+        self.min[i] = self.min[i].min(value);
+        self.max[i] = self.max[i].max(value);
+    }
+
+    /// The value of `func` for cell `i`, NODATA for empty cells.
+    fn value(&self, func: &Function, i: usize) -> f64 {
+        if self.count[i] == 0 {
+            return NODATA;
+        }
+
+        match func {
+            Function::Mean => self.sum[i] / self.count[i] as f64,
+            Function::Min | Function::TimeMin => self.min[i],
+            Function::Max | Function::TimeMax => self.max[i],
+            Function::Count => self.count[i] as f64,
+            Function::Range(None) | Function::TimeSpan => self.max[i] - self.min[i],
+            _ => NODATA,
+        }
+    }
+}
+
+/// Whether `func` can be updated point by point, from a count, sum, min and max per cell.
+fn is_running(func: &Function) -> bool {
+    matches!(
+        func,
+        Function::Mean
+            | Function::Min
+            | Function::Max
+            | Function::Count
+            | Function::Range(None)
+            | Function::TimeMin
+            | Function::TimeMax
+            | Function::TimeSpan
+    )
+}
+
+/// The stream's bands, kept up to date a cell at a time as points arrive.
+struct Live<'a> {
+    grid: GridDefinition,
+    funcs: &'a [Function],
+    accumulators: Vec<Accumulator>,
+    /// Per function, the accumulator of its variable.
+    func_vars: Vec<usize>,
+    bands: Vec<Vec<f64>>,
+    /// Cells with points since the last flush.
+    changed: Vec<usize>,
+    is_changed: Vec<bool>,
+}
+
+impl<'a> Live<'a> {
+    fn new(grid: GridDefinition, var: Variable, funcs: &'a [Function]) -> Self {
+        let len = grid.len();
+
+        let mut accumulators: Vec<Accumulator> = Vec::new();
+        let func_vars = funcs
+            .iter()
+            .map(|func| {
+                let var = func.variable().unwrap_or(var);
+
+                match accumulators.iter().position(|a| a.var == var) {
+                    Some(i) => i,
+                    None => {
+                        accumulators.push(Accumulator::new(var, len));
+                        accumulators.len() - 1
+                    }
+                }
+            })
+            .collect();
+
+        Self {
+            grid,
+            funcs,
+            accumulators,
+            func_vars,
+            bands: vec![vec![NODATA; len]; funcs.len()],
+            changed: Vec::new(),
+            is_changed: vec![false; len],
+        }
+    }
+
+    /// Adds a single point, if it passes `filter`.
+    fn add(
+        &mut self,
+        point: &Point,
+        filter: &PointFilter,
+        strict: bool,
+        report: &mut Report,
+    ) -> Result<()> {
+        if let Some(criterion) = filter.rejects(point) {
+            report.filtered(criterion);
+            return Ok(());
+        }
+
+        let Some((x_idx, y_idx)) = self.grid.cell(point.x, point.y) else {
+            return report.out_of_extent(point.x, point.y, strict);
+        };
+        let i = self.grid.index(x_idx, y_idx);
+
+        report.points_used += 1;
+
+        for accumulator in self.accumulators.iter_mut() {
+            let value = get_var(&accumulator.var, point);
+
+            // Missing values, e.g. GPS time in a point format without it.
+            if !value.is_nan() {
+                accumulator.add(i, value);
+            }
+        }
+
+        if !self.is_changed[i] {
+            self.is_changed[i] = true;
+            self.changed.push(i);
+        }
+
+        Ok(())
+    }
+
+    /// Brings the bands up to date with the cells changed since the last update.
+    fn update(&mut self) {
+        for i in self.changed.drain(..) {
+            self.is_changed[i] = false;
+
+            for (f, func) in self.funcs.iter().enumerate() {
+                self.bands[f][i] = self.accumulators[self.func_vars[f]].value(func, i);
+            }
+        }
+    }
+}
+
+/// Bins a live feed of points into `bounds` at `res`, rewriting the raster at `output` with the
+/// points binned so far every `flush_every` there are new points. Each update replaces the
+/// previous one in a single step, so readers never see a partial raster. Stdin is read until it
+/// ends, UDP until the process is stopped. Only the functions with running statistics (see
+/// `is_running`) are supported: the points aren't kept, and only changed cells are recomputed.
+#[instrument(name = "stream", skip_all, fields(res))]
+#[allow(clippy::too_many_arguments)]
+pub fn stream(
+    output: &Path,
+    bounds: Bounds,
+    res: f64,
+    filter: &PointFilter,
+    var: Variable,
+    funcs: &[Function],
+    params: &StreamParams,
+    report: &mut Report,
+) -> Result<()> {
+    if let Some(func) = funcs.iter().find(|f| !is_running(f)) {
+        return Err(Error::Unsupported(format!(
+            "--func {func:?} with stream, only mean, min, max, count, range and the time \
+             functions can be kept up to date without keeping every point"
+        )));
+    }
+
+    let mut feed = match params.listen {
+        Some(addr) => {
+            let socket = UdpSocket::bind(addr)?;
+            socket.set_read_timeout(Some(params.flush_every))?;
+            info!("Listening for points on udp://{addr}");
+
+            Feed::Udp(socket, vec![0; MAX_DATAGRAM])
+        }
+        None => {
+            info!("Reading points from stdin");
+            Feed::Stdin(io::stdin().lock())
+        }
+    };
+
+    let grid = GridDefinition::new(bounds, res);
+    let mut live = Live::new(grid, var, funcs);
+    let mut buf = String::new();
+    let (mut last_flush, mut pending) = (Instant::now(), false);
+
+    while feed.read(&mut buf)? {
+        for line in buf.lines() {
+            let Some(point) = parse_line(line, params.columns) else {
+                continue;
+            };
+
+            report.points_read += 1;
+            if !has_xyz(&point) {
+                warn!("Skipping '{}', no x/y/z", line.trim());
+                report.points_corrupt += 1;
+                continue;
+            }
+
+            live.add(&point, filter, params.strict, report)?;
+            pending = true;
+        }
+
+        if pending && last_flush.elapsed() >= params.flush_every {
+            flush(output, &mut live, params)?;
+            info!(
+                "{} points read, {} used",
+                report.points_read, report.points_used
+            );
+
+            (last_flush, pending) = (Instant::now(), false);
+        }
+    }
+
+    if pending {
+        flush(output, &mut live, params)?;
+    }
+
+    Ok(())
+}
+
+/// Hidden sibling of `output` with the same extension, so it's written with the same driver.
+fn partial_path(output: &Path) -> PathBuf {
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+
+    output.with_file_name(format!(".{name}"))
+}
+
+/// Writes the points binned so far, one band per binning function, and moves the raster over
+/// `output`.
+fn flush(output: &Path, live: &mut Live, params: &StreamParams) -> Result<()> {
+    live.update();
+
+    let bands = live
+        .funcs
+        .iter()
+        .zip(live.bands.iter())
+        .map(|(func, data)| Band::new(format!("{func:?}"), data.clone()))
+        .collect();
+
+    let partial = partial_path(output);
+    write_raster(
+        &partial,
+        &live.grid,
+        params.nodata,
+        bands,
+        BandType::F64,
+        params.compress,
+        &params.info,
+    )?;
+    fs::rename(partial, output)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use las::Vector;
+
+    use super::*;
+    use crate::filter::filter_parser;
+
+    /// Two cells of 1 m side by side.
+    fn grid() -> GridDefinition {
+        let bounds = Bounds {
+            min: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: 2.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        };
+
+        GridDefinition::new(bounds, 1.0)
+    }
+
+    fn at(x: f64, z: f64, gps_time: Option<f64>) -> Point {
+        Point {
+            x,
+            y: 0.5,
+            z,
+            gps_time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn running_functions() {
+        assert!(is_running(&Function::Mean));
+        assert!(is_running(&Function::TimeSpan));
+        assert!(!is_running(&Function::Median));
+    }
+
+    #[test]
+    fn updates_changed_cells() {
+        let funcs = [
+            Function::Mean,
+            Function::Max,
+            Function::Count,
+            Function::TimeMin,
+        ];
+        let mut live = Live::new(grid(), Variable::Z, &funcs);
+        let filter = PointFilter::new(vec![filter_parser("zrange=0:100").unwrap()], None, None);
+        let mut report = Report::default();
+
+        // Z and GPS time share the accumulators of their variables.
+        assert_eq!(live.accumulators.len(), 2);
+        assert_eq!(live.func_vars, [0, 0, 0, 1]);
+
+        for point in [
+            at(0.5, 1.0, Some(20.0)),
+            at(0.5, 3.0, Some(10.0)),
+            at(0.5, 500.0, Some(5.0)),
+            at(5.0, 1.0, None),
+        ] {
+            live.add(&point, &filter, false, &mut report).unwrap();
+        }
+
+        assert_eq!(report.points_used, 2);
+        assert_eq!(report.points_filtered, 1);
+        assert_eq!(report.points_out_of_extent, 1);
+        assert!(live
+            .add(&at(5.0, 1.0, None), &filter, true, &mut report)
+            .is_err());
+
+        // Nothing shows before an update.
+        assert_eq!(live.bands[0], [NODATA, NODATA]);
+
+        live.update();
+        assert_eq!(live.bands[0], [2.0, NODATA]);
+        assert_eq!(live.bands[1], [3.0, NODATA]);
+        assert_eq!(live.bands[2], [2.0, NODATA]);
+        assert_eq!(live.bands[3], [10.0, NODATA]);
+        assert!(live.changed.is_empty());
+
+        // Points without a GPS time leave its statistics be.
+        live.add(&at(1.5, 4.0, None), &filter, false, &mut report)
+            .unwrap();
+        live.update();
+        assert_eq!(live.bands[0], [2.0, 4.0]);
+        assert_eq!(live.bands[3], [10.0, NODATA]);
+    }
+
+    #[test]
+    fn partial_paths() {
+        assert_eq!(
+            partial_path(Path::new("out/live.tif")),
+            Path::new("out/.live.tif")
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use std::f64::consts::TAU;
+
+use las::point::{Classification, Format};
+use las::{Bounds, Builder, Point, Transform, Vector, Vlr, Writer};
+
+use crate::error::Result;
+
+/// A local engineering CRS in metres, so small scenes aren't taken for degrees.
+const SCENE_WKT: &str = "LOCAL_CS[\"Synthetic scene\",LOCAL_DATUM[\"Synthetic\",0],\
+    UNIT[\"metre\",1],AXIS[\"Easting\",EAST],AXIS[\"Northing\",NORTH]]";
+
+/// Deterministic pseudo random numbers (SplitMix64), so synthetic clouds are reproducible.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, via Box-Muller.
+    pub fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.uniform();
+        let v = self.uniform();
+
+        (-2.0 * u.ln()).sqrt() * (TAU * v).cos()
+    }
+}
+
+/// A flat roofed building.
+struct Building {
+    min: [f64; 2],
+    max: [f64; 2],
+    roof: f64,
+}
+
+/// A tree with a paraboloid crown.
+struct Tree {
+    center: [f64; 2],
+    radius: f64,
+    top: f64,
+}
+
+/// A square synthetic scene with its origin at (0, 0): sloping, rolling terrain with noise,
+/// buildings and trees.
+#[derive(Debug, Clone, Copy)]
+pub struct Scene {
+    /// Side length in map units.
+    pub size: f64,
+    /// Laser pulses per square map unit.
+    pub density: f64,
+    /// Standard deviation of the vertical noise.
+    pub noise: f64,
+    pub buildings: usize,
+    pub trees: usize,
+    pub seed: u64,
+}
+
+impl Scene {
+    /// A bare terrain scene of roughly `points` points at `density`.
+    pub fn with_points(points: u64, density: f64, seed: u64) -> Self {
+        Self {
+            size: (points as f64 / density).sqrt(),
+            density,
+            noise: 0.05,
+            buildings: 0,
+            trees: 0,
+            seed,
+        }
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        Bounds {
+            min: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: self.size,
+                y: self.size,
+                z: 100.0,
+            },
+        }
+    }
+
+    /// Number of laser pulses. Pulses hitting a tree have a second, ground return.
+    pub fn num_pulses(&self) -> u64 {
+        (self.size * self.size * self.density) as u64
+    }
+
+    /// Ground elevation, a gentle slope with rolling hills.
+    pub fn ground(&self, x: f64, y: f64) -> f64 {
+        10.0 + 0.02 * x + 0.01 * y + 2.0 * (x / 50.0).sin() * (y / 70.0).cos()
+    }
+
+    /// Randomly placed buildings and trees. Trees may overlap each other, but not buildings.
+    fn features(&self, rng: &mut Rng) -> (Vec<Building>, Vec<Tree>) {
+        let buildings: Vec<Building> = (0..self.buildings)
+            .map(|_| {
+                let (w, h) = (8.0 + rng.uniform() * 22.0, 8.0 + rng.uniform() * 22.0);
+                let min = [
+                    rng.uniform() * (self.size - w).max(0.0),
+                    rng.uniform() * (self.size - h).max(0.0),
+                ];
+                let max = [min[0] + w, min[1] + h];
+
+                // Roofs are level at the height of the footprint's highest ground.
+                let ground = [min, max, [min[0], max[1]], [max[0], min[1]]]
+                    .iter()
+                    .map(|[x, y]| self.ground(*x, *y))
+                    .fold(f64::MIN, f64::max);
+
+                Building {
+                    min,
+                    max,
+                    roof: ground + 4.0 + rng.uniform() * 12.0,
+                }
+            })
+            .collect();
+
+        let mut trees = Vec::with_capacity(self.trees);
+
+        // Give up on placing a tree after a few attempts, e.g. in a scene full of buildings.
+        for _ in 0..self.trees * 10 {
+            if trees.len() == self.trees {
+                break;
+            }
+
+            let center = [rng.uniform() * self.size, rng.uniform() * self.size];
+            let radius = 1.5 + rng.uniform() * 4.0;
+            let top = 5.0 + rng.uniform() * 25.0;
+
+            let overlaps = buildings.iter().any(|b| {
+                center[0] + radius > b.min[0]
+                    && center[0] - radius < b.max[0]
+                    && center[1] + radius > b.min[1]
+                    && center[1] - radius < b.max[1]
+            });
+
+            if !overlaps {
+                trees.push(Tree {
+                    center,
+                    radius,
+                    top,
+                });
+            }
+        }
+
+        (buildings, trees)
+    }
+
+    /// Returns of uniformly scattered pulses over the scene. Buildings are class 6, trees class 5
+    /// with a second return from the ground (class 2) below.
+    pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        let mut rng = Rng::new(self.seed);
+        let (buildings, trees) = self.features(&mut rng);
+
+        (0..self.num_pulses()).flat_map(move |i| {
+            let x = rng.uniform() * self.size;
+            let y = rng.uniform() * self.size;
+            let ground = self.ground(x, y);
+
+            let point = |z: f64, class: Classification, return_number: u8, returns: u8| Point {
+                x,
+                y,
+                z,
+                return_number,
+                number_of_returns: returns,
+                classification: class,
+                point_source_id: 1,
+                gps_time: Some(i as f64 * 1e-5),
+                ..Default::default()
+            };
+
+            let mut returns = Vec::with_capacity(2);
+
+            let building = buildings
+                .iter()
+                .find(|b| x >= b.min[0] && x < b.max[0] && y >= b.min[1] && y < b.max[1]);
+
+            // The highest crown above the pulse, if any.
+            let canopy = trees
+                .iter()
+                .filter_map(|t| {
+                    let d2 =
+                        ((x - t.center[0]).powi(2) + (y - t.center[1]).powi(2)) / t.radius.powi(2);
+
+                    (d2 < 1.0)
+                        .then(|| self.ground(t.center[0], t.center[1]) + t.top * (1.0 - 0.4 * d2))
+                })
+                .fold(None, |acc: Option<f64>, z| {
+                    Some(acc.map_or(z, |acc| acc.max(z)))
+                });
+
+            match (building, canopy) {
+                (Some(b), _) => returns.push(point(b.roof, Classification::Building, 1, 1)),
+                (None, Some(z)) => {
+                    returns.push(point(z, Classification::HighVegetation, 1, 2));
+                    returns.push(point(ground, Classification::Ground, 2, 2));
+                }
+                (None, None) => returns.push(point(ground, Classification::Ground, 1, 1)),
+            }
+
+            for p in returns.iter_mut() {
+                p.z += rng.normal() * self.noise;
+                p.intensity = (rng.uniform() * 1000.0) as u16;
+            }
+
+            returns
+        })
+    }
+
+    /// Writes the scene as LAS to `write`.
+    pub fn write<W: std::io::Write + std::io::Seek + Send + std::fmt::Debug + 'static>(
+        &self,
+        write: W,
+    ) -> Result<W> {
+        let mut builder = Builder::from((1, 4));
+        builder.point_format = Format::new(1)?;
+        builder.transforms = Vector {
+            x: Transform {
+                scale: 0.001,
+                offset: 0.0,
+            },
+            y: Transform {
+                scale: 0.001,
+                offset: 0.0,
+            },
+            z: Transform {
+                scale: 0.001,
+                offset: 0.0,
+            },
+        };
+        builder.vlrs.push(Vlr {
+            user_id: "LASF_Projection".to_string(),
+            record_id: 2112,
+            description: "OGC WKT".to_string(),
+            data: SCENE_WKT.as_bytes().to_vec(),
+        });
+
+        let mut writer = Writer::new(write, builder.into_header()?)?;
+
+        for point in self.points() {
+            writer.write_point(point)?;
+        }
+
+        Ok(writer.into_inner()?)
+    }
+}
@@ -0,0 +1,80 @@
+//! Logging of a run's events and stage spans (read, bin, triangulate, write...) to stderr, at the
+//! level set by `RUST_LOG` (default: info). Built with the `otel` feature, the spans are also
+//! exported over OTLP/HTTP when `OTEL_EXPORTER_OTLP_ENDPOINT` is set, so runs across a fleet can
+//! be profiled centrally. Services embedding the library can set up their own subscriber instead.
+
+use std::io;
+
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter};
+
+use crate::error::{Error, Result};
+
+/// Flushes the exported spans when dropped.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Error exporting spans: {e}");
+            }
+        }
+    }
+}
+
+/// Sets up the global subscriber, keep the returned `Telemetry` until the run is done.
+pub fn init() -> Result<Telemetry> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(io::stderr));
+
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::trace::TracerProvider as _;
+
+        let provider = match std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Some(_) => Some(otlp_provider()?),
+            None => None,
+        };
+        let layer = provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer().with_tracer(provider.tracer("las-rasterizer"))
+        });
+
+        registry
+            .with(layer)
+            .try_init()
+            .map_err(|e| Error::Telemetry(e.to_string()))?;
+
+        Ok(Telemetry { provider })
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        registry
+            .try_init()
+            .map_err(|e| Error::Telemetry(e.to_string()))?;
+
+        Ok(Telemetry {})
+    }
+}
+
+/// Exports spans as they end to the OTLP endpoint of the `OTEL_EXPORTER_OTLP_*` variables. Runs
+/// only have a span per stage, so they're sent one at a time without a background runtime.
+#[cfg(feature = "otel")]
+fn otlp_provider() -> Result<opentelemetry_sdk::trace::TracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| Error::Telemetry(e.to_string()))?;
+
+    Ok(opentelemetry_sdk::trace::TracerProvider::builder()
+        .with_simple_exporter(exporter)
+        .build())
+}
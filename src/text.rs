@@ -0,0 +1,231 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use clap::ValueEnum;
+use las::point::Classification;
+use las::{Point, Reader};
+
+use crate::error::{Error, Result};
+use crate::input::buffer_points;
+use crate::{PointValues, Variable};
+
+/// Variables of a text point file and their columns (0-based), e.g. from
+/// `x=1,y=2,z=3,intensity=4`.
+#[derive(Debug, Clone)]
+pub struct TextColumns(Vec<(Variable, usize)>);
+
+impl Default for TextColumns {
+    fn default() -> Self {
+        Self(vec![(Variable::X, 0), (Variable::Y, 1), (Variable::Z, 2)])
+    }
+}
+
+impl TextColumns {
+    fn column(&self, var: &Variable) -> Option<usize> {
+        self.0.iter().find(|(v, _)| v == var).map(|(_, c)| *c)
+    }
+}
+
+pub fn text_columns_parser(s: &str) -> core::result::Result<TextColumns, String> {
+    let mut columns = Vec::new();
+
+    for mapping in s.split(',') {
+        let (name, column) = mapping
+            .split_once('=')
+            .ok_or(format!("Expected variable=column, got '{mapping}'"))?;
+
+        let var = match name.trim().replace('_', "-").as_str() {
+            "classification" => Variable::Classification,
+            name => Variable::from_str(name, true)?,
+        };
+
+        let column: usize = column
+            .trim()
+            .parse()
+            .map_err(|e: std::num::ParseIntError| e.to_string())?;
+
+        if column == 0 {
+            return Err("Columns are numbered from 1".to_string());
+        }
+
+        columns.push((var, column - 1));
+    }
+
+    let columns = TextColumns(columns);
+
+    for var in [Variable::X, Variable::Y, Variable::Z] {
+        if columns.column(&var).is_none() {
+            return Err(format!("Missing a column for {var:?}"));
+        }
+    }
+
+    Ok(columns)
+}
+
+/// Whether `path` is a text point file rather than LAS/LAZ.
+pub fn is_text(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ["xyz", "csv", "txt", "pts"].contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// A line of a text point file.
+struct Row<'a> {
+    columns: &'a TextColumns,
+    /// Every field of the line, NaN where not a number.
+    fields: Vec<f64>,
+}
+
+impl PointValues for Row<'_> {
+    fn value(&self, var: &Variable) -> f64 {
+        self.columns
+            .column(var)
+            .and_then(|c| self.fields.get(c))
+            .copied()
+            .unwrap_or(f64::NAN)
+    }
+}
+
+impl Row<'_> {
+    fn to_point(&self) -> Point {
+        let value = |var: Variable| Some(self.value(&var)).filter(|v| v.is_finite());
+
+        Point {
+            x: self.value(&Variable::X),
+            y: self.value(&Variable::Y),
+            z: self.value(&Variable::Z),
+            intensity: value(Variable::Intensity).map_or(0, |v| v as u16),
+            gps_time: self
+                .columns
+                .column(&Variable::GpsTime)
+                .map(|_| self.value(&Variable::GpsTime)),
+            point_source_id: value(Variable::PointSourceId).map_or(0, |v| v as u16),
+            user_data: value(Variable::UserData).map_or(0, |v| v as u8),
+            classification: Classification::from(
+                value(Variable::Classification).map_or(1, |v| v as u8),
+            ),
+            ..Default::default()
+        }
+    }
+}
+
+/// The point of a line of fields separated by commas, semicolons or whitespace, None for blank
+/// lines and `#` comments. Fields which aren't numbers are NaN, see `has_xyz`.
+pub fn parse_line(line: &str, columns: &TextColumns) -> Option<Point> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let row = Row {
+        columns,
+        fields: line
+            .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .filter(|f| !f.is_empty())
+            .map(|f| f.parse().unwrap_or(f64::NAN))
+            .collect(),
+    };
+
+    Some(row.to_point())
+}
+
+/// Whether a parsed line has numbers for x, y and z, e.g. not a line of column names.
+pub fn has_xyz(point: &Point) -> bool {
+    [point.x, point.y, point.z].iter().all(|v| v.is_finite())
+}
+
+/// Reads a text point file with a point per line, fields separated by commas, semicolons or
+/// whitespace. Blank lines, `#` comments and a leading header line are skipped. The points are
+/// buffered in memory as LAS, so they go through the same pipeline as any other input.
+pub fn read_text(path: &Path, columns: &TextColumns) -> Result<Reader> {
+    let name = path.display().to_string();
+    let mut points = Vec::new();
+
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let Some(point) = parse_line(&line, columns) else {
+            continue;
+        };
+
+        if !has_xyz(&point) {
+            match points.is_empty() {
+                // Column names, or the point count of a .pts file.
+                true => continue,
+                false => {
+                    return Err(Error::TextInput(format!(
+                        "{name}, line {}: no x/y/z in '{}'",
+                        i + 1,
+                        line.trim()
+                    )))
+                }
+            }
+        }
+
+        points.push(point);
+    }
+
+    buffer_points(&name, points)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn columns() {
+        let columns = text_columns_parser("x=2, y=1, z=3, intensity=4, classification=5").unwrap();
+        assert_eq!(columns.column(&Variable::X), Some(1));
+        assert_eq!(columns.column(&Variable::Intensity), Some(3));
+        assert_eq!(columns.column(&Variable::Classification), Some(4));
+
+        assert_eq!(
+            text_columns_parser("x=1,y=2").unwrap_err(),
+            "Missing a column for Z"
+        );
+        assert_eq!(
+            text_columns_parser("x=0,y=1,z=2").unwrap_err(),
+            "Columns are numbered from 1"
+        );
+        assert!(text_columns_parser("x=1,y=2,z").is_err());
+    }
+
+    #[test]
+    fn lines() {
+        let columns = text_columns_parser("x=1,y=2,z=3,intensity=4,classification=5").unwrap();
+
+        let point = parse_line("1.5; 2.5\t3.5, 120 2", &columns).unwrap();
+        assert_eq!((point.x, point.y, point.z), (1.5, 2.5, 3.5));
+        assert_eq!(point.intensity, 120);
+        assert_eq!(point.classification, Classification::Ground);
+        assert!(point.gps_time.is_none());
+
+        assert!(parse_line("   ", &columns).is_none());
+        assert!(parse_line("# x y z", &columns).is_none());
+        assert!(!has_xyz(&parse_line("x,y,z", &columns).unwrap()));
+    }
+
+    #[test]
+    fn reads_a_file() {
+        let path = crate::util::temp_path("points.csv");
+        fs::write(&path, "x,y,z\n# A comment\n1,2,3\n\n4,5,6\n").unwrap();
+
+        let mut reader = read_text(&path, &TextColumns::default()).unwrap();
+        let mut points = Vec::new();
+        while let Some(point) = reader.read_point().unwrap() {
+            points.push((point.x, point.y, point.z));
+        }
+        assert_eq!(points, [(1.0, 2.0, 3.0), (4.0, 5.0, 6.0)]);
+
+        fs::write(&path, "1,2,3\n4,five,6\n").unwrap();
+        assert!(matches!(
+            read_text(&path, &TextColumns::default()),
+            Err(Error::TextInput(_))
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
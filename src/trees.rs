@@ -0,0 +1,340 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::path::Path;
+use std::ptr;
+
+use gdal::raster::Buffer;
+use gdal::vector::{
+    FieldValue, Geometry, LayerAccess, LayerOptions, OGRFieldType, OGRwkbGeometryType,
+};
+use gdal::DriverManager;
+use gdal_sys::CPLErr;
+use spade::{DelaunayTriangulation, FloatTriangulation, HasPosition, Point2, Triangulation};
+use tracing::info;
+
+use crate::error::{Error, Result};
+use crate::filter::PointFilter;
+use crate::input::Input;
+use crate::output::raster_driver;
+use crate::report::Report;
+use crate::util::GridDefinition;
+use crate::vector::vector_driver;
+use crate::NODATA;
+
+struct GroundPoint {
+    position: Point2<f64>,
+    z: f64,
+}
+
+impl HasPosition for GroundPoint {
+    type Scalar = f64;
+
+    fn position(&self) -> Point2<Self::Scalar> {
+        self.position
+    }
+}
+
+/// Variable window size for local maxima detection, `base + slope * height` in map units.
+#[derive(Debug, Clone, Copy)]
+pub struct Window {
+    pub base: f64,
+    pub slope: f64,
+}
+
+pub fn window_parser(s: &str) -> core::result::Result<Window, String> {
+    let parse = |num: &str| num.trim().parse::<f64>().map_err(|e| e.to_string());
+
+    match s.split_once(',') {
+        Some((base, slope)) => Ok(Window {
+            base: parse(base)?,
+            slope: parse(slope)?,
+        }),
+        None => Ok(Window {
+            base: parse(s)?,
+            slope: 0.0,
+        }),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TreeTop {
+    pub x: f64,
+    pub y: f64,
+    pub height: f64,
+}
+
+/// Builds a canopy height model: the highest point per cell minus a ground surface triangulated
+/// from points of `ground_class`.
+pub fn canopy_height(
+    mut input: Input,
+    grid: &GridDefinition,
+    filter: &PointFilter,
+    ground_class: u8,
+    strict: bool,
+    report: &mut Report,
+) -> Result<Vec<f64>> {
+    let mut dsm = vec![f64::MIN; grid.len()];
+    let mut ground = Vec::new();
+
+    while let Some(point) = input.next_point(report)? {
+        if let Some(criterion) = filter.rejects(&point) {
+            report.filtered(criterion);
+            continue;
+        }
+
+        if u8::from(point.classification) == ground_class {
+            ground.push(GroundPoint {
+                position: Point2::new(point.x, point.y),
+                z: point.z,
+            });
+        }
+
+        let Some((x_idx, y_idx)) = grid.cell(point.x, point.y) else {
+            report.out_of_extent(point.x, point.y, strict)?;
+            continue;
+        };
+
+        report.points_used += 1;
+
+        let cell = &mut dsm[grid.index(x_idx, y_idx)];
+        *cell = cell.max(point.z);
+    }
+
+    info!("Triangulating ground...");
+    let t = DelaunayTriangulation::<GroundPoint>::bulk_load(ground)?;
+    let interp = t.barycentric();
+
+    Ok(dsm
+        .into_iter()
+        .enumerate()
+        .map(|(i, top)| {
+            if top == f64::MIN {
+                return NODATA;
+            }
+
+            let (x, y) = grid.center(i);
+            let position = Point2::new(x, y);
+
+            match interp.interpolate(|v| v.data().z, position) {
+                Some(z) => (top - z).max(0.0),
+                None => NODATA,
+            }
+        })
+        .collect())
+}
+
+/// Variable-window local maxima on a canopy height model. A cell is a tree top when no other
+/// cell within half the window size (at its height) is higher.
+pub fn local_maxima(
+    chm: &[f64],
+    grid: &GridDefinition,
+    min_height: f64,
+    window: Window,
+) -> Vec<TreeTop> {
+    let (width, height, res) = (grid.width, grid.height, grid.res);
+    let mut tops = Vec::new();
+
+    for (i, h) in chm.iter().copied().enumerate() {
+        if h == NODATA || h < min_height {
+            continue;
+        }
+
+        let (x, y) = ((i % width) as i64, (i / width) as i64);
+        let radius = (window.base + window.slope * h).max(res) * 0.5;
+        let r = (radius / res).ceil() as i64;
+        let r_2 = (radius / res).powi(2);
+
+        let is_max = (-r..=r)
+            .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter(|(dx, dy)| (dx * dx + dy * dy) as f64 <= r_2)
+            .all(|(dx, dy)| {
+                let (nx, ny) = (x + dx, y + dy);
+
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    return true;
+                }
+
+                let j = ny as usize * width + nx as usize;
+
+                // Ties go to the first cell so plateaus yield a single top.
+                chm[j] < h || (chm[j] == h && j >= i)
+            });
+
+        if is_max {
+            let (x, y) = grid.cell_center(x as usize, y as usize);
+            tops.push(TreeTop { x, y, height: h });
+        }
+    }
+
+    tops
+}
+
+/// Writes tree tops as a point layer with a `height` attribute.
+pub fn write_tree_tops(path: &Path, tops: &[TreeTop]) -> Result<()> {
+    let driver = vector_driver(path)?;
+    let mut ds = driver.create_vector_only(path)?;
+
+    let mut layer = ds.create_layer(LayerOptions {
+        name: "tree_tops",
+        ty: OGRwkbGeometryType::wkbPoint,
+        ..Default::default()
+    })?;
+    layer.create_defn_fields(&[("height", OGRFieldType::OFTReal)])?;
+
+    for top in tops {
+        let geometry = Geometry::from_wkt(&format!("POINT ({} {})", top.x, top.y))?;
+        layer.create_feature_fields(geometry, &["height"], &[FieldValue::RealValue(top.height)])?;
+    }
+
+    Ok(())
+}
+
+/// Max-heap entry ordered by canopy height.
+struct Flood {
+    height: f64,
+    i: usize,
+}
+
+impl PartialEq for Flood {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Flood {}
+
+impl PartialOrd for Flood {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Flood {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.height.total_cmp(&other.height)
+    }
+}
+
+/// Marker-controlled watershed of the canopy height model, flooding downwards from each tree top.
+/// Returns a crown ID per cell (1-based, in `tops` order) and 0 for cells belonging to no crown.
+pub fn segment_crowns(
+    chm: &[f64],
+    grid: &GridDefinition,
+    min_height: f64,
+    tops: &[TreeTop],
+) -> Vec<i32> {
+    let (width, height) = (grid.width, grid.height);
+    let mut crowns = vec![0; chm.len()];
+    let mut queue = BinaryHeap::new();
+
+    for (id, top) in tops.iter().enumerate() {
+        let Some((x, y)) = grid.cell(top.x, top.y) else {
+            continue;
+        };
+        let i = grid.index(x, y);
+
+        crowns[i] = id as i32 + 1;
+        queue.push(Flood { height: chm[i], i });
+    }
+
+    while let Some(Flood { height: h, i }) = queue.pop() {
+        let (x, y) = (i % width, i / width);
+
+        let neighbours = [
+            (x > 0).then(|| i - 1),
+            (x + 1 < width).then(|| i + 1),
+            (y > 0).then(|| i - width),
+            (y + 1 < height).then(|| i + width),
+        ];
+
+        for j in neighbours.into_iter().flatten() {
+            // Only flow downhill, so neighbouring crowns meet at the valleys between them.
+            if crowns[j] != 0 || chm[j] == NODATA || chm[j] < min_height || chm[j] > h {
+                continue;
+            }
+
+            crowns[j] = crowns[i];
+            queue.push(Flood {
+                height: chm[j],
+                i: j,
+            });
+        }
+    }
+
+    crowns
+}
+
+/// Writes a crown ID raster, with 0 as NODATA, in the CRS with the WKT `crs`.
+pub fn write_crowns(
+    path: &Path,
+    grid: &GridDefinition,
+    crowns: Vec<i32>,
+    crs: Option<&str>,
+) -> Result<()> {
+    let driver = raster_driver(path)?;
+    let (width, height) = (grid.width, grid.height);
+
+    let mut ds = driver.create_with_band_type::<i32, _>(path, width, height, 1)?;
+    ds.set_geo_transform(&grid.geo_transform())?;
+
+    if let Some(wkt) = crs {
+        ds.set_projection(wkt)?;
+    }
+
+    let mut rb = ds.rasterband(1)?;
+    rb.set_no_data_value(Some(0.0))?;
+    rb.write(
+        (0, 0),
+        (width, height),
+        &mut Buffer::new((width, height), crowns),
+    )?;
+
+    Ok(())
+}
+
+/// Polygonizes a crown ID raster into a layer with a `crown_id` attribute.
+pub fn write_crown_polygons(path: &Path, grid: &GridDefinition, crowns: Vec<i32>) -> Result<()> {
+    let (width, height) = (grid.width, grid.height);
+
+    let mem = DriverManager::get_driver_by_name("MEM")?;
+    let mut src = mem.create_with_band_type::<i32, _>("", width, height, 1)?;
+    src.set_geo_transform(&grid.geo_transform())?;
+
+    let mut rb = src.rasterband(1)?;
+    rb.write(
+        (0, 0),
+        (width, height),
+        &mut Buffer::new((width, height), crowns),
+    )?;
+
+    let driver = vector_driver(path)?;
+    let mut ds = driver.create_vector_only(path)?;
+
+    let layer = ds.create_layer(LayerOptions {
+        name: "crowns",
+        ty: OGRwkbGeometryType::wkbPolygon,
+        ..Default::default()
+    })?;
+    layer.create_defn_fields(&[("crown_id", OGRFieldType::OFTInteger)])?;
+
+    // The band doubles as its own mask, leaving out cells without a crown.
+    let err = unsafe {
+        gdal_sys::GDALPolygonize(
+            rb.c_rasterband(),
+            rb.c_rasterband(),
+            layer.c_layer(),
+            0,
+            ptr::null_mut(),
+            None,
+            ptr::null_mut(),
+        )
+    };
+
+    if err != CPLErr::CE_None {
+        return Err(Error::ShouldntHappen(
+            "Polygonizing crowns failed".to_string(),
+        ));
+    }
+
+    Ok(())
+}
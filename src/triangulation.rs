@@ -1,15 +1,27 @@
-use std::collections::VecDeque;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 
-use las::point::Classification;
-use las::{Bounds, Reader};
-use log::info;
+use las::{Bounds, Vector};
 use spade::handles::FixedDirectedEdgeHandle;
 use spade::{
     ConstrainedDelaunayTriangulation, FloatTriangulation, HasPosition, Point2, Triangulation,
 };
+use tracing::{info, info_span, instrument, warn};
 
-use crate::error::Result;
-use crate::util::get_raster_size;
+use crate::error::{Error, Result};
+use crate::filter::PointFilter;
+use crate::input::Input;
+use crate::report::Report;
+use crate::service::{Observer, Stage};
+use crate::util::{read_u64, GridDefinition};
+use crate::vector::VectorFeature;
+use crate::voxel::{VoxelGrid, VoxelKeep};
 use crate::{get_var, Variable, NODATA};
 
 #[derive(Debug, Copy, Clone)]
@@ -39,51 +51,601 @@ impl HasPosition for Point {
 
 type TriangulationType = ConstrainedDelaunayTriangulation<Point>;
 
-pub fn triangulate(
-    mut reader: Reader,
-    bounds: Bounds,
+/// Leading bytes of a saved triangulation, with a format version.
+const TIN_MAGIC: &[u8; 8] = b"LRTIN\0\0\x01";
+
+/// Bytes per saved vertex: X, Y, Z and value.
+const TIN_VERTEX_SIZE: u64 = 4 * 8;
+
+/// Bytes per saved constraint edge: its two vertex indices.
+const TIN_EDGE_SIZE: u64 = 2 * 8;
+
+/// A value that changes linearly over the course of the insertion, from `start` at the first
+/// point to `end` at the last.
+#[derive(Debug, Clone, Copy)]
+pub struct Schedule {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Schedule {
+    /// Value after `progress` (0-1) of the points have been inserted.
+    pub fn at(&self, progress: f64) -> f64 {
+        self.start + (self.end - self.start) * progress
+    }
+}
+
+/// Parses a constant `3.0` or a schedule `3.0..0.5`.
+pub fn schedule_parser(s: &str) -> core::result::Result<Schedule, String> {
+    let parse = |num: &str| num.trim().parse::<f64>().map_err(|e| e.to_string());
+
+    match s.split_once("..") {
+        Some((start, end)) => Ok(Schedule {
+            start: parse(start)?,
+            end: parse(end)?,
+        }),
+        None => {
+            let value = parse(s)?;
+            Ok(Schedule {
+                start: value,
+                end: value,
+            })
+        }
+    }
+}
+
+/// Spike-free triangulation parameters, see `Commands::Triangulate`.
+pub struct TriangulationParams {
+    pub freeze_distance: f64,
+    pub insertion_buffer: Schedule,
+    pub max_edge: Option<f64>,
+    pub max_area: Option<f64>,
+    /// Surveyed control points/lines inserted as mandatory vertices.
+    pub seeds: Vec<VectorFeature>,
+    /// Insert the segments of seed lines as constraint edges.
+    pub seed_constraints: bool,
+    /// Stop inserting points once the triangulation has this many vertices.
+    pub max_vertices: Option<usize>,
+    /// Only insert points deviating more than this vertically from the current surface.
+    pub tin_error: Option<f64>,
+    /// Points held in memory before sorting them and spilling them to disk, the sort order
+    /// then comes from merging the spilled runs.
+    pub chunk_size: Option<usize>,
+    /// Thin the points to one per voxel of this size before sorting, keeping the given one.
+    pub voxel: Option<(f64, VoxelKeep)>,
+}
+
+/// A built triangulation, sampled per pixel when rasterizing.
+pub struct Surface {
+    t: TriangulationType,
+    max_edge: Option<f64>,
+    max_area: Option<f64>,
+}
+
+impl Surface {
+    /// Interpolated value at a position, NODATA outside of the triangulation or within triangles
+    /// exceeding the edge/area limits.
+    pub fn sample(&self, x: f64, y: f64) -> f64 {
+        let position = Point2 { x, y };
+
+        if (self.max_edge.is_some() || self.max_area.is_some())
+            && exceeds_limits(&self.t, position, self.max_edge, self.max_area)
+        {
+            return NODATA;
+        }
+
+        self.t
+            .barycentric()
+            .interpolate(|b| b.data().value, position)
+            .unwrap_or(NODATA)
+    }
+
+    /// Replaces the edge/area limits, so one triangulation can be sampled with several.
+    pub fn set_limits(&mut self, max_edge: Option<f64>, max_area: Option<f64>) {
+        self.max_edge = max_edge;
+        self.max_area = max_area;
+    }
+
+    /// Bounds of the triangulation's vertices.
+    pub fn bounds(&self) -> Bounds {
+        let mut bounds = Bounds {
+            min: Vector {
+                x: f64::MAX,
+                y: f64::MAX,
+                z: f64::MAX,
+            },
+            max: Vector {
+                x: f64::MIN,
+                y: f64::MIN,
+                z: f64::MIN,
+            },
+        };
+
+        for v in self.t.vertices() {
+            let p = v.data();
+
+            bounds.min.x = bounds.min.x.min(p.position.x);
+            bounds.min.y = bounds.min.y.min(p.position.y);
+            bounds.min.z = bounds.min.z.min(p.z);
+            bounds.max.x = bounds.max.x.max(p.position.x);
+            bounds.max.y = bounds.max.y.max(p.position.y);
+            bounds.max.z = bounds.max.z.max(p.z);
+        }
+
+        bounds
+    }
+
+    /// Saves the vertices and constraint edges of the triangulation, which is all that's needed
+    /// to rebuild it.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+
+        w.write_all(TIN_MAGIC)?;
+        w.write_all(&(self.t.num_vertices() as u64).to_le_bytes())?;
+
+        for v in self.t.vertices() {
+            let p = v.data();
+
+            for value in [p.position.x, p.position.y, p.z, p.value] {
+                w.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        let constraints = self
+            .t
+            .undirected_edges()
+            .filter(|e| e.is_constraint_edge())
+            .map(|e| e.vertices().map(|v| v.fix().index() as u64))
+            .collect::<Vec<[u64; 2]>>();
+
+        w.write_all(&(constraints.len() as u64).to_le_bytes())?;
+
+        for [a, b] in constraints {
+            w.write_all(&a.to_le_bytes())?;
+            w.write_all(&b.to_le_bytes())?;
+        }
+
+        w.flush()?;
+
+        Ok(())
+    }
+
+    /// Loads a triangulation saved with `save`. The vertex and edge counts are checked against
+    /// the file length before allocating anything for them.
+    pub fn load(path: &Path, max_edge: Option<f64>, max_area: Option<f64>) -> Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut r = BufReader::new(file);
+        let invalid = || Error::InvalidTin(path.display().to_string());
+
+        let mut magic = [0u8; TIN_MAGIC.len()];
+        r.read_exact(&mut magic)?;
+
+        if magic != *TIN_MAGIC {
+            return Err(invalid());
+        }
+
+        // Past the magic and both counts.
+        let body = len.saturating_sub(TIN_MAGIC.len() as u64 + 2 * 8);
+
+        let num_vertices = read_u64(&mut r)?;
+        if num_vertices > body / TIN_VERTEX_SIZE {
+            return Err(invalid());
+        }
+
+        let mut vertices = Vec::with_capacity(num_vertices as usize);
+
+        for _ in 0..num_vertices {
+            let mut values = [0.0; 4];
+            for value in values.iter_mut() {
+                *value = f64::from_bits(read_u64(&mut r)?);
+            }
+
+            let [x, y, z, value] = values;
+            vertices.push(Point::new(x, y, z, value));
+        }
+
+        let num_constraints = read_u64(&mut r)?;
+        let edges_size = body - num_vertices * TIN_VERTEX_SIZE;
+        if num_constraints.checked_mul(TIN_EDGE_SIZE) != Some(edges_size) {
+            return Err(invalid());
+        }
+
+        let mut edges = Vec::with_capacity(num_constraints as usize);
+
+        for _ in 0..num_constraints {
+            let edge = [read_u64(&mut r)?, read_u64(&mut r)?];
+            if edge.iter().any(|v| *v >= num_vertices) {
+                return Err(invalid());
+            }
+
+            edges.push(edge.map(|v| v as usize));
+        }
+
+        info!(
+            "Loaded a triangulation of {} vertices, {} constraint edges",
+            vertices.len(),
+            edges.len()
+        );
+
+        Ok(Self {
+            t: TriangulationType::bulk_load_cdt(vertices, edges)?,
+            max_edge,
+            max_area,
+        })
+    }
+
+    /// Value of the pixel (x, y) of a raster of `grid`, at its center.
+    pub fn pixel(&self, grid: &GridDefinition, x: usize, y: usize) -> f64 {
+        let (p_x, p_y) = grid.cell_center(x, y);
+
+        self.sample(p_x, p_y)
+    }
+}
+
+/// Sorts points by Z, descending, the spike-free insertion order.
+fn sort_descending(points: &mut [Point]) {
+    points.sort_by(|a, b| b.z.partial_cmp(&a.z).unwrap());
+}
+
+/// Number of runs spilled so far.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs merged at once. More are first merged in groups of this many into longer runs, so the
+/// open files stay bounded however small the chunks.
+const MAX_FAN_IN: usize = 64;
+
+/// Points sorted by descending Z, spilled to a temporary file and read back one at a time. The
+/// file is only opened for reading once the run is merged.
+struct Run {
+    path: PathBuf,
+    read: Option<BufReader<File>>,
+}
+
+impl Run {
+    /// Creates a new temporary file, never one that already exists (or a link planted in its
+    /// place) in a shared temporary directory.
+    fn create() -> Result<(PathBuf, BufWriter<File>)> {
+        loop {
+            // Unique across the triangulations of a process.
+            let id = RUNS.fetch_add(1, AtomicOrdering::Relaxed);
+            let path = env::temp_dir().join(format!("las-rasterizer-{}-{id}.run", process::id()));
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((path, BufWriter::new(file))),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Writes out `points`, in order, as a run.
+    fn write(points: impl Iterator<Item = Result<Point>>) -> Result<Self> {
+        let (path, mut w) = Self::create()?;
+        // Removes the file on errors too.
+        let run = Self { path, read: None };
+
+        for point in points {
+            let point = point?;
+            for value in [point.position.x, point.position.y, point.z, point.value] {
+                w.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        w.flush()?;
+
+        Ok(run)
+    }
+
+    /// Sorts and writes out `points`, leaving it empty.
+    fn spill(points: &mut Vec<Point>) -> Result<Self> {
+        sort_descending(points);
+
+        Self::write(points.drain(..).map(Ok))
+    }
+
+    fn next(&mut self) -> Result<Option<Point>> {
+        let read = match self.read.as_mut() {
+            Some(read) => read,
+            None => self.read.insert(BufReader::new(File::open(&self.path)?)),
+        };
+
+        let x = match read_u64(&mut *read) {
+            Ok(x) => f64::from_bits(x),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut next = || -> Result<f64> { Ok(f64::from_bits(read_u64(&mut *read)?)) };
+
+        Ok(Some(Point::new(x, next()?, next()?, next()?)))
+    }
+
+    /// Merges `runs` into a single stream of descending Z.
+    fn merge(mut runs: Vec<Run>) -> Result<SortedPoints> {
+        let mut heads = BinaryHeap::with_capacity(runs.len());
+        for (i, run) in runs.iter_mut().enumerate() {
+            if let Some(point) = run.next()? {
+                heads.push(Head { point, run: i });
+            }
+        }
+
+        Ok(SortedPoints::Merge { runs, heads })
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The next point of a run, ordered by Z for merging.
+struct Head {
+    point: Point,
+    run: usize,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.point.z.total_cmp(&other.point.z)
+    }
+}
+
+/// Collected points in descending Z order, from memory or merged from spilled runs.
+enum SortedPoints {
+    Memory(std::vec::IntoIter<Point>),
+    Merge {
+        runs: Vec<Run>,
+        heads: BinaryHeap<Head>,
+    },
+}
+
+impl Iterator for SortedPoints {
+    type Item = Result<Point>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (runs, heads) = match self {
+            SortedPoints::Memory(points) => return points.next().map(Ok),
+            SortedPoints::Merge { runs, heads } => (runs, heads),
+        };
+
+        let Head { point, run } = heads.pop()?;
+
+        match runs[run].next() {
+            Ok(Some(next)) => heads.push(Head { point: next, run }),
+            Ok(None) => {}
+            Err(e) => return Some(Err(e)),
+        }
+
+        Some(Ok(point))
+    }
+}
+
+/// Points collected for a triangulation, so several triangulations can share a single read of
+/// the input. With a chunk size, points are held in memory up to that many at a time, full
+/// chunks being sorted and spilled to temporary files for an external merge sort. Voxel
+/// thinning holds the kept point of every voxel in memory instead, bounded by the occupied
+/// volume rather than the chunk size, and only spills once thinned.
+pub struct TinPoints {
     var: Variable,
-    res: f64,
-    freeze_distance: f64,
-    insertion_buffer: f64,
-) -> Result<Vec<f64>> {
-    let mut points: Vec<Point> = Vec::with_capacity(reader.header().number_of_points() as usize);
+    points: Vec<Point>,
+    /// Highest point, filtered or not.
+    max_z: f64,
+    chunk_size: Option<usize>,
+    runs: Vec<Run>,
+    len: usize,
+    /// Voxel thinning, points are held here rather than in `points` until sorting.
+    voxels: Option<VoxelGrid<Point>>,
+}
 
-    // To avoid unnessicary square roots.
-    let freeze_distance_2 = freeze_distance * freeze_distance;
-    let mut buffer_height = f64::MIN;
+impl TinPoints {
+    pub fn new(
+        var: Variable,
+        capacity: usize,
+        chunk_size: Option<usize>,
+        voxel: Option<(f64, VoxelKeep)>,
+    ) -> Self {
+        Self {
+            voxels: voxel.map(|(size, keep)| VoxelGrid::new(size, keep)),
+            var,
+            points: Vec::with_capacity(chunk_size.map_or(capacity, |c| c.min(capacity))),
+            max_z: f64::MIN,
+            chunk_size,
+            runs: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Adds a single point, if it passes `filter`.
+    pub fn add(
+        &mut self,
+        point: &las::Point,
+        filter: &PointFilter,
+        report: &mut Report,
+    ) -> Result<()> {
+        self.max_z = self.max_z.max(point.z);
 
-    for p in reader.points() {
-        let point = p?;
+        if let Some(criterion) = filter.rejects(point) {
+            report.filtered(criterion);
+            return Ok(());
+        }
 
-        buffer_height = buffer_height.max(point.z);
+        let value = get_var(&self.var, point);
+        report.points_used += 1;
 
-        if point.classification == Classification::HighNoise {
-            continue;
+        if let Some(voxels) = self.voxels.as_mut() {
+            voxels.add(
+                [point.x, point.y, point.z],
+                Point::new(point.x, point.y, point.z, value),
+            );
+            return Ok(());
         }
 
-        let var = get_var(&var, &point);
+        self.push(Point::new(point.x, point.y, point.z, value))
+    }
+
+    /// Collects a point, spilling full chunks.
+    fn push(&mut self, point: Point) -> Result<()> {
+        self.points.push(point);
+        self.len += 1;
 
-        points.push(Point::new(point.x, point.y, point.z, var));
+        if self
+            .chunk_size
+            .is_some_and(|size| self.points.len() >= size)
+        {
+            info!("Spilling a sorted chunk of {} points", self.points.len());
+            self.runs.push(Run::spill(&mut self.points)?);
+        }
+
+        Ok(())
     }
 
+    /// The points in descending Z order, and their number.
+    fn sorted(mut self) -> Result<(SortedPoints, usize)> {
+        if let Some(voxels) = self.voxels.take() {
+            info!("Thinned the points to {} voxels", voxels.occupied());
+
+            for point in voxels.into_points() {
+                self.push(point)?;
+            }
+        }
+
+        if self.runs.is_empty() {
+            sort_descending(&mut self.points);
+            return Ok((SortedPoints::Memory(self.points.into_iter()), self.len));
+        }
+
+        if !self.points.is_empty() {
+            self.runs.push(Run::spill(&mut self.points)?);
+        }
+
+        let mut runs = std::mem::take(&mut self.runs);
+        while runs.len() > MAX_FAN_IN {
+            info!(
+                "Merging {} sorted chunks in groups of {MAX_FAN_IN}",
+                runs.len()
+            );
+
+            let mut merged = Vec::with_capacity(runs.len().div_ceil(MAX_FAN_IN));
+            while !runs.is_empty() {
+                let group: Vec<Run> = runs.drain(..MAX_FAN_IN.min(runs.len())).collect();
+                merged.push(Run::write(Run::merge(group)?)?);
+            }
+
+            runs = merged;
+        }
+
+        info!("Merging {} sorted chunks", runs.len());
+
+        Ok((Run::merge(runs)?, self.len))
+    }
+}
+
+#[instrument(skip_all)]
+pub fn triangulate(
+    mut input: Input,
+    filter: &PointFilter,
+    var: Variable,
+    params: &TriangulationParams,
+    report: &mut Report,
+    observer: Option<&Observer>,
+) -> Result<Surface> {
+    let mut points = TinPoints::new(
+        var,
+        input.number_of_points() as usize,
+        params.chunk_size,
+        params.voxel,
+    );
+
+    info_span!("read").in_scope(|| -> Result<()> {
+        while let Some(point) = input.next_point(report)? {
+            points.add(&point, filter, report)?;
+        }
+
+        Ok(())
+    })?;
+
+    build(points, params, observer)
+}
+
+/// Builds a spike-free triangulation of collected points. `observer` is told of the points
+/// inserted, and can cancel.
+#[instrument(skip_all)]
+pub fn build(
+    points: TinPoints,
+    params: &TriangulationParams,
+    observer: Option<&Observer>,
+) -> Result<Surface> {
+    let (var, max_z) = (points.var, points.max_z);
+
+    let freeze_distance = params.freeze_distance;
+
+    // To avoid unnessicary square roots.
+    let freeze_distance_2 = freeze_distance * freeze_distance;
+    let mut buffer_height = max_z;
+
     info!("Sorting points...");
-    // Sort by Z (Descending)
-    points.sort_by(|a, b| b.z.partial_cmp(&a.z).unwrap());
+    let (points, len) = points.sorted()?;
 
     let mut t = TriangulationType::new();
     let mut constraint_buffer: VecDeque<FixedDirectedEdgeHandle> = VecDeque::new();
 
+    if !params.seeds.is_empty() {
+        if !matches!(var, Variable::Z) {
+            warn!("Seed points carry their Z value, regardless of the rasterized variable.");
+        }
+
+        info!("Inserting seed points...");
+        insert_seeds(&mut t, &params.seeds, params.seed_constraints)?;
+    }
+
     info!("Building triangulation...");
 
-    let len = points.len();
-    let frac = len / 10;
+    let frac = (len / 10).max(1);
+    let mut deferred: Vec<Point> = Vec::new();
+
+    for (point_i, point) in points.enumerate() {
+        let point = point?;
 
-    for (point_i, point) in points.into_iter().enumerate() {
         if point_i % frac == 0 {
             info!("{}%", ((point_i as f32 / len as f32) * 100.0).round());
         }
 
+        if let Some(observer) = observer {
+            observer.observe(Stage::Triangulating, point_i as u64 + 1, len as u64)?;
+        }
+
+        // Points are in spike-free (descending Z) order, so the budget keeps the top surface.
+        if params
+            .max_vertices
+            .is_some_and(|max| t.num_vertices() >= max)
+        {
+            info!(
+                "Reached the vertex budget of {}, skipping the remaining {} points",
+                t.num_vertices(),
+                len - point_i
+            );
+            break;
+        }
+
+        // Tightens as the triangulation densifies, if given as a schedule.
+        let insertion_buffer = params.insertion_buffer.at(point_i as f64 / len as f64);
+
         for (i, edge) in constraint_buffer.iter().rev().enumerate() {
             let edge = t.directed_edge(*edge);
 
@@ -105,6 +667,16 @@ pub fn triangulate(
         }
 
         let mut insert_vert = |t: &mut TriangulationType, buffer_height: &mut f64| -> Result<()> {
+            // Greedy insertion, points already represented within the error are deferred.
+            if let Some(dz) = params.tin_error {
+                let z = t.barycentric().interpolate(|v| v.data().z, point.position);
+
+                if z.is_some_and(|z| (point.z - z).abs() <= dz) {
+                    deferred.push(point);
+                    return Ok(());
+                }
+            }
+
             *buffer_height = buffer_height.min(point.z);
 
             let vert = t.insert(point)?;
@@ -114,49 +686,378 @@ pub fn triangulate(
             Ok(())
         };
 
-        match t.locate(point.position) {
-            spade::PositionInTriangulation::OnFace(handle) => {
-                let [a, b, c] = t.face(handle).adjacent_edges();
+        if admits(&t, &point, buffer_height) {
+            insert_vert(&mut t, &mut buffer_height)?;
+        }
+    }
 
-                if !(a.is_constraint_edge() && b.is_constraint_edge() && c.is_constraint_edge()) {
-                    insert_vert(&mut t, &mut buffer_height)?;
-                }
-            }
-            // Reasonably sure this overwrites/isn't added at all, but leaving it for now.
-            spade::PositionInTriangulation::OnVertex(handle) => {
-                if point.z - t.vertex(handle).data().z > buffer_height {
-                    insert_vert(&mut t, &mut buffer_height)?;
+    if let Some(dz) = params.tin_error {
+        info!("Refining to a vertical error of {dz}...");
+
+        let within = refine(
+            &mut t,
+            deferred,
+            dz,
+            buffer_height,
+            params.max_vertices,
+            observer,
+        )?;
+
+        info!(
+            "{} vertices, {within} points within the error bound",
+            t.num_vertices()
+        );
+    }
+
+    Ok(Surface {
+        t,
+        max_edge: params.max_edge,
+        max_area: params.max_area,
+    })
+}
+
+/// Whether `point` may join the triangulation: it isn't on a frozen face (all constraint edges)
+/// or a constraint edge, nor a vertex within the buffer.
+fn admits(t: &TriangulationType, point: &Point, buffer_height: f64) -> bool {
+    match t.locate(point.position) {
+        spade::PositionInTriangulation::OnFace(handle) => {
+            let [a, b, c] = t.face(handle).adjacent_edges();
+
+            !(a.is_constraint_edge() && b.is_constraint_edge() && c.is_constraint_edge())
+        }
+        // Reasonably sure this overwrites/isn't added at all, but leaving it for now.
+        spade::PositionInTriangulation::OnVertex(handle) => {
+            point.z - t.vertex(handle).data().z > buffer_height
+        }
+        spade::PositionInTriangulation::OnEdge(handle) => {
+            !t.is_constraint_edge(handle.as_undirected())
+        }
+        // No triangulation or outside hull
+        _ => true,
+    }
+}
+
+/// Vertical distance of `point` to the surface, infinite outside of it.
+fn vertical_error(t: &TriangulationType, point: &Point) -> f64 {
+    t.barycentric()
+        .interpolate(|v| v.data().z, point.position)
+        .map_or(f64::INFINITY, |z| (point.z - z).abs())
+}
+
+/// A deferred point and its vertical error when it was scored, ordered by the error.
+struct Candidate {
+    error: f64,
+    i: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.error.total_cmp(&other.error)
+    }
+}
+
+/// Points deferred by `--tin-error`, bucketed on a grid so the points around an insertion can
+/// be found and re-scored.
+struct Deferred {
+    points: Vec<Point>,
+    /// Vertical error of each point, None once it's been inserted or rejected.
+    errors: Vec<Option<f64>>,
+    origin: Point2<f64>,
+    cell: f64,
+    width: usize,
+    height: usize,
+    buckets: Vec<Vec<usize>>,
+}
+
+impl Deferred {
+    /// Points per bucket, on average.
+    const BUCKET_POINTS: f64 = 4.0;
+
+    fn new(points: Vec<Point>) -> Self {
+        let (mut min, mut max) = (
+            Point2::new(f64::MAX, f64::MAX),
+            Point2::new(f64::MIN, f64::MIN),
+        );
+        for p in points.iter() {
+            min = Point2::new(min.x.min(p.position.x), min.y.min(p.position.y));
+            max = Point2::new(max.x.max(p.position.x), max.y.max(p.position.y));
+        }
+
+        let area = (max.x - min.x) * (max.y - min.y);
+        let cell = match area > 0.0 {
+            true => (area / points.len() as f64 * Self::BUCKET_POINTS).sqrt(),
+            false => 1.0,
+        };
+        let (width, height) = match points.is_empty() {
+            true => (0, 0),
+            false => (
+                ((max.x - min.x) / cell) as usize + 1,
+                ((max.y - min.y) / cell) as usize + 1,
+            ),
+        };
+
+        let mut deferred = Self {
+            errors: vec![None; points.len()],
+            points,
+            origin: min,
+            cell,
+            width,
+            height,
+            buckets: vec![Vec::new(); width * height],
+        };
+        for i in 0..deferred.points.len() {
+            let (x, y) = deferred.bucket(deferred.points[i].position);
+            deferred.buckets[y * width + x].push(i);
+        }
+
+        deferred
+    }
+
+    /// Bucket of `position`, clamped to the grid.
+    fn bucket(&self, position: Point2<f64>) -> (usize, usize) {
+        let index = |v: f64, origin: f64, len: usize| {
+            (((v - origin) / self.cell).max(0.0) as usize).min(len.saturating_sub(1))
+        };
+
+        (
+            index(position.x, self.origin.x, self.width),
+            index(position.y, self.origin.y, self.height),
+        )
+    }
+
+    /// Points of the buckets overlapping `min`-`max`, possibly some beyond.
+    fn around(&self, min: Point2<f64>, max: Point2<f64>) -> impl Iterator<Item = usize> + '_ {
+        let (min_x, min_y) = self.bucket(min);
+        let (max_x, max_y) = self.bucket(max);
+
+        (min_y..=max_y)
+            .flat_map(move |y| (min_x..=max_x).map(move |x| y * self.width + x))
+            .flat_map(|b| self.buckets[b].iter().copied())
+    }
+}
+
+/// Greedy insertion of the deferred points: the point furthest from the surface is inserted
+/// first, through the same checks as the main insertion, and the points around it re-scored,
+/// until every point is within `dz` or the vertex budget is reached. Returns the number of
+/// points left out within the error bound.
+fn refine(
+    t: &mut TriangulationType,
+    points: Vec<Point>,
+    dz: f64,
+    buffer_height: f64,
+    max_vertices: Option<usize>,
+    observer: Option<&Observer>,
+) -> Result<usize> {
+    if points.is_empty() {
+        return Ok(0);
+    }
+
+    let mut deferred = Deferred::new(points);
+    let mut heap = BinaryHeap::new();
+
+    for i in 0..deferred.points.len() {
+        let error = vertical_error(t, &deferred.points[i]);
+        deferred.errors[i] = Some(error);
+
+        if error > dz {
+            heap.push(Candidate { error, i });
+        }
+    }
+
+    while let Some(Candidate { error, i }) = heap.pop() {
+        if let Some(observer) = observer {
+            observer.check()?;
+        }
+
+        // Stale, re-scored since or already inserted.
+        if deferred.errors[i] != Some(error) {
+            continue;
+        }
+
+        if max_vertices.is_some_and(|max| t.num_vertices() >= max) {
+            info!("Reached the vertex budget of {}", t.num_vertices());
+            break;
+        }
+
+        deferred.errors[i] = None;
+        let point = deferred.points[i];
+
+        if !admits(t, &point, buffer_height) {
+            continue;
+        }
+
+        let vert = t.insert(point)?;
+
+        // Only the faces around the new vertex changed.
+        let vertex = t.vertex(vert);
+        let (mut min, mut max) = (vertex.position(), vertex.position());
+        for neighbor in vertex.out_edges().map(|e| e.to().position()) {
+            min = Point2::new(min.x.min(neighbor.x), min.y.min(neighbor.y));
+            max = Point2::new(max.x.max(neighbor.x), max.y.max(neighbor.y));
+        }
+
+        let around: Vec<usize> = deferred.around(min, max).collect();
+        for j in around {
+            let Some(previous) = deferred.errors[j] else {
+                continue;
+            };
+
+            let error = vertical_error(t, &deferred.points[j]);
+            if error != previous {
+                deferred.errors[j] = Some(error);
+
+                if error > dz {
+                    heap.push(Candidate { error, i: j });
                 }
             }
-            spade::PositionInTriangulation::OnEdge(handle) => {
-                if !t.is_constraint_edge(handle.as_undirected()) {
-                    insert_vert(&mut t, &mut buffer_height)?;
+        }
+    }
+
+    Ok(deferred
+        .errors
+        .iter()
+        .flatten()
+        .filter(|error| **error <= dz)
+        .count())
+}
+
+/// Inserts control points ahead of the point cloud. These never enter the constraint buffer, so
+/// they can't freeze triangles, but they are always part of the surface.
+fn insert_seeds(
+    t: &mut TriangulationType,
+    seeds: &[VectorFeature],
+    as_constraints: bool,
+) -> Result<()> {
+    for seed in seeds {
+        for part in seed.parts.iter() {
+            let mut prev = None;
+
+            for [x, y, z] in part.iter().copied() {
+                // An attribute value overrides the geometry's own Z.
+                let z = seed.value.unwrap_or(z);
+                let vert = t.insert(Point::new(x, y, z, z))?;
+
+                if as_constraints {
+                    if let Some(prev) = prev {
+                        if prev != vert && t.can_add_constraint(prev, vert) {
+                            t.add_constraint(prev, vert);
+                        }
+                    }
                 }
-            }
-            // No triangulation or outside hull
-            _ => {
-                insert_vert(&mut t, &mut buffer_height)?;
+
+                prev = Some(vert);
             }
         }
     }
 
-    let (width, height) = get_raster_size(&bounds, res);
-    let mut ret: Vec<f64> = Vec::with_capacity(width * height);
+    Ok(())
+}
 
-    info!("Triangulating...");
-    let interp = t.barycentric();
-    for y in 0..height {
-        // Center of pixel
-        let p_y = bounds.min.y.round() + res * 0.5 + res * y as f64;
-        for x in 0..width {
-            let p_x = bounds.min.x.round() + res * 0.5 + res * x as f64;
+/// Checks whether the triangle containing `position` has an edge longer than `max_edge` or an
+/// area larger than `max_area`. Long, thin slivers along flightline edges may pass the edge
+/// test while still covering a large area, hence both limits.
+fn exceeds_limits(
+    t: &TriangulationType,
+    position: Point2<f64>,
+    max_edge: Option<f64>,
+    max_area: Option<f64>,
+) -> bool {
+    let face = match t.locate(position) {
+        spade::PositionInTriangulation::OnFace(handle) => t.face(handle),
+        _ => return false,
+    };
 
-            let p = interp
-                .interpolate(|b| b.data().value, Point2 { x: p_x, y: p_y })
-                .unwrap_or(NODATA);
-            ret.push(p);
+    let [a, b, c] = face.vertices().map(|v| v.position());
+
+    if let Some(max_edge) = max_edge {
+        let max_edge_2 = max_edge * max_edge;
+
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            if (from.x - to.x).powi(2) + (from.y - to.y).powi(2) > max_edge_2 {
+                return true;
+            }
         }
     }
 
-    Ok(ret)
+    if let Some(max_area) = max_area {
+        let area = ((b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y)).abs() * 0.5;
+
+        if area > max_area {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedules() {
+        let constant = schedule_parser("3").unwrap();
+        assert_eq!((constant.start, constant.end), (3.0, 3.0));
+        assert_eq!(constant.at(0.7), 3.0);
+
+        let schedule = schedule_parser("3.0..0.5").unwrap();
+        assert_eq!((schedule.start, schedule.end), (3.0, 0.5));
+        assert_eq!(schedule.at(0.0), 3.0);
+        assert_eq!(schedule.at(0.5), 1.75);
+        assert_eq!(schedule.at(1.0), 0.5);
+
+        assert!(schedule_parser("a").is_err());
+        assert!(schedule_parser("1..").is_err());
+        assert!(schedule_parser("").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_tins() {
+        let path = crate::util::temp_path("truncated.tin");
+        let load = |bytes: &[u8]| {
+            fs::write(&path, bytes).unwrap();
+            Surface::load(&path, None, None)
+        };
+
+        let mut bytes = TIN_MAGIC.to_vec();
+        bytes.extend(u64::MAX.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        assert!(matches!(load(&bytes), Err(Error::InvalidTin(_))));
+
+        // Two vertices claimed, one saved.
+        let mut bytes = TIN_MAGIC.to_vec();
+        bytes.extend(2u64.to_le_bytes());
+        for value in [1.0f64, 2.0, 3.0, 4.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(0u64.to_le_bytes());
+        assert!(matches!(load(&bytes), Err(Error::InvalidTin(_))));
+
+        // An edge to a vertex that isn't there.
+        let mut bytes = TIN_MAGIC.to_vec();
+        bytes.extend(1u64.to_le_bytes());
+        for value in [1.0f64, 2.0, 3.0, 4.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(1u64.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        bytes.extend(5u64.to_le_bytes());
+        assert!(matches!(load(&bytes), Err(Error::InvalidTin(_))));
+
+        fs::remove_file(&path).unwrap();
+    }
 }
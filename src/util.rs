@@ -1,4 +1,9 @@
-use las::Bounds;
+use std::io::{self, Read};
+
+use las::{Bounds, Header, Vector};
+use serde::{Deserialize, Serialize};
+
+use crate::crs::{is_planar, vertical_units};
 
 /// (width, height)
 pub fn get_raster_size(bounds: &Bounds, res: f64) -> (usize, usize) {
@@ -7,3 +12,450 @@ pub fn get_raster_size(bounds: &Bounds, res: f64) -> (usize, usize) {
 
     (width, height)
 }
+
+/// A raster grid: the extent it covers, its cell size and its size in cells. Cells are
+/// indexed row by row from the minimum corner, as band data is laid out in the (south-up)
+/// outputs.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(into = "GridSpec", from = "GridSpec")]
+pub struct GridDefinition {
+    pub bounds: Bounds,
+    pub res: f64,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A grid as serialized: its origin (minimum corner), cell size and size in cells. The bounds
+/// of a deserialized grid end on its last cells' edges, without a Z range.
+#[derive(PartialEq, Serialize, Deserialize)]
+struct GridSpec {
+    origin: [f64; 2],
+    res: f64,
+    width: usize,
+    height: usize,
+}
+
+/// Grids are equal when their cells are, whatever the extent they were made for.
+impl PartialEq for GridDefinition {
+    fn eq(&self, other: &Self) -> bool {
+        GridSpec::from(*self) == GridSpec::from(*other)
+    }
+}
+
+impl From<GridDefinition> for GridSpec {
+    fn from(grid: GridDefinition) -> Self {
+        Self {
+            origin: [grid.bounds.min.x, grid.bounds.min.y],
+            res: grid.res,
+            width: grid.width,
+            height: grid.height,
+        }
+    }
+}
+
+impl From<GridSpec> for GridDefinition {
+    fn from(spec: GridSpec) -> Self {
+        let [x, y] = spec.origin;
+
+        Self {
+            bounds: Bounds {
+                min: Vector { x, y, z: 0.0 },
+                max: Vector {
+                    x: x + spec.width as f64 * spec.res,
+                    y: y + spec.height as f64 * spec.res,
+                    z: 0.0,
+                },
+            },
+            res: spec.res,
+            width: spec.width,
+            height: spec.height,
+        }
+    }
+}
+
+impl GridDefinition {
+    /// The grid covering `bounds` at `res`, the last column and row extending past `bounds` if
+    /// it isn't a multiple of `res`.
+    pub fn new(bounds: Bounds, res: f64) -> Self {
+        let (width, height) = get_raster_size(&bounds, res);
+
+        Self {
+            bounds,
+            res,
+            width,
+            height,
+        }
+    }
+
+    /// Number of cells.
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// (x, y) cell of a position, clipping positions on the max edge of the bounds into the last
+    /// cell. None if the position is outside of the bounds.
+    pub fn cell(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        let (x_idx, y_idx) = self.signed_cell(x, y)?;
+
+        if x_idx < 0 || y_idx < 0 || x > self.bounds.max.x || y > self.bounds.max.y {
+            return None;
+        }
+
+        Some((
+            (x_idx as usize).min(self.width.saturating_sub(1)),
+            (y_idx as usize).min(self.height.saturating_sub(1)),
+        ))
+    }
+
+    /// (x, y) cell of a position on the grid extended past its bounds, so negative or past the
+    /// last column or row outside of them. None if either coordinate is NaN.
+    pub fn signed_cell(&self, x: f64, y: f64) -> Option<(i64, i64)> {
+        let x_idx = ((x - self.bounds.min.x) / self.res).floor();
+        let y_idx = ((y - self.bounds.min.y) / self.res).floor();
+
+        match x_idx.is_nan() || y_idx.is_nan() {
+            true => None,
+            false => Some((x_idx as i64, y_idx as i64)),
+        }
+    }
+
+    /// Index in band data of the cell (x, y).
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    /// Center of the cell (x, y).
+    pub fn cell_center(&self, x: usize, y: usize) -> (f64, f64) {
+        (
+            self.bounds.min.x + (x as f64 + 0.5) * self.res,
+            self.bounds.min.y + (y as f64 + 0.5) * self.res,
+        )
+    }
+
+    /// Center of the cell at index `i` of band data.
+    pub fn center(&self, i: usize) -> (f64, f64) {
+        self.cell_center(i % self.width, i / self.width)
+    }
+
+    /// GDAL geotransform of the grid, south-up.
+    pub fn geo_transform(&self) -> [f64; 6] {
+        [
+            self.bounds.min.x,
+            self.res,
+            0.0,
+            self.bounds.min.y,
+            0.0,
+            self.res,
+        ]
+    }
+
+    /// The cells from (x0, y0) up to (x1, y1), exclusive, as a grid of their own.
+    fn subgrid(&self, (x0, y0): (usize, usize), (x1, y1): (usize, usize)) -> Self {
+        let (min_x, min_y) = (
+            self.bounds.min.x + x0 as f64 * self.res,
+            self.bounds.min.y + y0 as f64 * self.res,
+        );
+
+        Self {
+            bounds: Bounds {
+                min: Vector {
+                    x: min_x,
+                    y: min_y,
+                    z: self.bounds.min.z,
+                },
+                max: Vector {
+                    x: (self.bounds.min.x + x1 as f64 * self.res).min(self.bounds.max.x),
+                    y: (self.bounds.min.y + y1 as f64 * self.res).min(self.bounds.max.y),
+                    z: self.bounds.max.z,
+                },
+            },
+            res: self.res,
+            width: x1 - x0,
+            height: y1 - y0,
+        }
+    }
+
+    /// The cells overlapping `bounds` in XY, as a grid aligned with this one, with its offset
+    /// (x, y) in cells. None if there are none.
+    pub fn intersection(&self, bounds: &Bounds) -> Option<((usize, usize), Self)> {
+        let range = |min: f64, max: f64, origin: f64, len: usize| {
+            let first = ((min - origin) / self.res).floor().max(0.0);
+            let last = ((max - origin) / self.res).ceil().min(len as f64);
+
+            (first < last).then_some((first as usize, last as usize))
+        };
+
+        let (x0, x1) = range(bounds.min.x, bounds.max.x, self.bounds.min.x, self.width)?;
+        let (y0, y1) = range(bounds.min.y, bounds.max.y, self.bounds.min.y, self.height)?;
+
+        Some(((x0, y0), self.subgrid((x0, y0), (x1, y1))))
+    }
+
+    /// Tiles of up to `size` by `size` cells covering the grid row by row, with their offsets
+    /// (x, y) in cells.
+    pub fn tiles(&self, size: usize) -> impl Iterator<Item = ((usize, usize), Self)> + '_ {
+        (0..self.height).step_by(size).flat_map(move |y| {
+            (0..self.width).step_by(size).map(move |x| {
+                let end = ((x + size).min(self.width), (y + size).min(self.height));
+
+                ((x, y), self.subgrid((x, y), end))
+            })
+        })
+    }
+}
+
+/// `bounds` shrunk in XY to the `percent` to 100 - `percent` percentiles of `samples`, so a
+/// few wild outlier coordinates don't blow up the extent.
+pub fn trim_bounds(bounds: &Bounds, samples: &[(f64, f64)], percent: f64) -> Bounds {
+    if samples.is_empty() {
+        return *bounds;
+    }
+
+    let percentiles = |mut values: Vec<f64>| {
+        values.sort_by(f64::total_cmp);
+        let last = (values.len() - 1) as f64;
+
+        (
+            values[(percent / 100.0 * last).floor() as usize],
+            values[((100.0 - percent) / 100.0 * last).ceil() as usize],
+        )
+    };
+
+    let (min_x, max_x) = percentiles(samples.iter().map(|s| s.0).collect());
+    let (min_y, max_y) = percentiles(samples.iter().map(|s| s.1).collect());
+
+    let mut trimmed = *bounds;
+    trimmed.min.x = bounds.min.x.max(min_x);
+    trimmed.min.y = bounds.min.y.max(min_y);
+    trimmed.max.x = bounds.max.x.min(max_x);
+    trimmed.max.y = bounds.max.y.min(max_y);
+
+    trimmed
+}
+
+/// `bounds` shrunk in XY to their overlap with `clip`, or as they are if they don't overlap.
+pub fn clip_bounds(bounds: &Bounds, clip: &Bounds) -> Bounds {
+    let mut clipped = *bounds;
+    clipped.min.x = bounds.min.x.max(clip.min.x);
+    clipped.min.y = bounds.min.y.max(clip.min.y);
+    clipped.max.x = bounds.max.x.min(clip.max.x);
+    clipped.max.y = bounds.max.y.min(clip.max.y);
+
+    match clipped.min.x < clipped.max.x && clipped.min.y < clipped.max.y {
+        true => clipped,
+        false => *bounds,
+    }
+}
+
+/// OGC WKT of the point cloud's CRS, if the header has a WKT (E)VLR.
+pub fn header_wkt(header: &Header) -> Option<String> {
+    header
+        .vlrs()
+        .iter()
+        .chain(header.evlrs().iter())
+        .find(|vlr| vlr.user_id == "LASF_Projection" && vlr.record_id == 2112)
+        .map(|vlr| {
+            String::from_utf8_lossy(&vlr.data)
+                .trim_end_matches('\0')
+                .to_string()
+        })
+}
+
+/// Heuristics for likely unit problems, e.g. a metric resolution over an extent in degrees.
+/// `headers` are those of every input. Returns a description of each problem found.
+pub fn unit_diagnostics(bounds: &Bounds, headers: &[&Header], res: f64) -> Vec<String> {
+    let mut problems = Vec::new();
+    let wkts: Vec<String> = headers.iter().filter_map(|h| header_wkt(h)).collect();
+
+    // Small projected or local coordinates, e.g. a site grid, aren't degrees whatever they look
+    // like.
+    let planar = wkts.iter().any(|wkt| is_planar(wkt));
+    let looks_geographic = !planar
+        && [bounds.min.x, bounds.max.x]
+            .iter()
+            .all(|x| x.abs() <= 180.0)
+        && [bounds.min.y, bounds.max.y].iter().all(|y| y.abs() <= 90.0);
+
+    if looks_geographic && res > 0.01 {
+        problems.push(format!(
+            "The extent looks like degrees, but a resolution of {res} would be kilometres per pixel"
+        ));
+    }
+
+    if !looks_geographic && res < 1e-5 {
+        problems.push(format!(
+            "The extent looks projected, but a resolution of {res} looks like degrees"
+        ));
+    }
+
+    let geographic_crs = wkts
+        .iter()
+        .any(|wkt| wkt.starts_with("GEOGCS") || wkt.starts_with("GEOGCRS"));
+
+    if geographic_crs && !looks_geographic {
+        problems.push("The CRS is geographic, but the extent is outside of degree ranges".into());
+    }
+
+    let (width, height) = get_raster_size(bounds, res);
+
+    if width == 0 || height == 0 {
+        problems.push(format!(
+            "The output raster would be empty ({width}x{height})"
+        ));
+    } else if (width as u128) * (height as u128) > 1 << 34 {
+        problems.push(format!(
+            "The output raster would be {width}x{height} pixels, check --res and --extent"
+        ));
+    }
+
+    // Higher than Everest to the Mariana trench suggests unscaled or mislabeled Z.
+    let z_min = headers
+        .iter()
+        .map(|h| h.bounds().min.z)
+        .fold(f64::MAX, f64::min);
+    let z_max = headers
+        .iter()
+        .map(|h| h.bounds().max.z)
+        .fold(f64::MIN, f64::max);
+    let z_range = z_max - z_min;
+
+    let vertical = wkts.iter().find_map(|wkt| {
+        let vertical = vertical_units(wkt).ok()?;
+        Some((vertical.metres?, vertical.unit.unwrap_or_default()))
+    });
+
+    match vertical {
+        Some((metres, unit)) if z_range * metres > 20000.0 => problems.push(format!(
+            "The Z range of {z_range} is implausible in the vertical units of the CRS ({unit})"
+        )),
+        Some(_) => {}
+        // In feet, without known units.
+        None if z_range > 66000.0 => problems.push(format!(
+            "The Z range of {z_range} is implausible for metres or feet"
+        )),
+        None => {}
+    }
+
+    problems
+}
+
+/// Reads a little endian u64, as used by the binary sidecar files.
+pub fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// A path in the temporary directory unique to this process, for tests writing files.
+#[cfg(test)]
+pub fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("las-rasterizer-{}-{name}", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Bounds {
+        Bounds {
+            min: Vector {
+                x: min_x,
+                y: min_y,
+                z: 0.0,
+            },
+            max: Vector {
+                x: max_x,
+                y: max_y,
+                z: 0.0,
+            },
+        }
+    }
+
+    #[test]
+    fn size() {
+        let grid = GridDefinition::new(bounds(0.0, 0.0, 10.0, 5.0), 2.0);
+
+        assert_eq!((grid.width, grid.height, grid.len()), (5, 3, 15));
+        assert_eq!(grid.geo_transform(), [0.0, 2.0, 0.0, 0.0, 0.0, 2.0]);
+    }
+
+    #[test]
+    fn cells() {
+        let grid = GridDefinition::new(bounds(0.0, 0.0, 10.0, 5.0), 2.0);
+
+        assert_eq!(grid.cell(0.0, 0.0), Some((0, 0)));
+        assert_eq!(grid.cell(3.9, 2.1), Some((1, 1)));
+        // The max edges fall into the last column and row.
+        assert_eq!(grid.cell(10.0, 5.0), Some((4, 2)));
+        assert_eq!(grid.cell(10.1, 1.0), None);
+        assert_eq!(grid.cell(1.0, -0.1), None);
+        assert_eq!(grid.cell(f64::NAN, 1.0), None);
+        assert_eq!(grid.cell(1.0, f64::NAN), None);
+        assert_eq!(grid.cell(1.0, f64::INFINITY), None);
+
+        assert_eq!(grid.signed_cell(-0.5, 11.0), Some((-1, 5)));
+        assert_eq!(grid.signed_cell(1.0, f64::NAN), None);
+    }
+
+    #[test]
+    fn indices_and_centers() {
+        let grid = GridDefinition::new(bounds(100.0, 200.0, 110.0, 205.0), 2.0);
+
+        assert_eq!(grid.index(3, 2), 13);
+        assert_eq!(grid.cell_center(3, 2), (107.0, 205.0));
+        assert_eq!(grid.center(13), (107.0, 205.0));
+
+        for i in 0..grid.len() {
+            let (x, y) = grid.center(i);
+            let (x_idx, y_idx) = grid.cell(x, y).unwrap();
+
+            assert_eq!(grid.index(x_idx, y_idx), i);
+        }
+    }
+
+    #[test]
+    fn intersection_and_tiles() {
+        let grid = GridDefinition::new(bounds(0.0, 0.0, 10.0, 10.0), 1.0);
+
+        let ((x0, y0), sub) = grid.intersection(&bounds(2.5, -5.0, 4.5, 3.0)).unwrap();
+        assert_eq!((x0, y0, sub.width, sub.height), (2, 0, 3, 3));
+        assert_eq!((sub.bounds.min.x, sub.bounds.max.x), (2.0, 5.0));
+        assert!(grid.intersection(&bounds(11.0, 0.0, 12.0, 1.0)).is_none());
+
+        let tiles: Vec<_> = grid.tiles(4).collect();
+        assert_eq!(tiles.len(), 9);
+        assert_eq!(tiles[2].0, (8, 0));
+        assert_eq!((tiles[8].1.width, tiles[8].1.height), (2, 2));
+        assert_eq!(
+            tiles.iter().map(|(_, t)| t.len()).sum::<usize>(),
+            grid.len()
+        );
+    }
+
+    #[test]
+    fn equality_and_serialization() {
+        let grid = GridDefinition::new(bounds(0.0, 0.0, 9.5, 10.0), 1.0);
+
+        // The same cells, for another extent.
+        assert_eq!(grid, GridDefinition::new(bounds(0.0, 0.0, 9.2, 9.9), 1.0));
+        assert_ne!(grid, GridDefinition::new(bounds(0.5, 0.0, 9.5, 10.0), 1.0));
+        assert_ne!(grid, GridDefinition::new(bounds(0.0, 0.0, 9.5, 10.0), 0.5));
+
+        let text = toml::to_string(&grid).unwrap();
+        let read: GridDefinition = toml::from_str(&text).unwrap();
+
+        assert_eq!(read, grid);
+        assert_eq!((read.bounds.max.x, read.bounds.max.y), (10.0, 10.0));
+    }
+
+    #[test]
+    fn raster_size() {
+        assert_eq!(get_raster_size(&bounds(0.0, 0.0, 10.0, 10.0), 3.0), (4, 4));
+        assert_eq!(get_raster_size(&bounds(0.0, 0.0, 10.0, 10.0), 2.5), (4, 4));
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::path::Path;
+
+use las::{Bounds, Point, Reader};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::input::skip_size;
+
+/// Problems found in a LAS/LAZ file, serialized as the machine-readable summary of `validate`.
+#[derive(Debug, Serialize)]
+pub struct Validation {
+    pub path: String,
+    /// Number of points the header claims.
+    pub header_points: u64,
+    /// Points which decoded.
+    pub points_read: u64,
+    /// Points which failed to decode, skipped along with the rest of their LAZ chunk.
+    pub points_corrupt: u64,
+    /// Index of the first point of each run of corrupt points.
+    pub corrupt_at: Vec<u64>,
+    /// Whether the file ends before the header's point records do.
+    pub truncated: bool,
+    /// Points outside of the header bounds, by more than half the scale.
+    pub points_out_of_bounds: u64,
+    /// Whether the file has none of the above problems.
+    pub valid: bool,
+}
+
+/// Whether `point` is within `bounds`, give or take `tolerance` per axis.
+fn within(bounds: &Bounds, tolerance: [f64; 3], point: &Point) -> bool {
+    let [tx, ty, tz] = tolerance;
+
+    point.x >= bounds.min.x - tx
+        && point.x <= bounds.max.x + tx
+        && point.y >= bounds.min.y - ty
+        && point.y <= bounds.max.y + ty
+        && point.z >= bounds.min.z - tz
+        && point.z <= bounds.max.z + tz
+}
+
+/// Reads every point of the local LAS/LAZ file at `path`, checking the point records against the
+/// header. Problems with the points are reported rather than returned as errors, only a file
+/// which can't be opened at all is an error.
+pub fn validate(path: &Path) -> Result<Validation> {
+    let name = path.display().to_string();
+    let mut reader = Reader::from_path(path).map_err(|err| Error::InputFile(name.clone(), err))?;
+    let header = reader.header().clone();
+
+    let header_points = header.number_of_points();
+    let bounds = header.bounds();
+    let transforms = header.transforms();
+    let tolerance = [
+        transforms.x.scale / 2.0,
+        transforms.y.scale / 2.0,
+        transforms.z.scale / 2.0,
+    ];
+
+    // Uncompressed point records have a fixed size, so a short file shows without reading it.
+    let mut truncated = match header.point_format().is_compressed {
+        true => false,
+        false => {
+            let raw = header.clone().into_raw()?;
+            let expected = raw.offset_to_point_data as u64
+                + header_points * header.point_format().len() as u64;
+
+            fs::metadata(path)?.len() < expected
+        }
+    };
+
+    let (mut points_read, mut points_corrupt, mut points_out_of_bounds) = (0, 0, 0);
+    let mut corrupt_at = Vec::new();
+    let mut index = 0;
+
+    while index < header_points {
+        match reader.read_point() {
+            Ok(Some(point)) => {
+                points_read += 1;
+                index += 1;
+
+                if !within(&bounds, tolerance, &point) {
+                    points_out_of_bounds += 1;
+                }
+            }
+            // Fewer points than the header claims.
+            Ok(None) => {
+                truncated = true;
+                break;
+            }
+            Err(err) => {
+                let Some(skip) = skip_size(&header) else {
+                    warn!("{name}: corrupt point {index} in a variable sized chunk: {err}");
+                    points_corrupt += header_points - index;
+                    corrupt_at.push(index);
+                    break;
+                };
+
+                let next = ((index / skip + 1) * skip).min(header_points);
+                warn!(
+                    "{name}: {} corrupt point(s) at {index}: {err}",
+                    next - index
+                );
+
+                points_corrupt += next - index;
+                corrupt_at.push(index);
+                index = next;
+
+                if index < header_points {
+                    reader
+                        .seek(index)
+                        .map_err(|err| Error::InputFile(name.clone(), err))?;
+                }
+            }
+        }
+    }
+
+    let valid = !truncated && points_corrupt == 0 && points_out_of_bounds == 0;
+
+    info!(
+        "{name}: {points_read} of {header_points} points read, {points_corrupt} corrupt, \
+        {points_out_of_bounds} out of bounds{}",
+        match truncated {
+            true => ", truncated",
+            false => "",
+        }
+    );
+
+    Ok(Validation {
+        path: name,
+        header_points,
+        points_read,
+        points_corrupt,
+        corrupt_at,
+        truncated,
+        points_out_of_bounds,
+        valid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use las::{Builder, Vector, Writer};
+
+    use super::*;
+    use crate::util::temp_path;
+
+    #[test]
+    fn within_half_the_scale() {
+        let bounds = Bounds {
+            min: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: 10.0,
+                y: 10.0,
+                z: 5.0,
+            },
+        };
+        let at = |x: f64, y: f64, z: f64| Point {
+            x,
+            y,
+            z,
+            ..Default::default()
+        };
+        let tolerance = [0.005; 3];
+
+        assert!(within(&bounds, tolerance, &at(5.0, 5.0, 2.0)));
+        assert!(within(&bounds, tolerance, &at(10.004, -0.004, 5.0)));
+        assert!(!within(&bounds, tolerance, &at(10.01, 5.0, 2.0)));
+        assert!(!within(&bounds, tolerance, &at(5.0, 5.0, -1.0)));
+    }
+
+    #[test]
+    fn truncated_files() {
+        let path = temp_path("validate.las");
+        let mut writer =
+            Writer::from_path(&path, Builder::from((1, 4)).into_header().unwrap()).unwrap();
+        for x in [1.0, 2.0, 3.0] {
+            writer
+                .write_point(Point {
+                    x,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        writer.close().unwrap();
+
+        let validation = validate(&path).unwrap();
+        assert!(validation.valid);
+        assert_eq!((validation.header_points, validation.points_read), (3, 3));
+
+        // Half of the last point record.
+        let len = fs::metadata(&path).unwrap().len();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 10)
+            .unwrap();
+
+        let validation = validate(&path).unwrap();
+        assert!(validation.truncated);
+        assert!(!validation.valid);
+        assert_eq!(validation.points_read, 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
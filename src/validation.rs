@@ -0,0 +1,189 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use las::Point;
+use serde::Serialize;
+use tracing::info;
+
+use crate::filter::PointFilter;
+use crate::synthetic::Rng;
+use crate::util::GridDefinition;
+use crate::{get_var, Variable, NODATA};
+
+/// A random subset of the input's points, withheld from rasterization to check the raster
+/// against. Clones share the withheld points, so they stay reachable once the input is consumed.
+#[derive(Clone)]
+pub struct Holdout {
+    fraction: f64,
+    rng: Rc<RefCell<Rng>>,
+    points: Rc<RefCell<Vec<Point>>>,
+}
+
+impl Holdout {
+    /// Withholds `fraction` (0-1) of the points. The same input gives the same subset.
+    pub fn new(fraction: f64) -> Self {
+        Self {
+            fraction,
+            rng: Rc::new(RefCell::new(Rng::new(0))),
+            points: Rc::default(),
+        }
+    }
+
+    /// Whether `point` is withheld, keeping it if so.
+    pub fn take(&self, point: &Point) -> bool {
+        if self.rng.borrow_mut().uniform() >= self.fraction {
+            return false;
+        }
+
+        self.points.borrow_mut().push(point.clone());
+        true
+    }
+
+    /// Prediction errors of `band` at the withheld points passing `filter`, comparing the cell
+    /// containing each point with its value of `var`.
+    pub fn accuracy(
+        &self,
+        band: &[f64],
+        grid: &GridDefinition,
+        filter: &PointFilter,
+        var: Variable,
+    ) -> Accuracy {
+        let mut accuracy = Accuracy::default();
+        let (mut squares, mut absolutes, mut sum) = (0.0, 0.0, 0.0);
+
+        for point in self.points.borrow().iter() {
+            let value = get_var(&var, point);
+
+            if !filter.keep(point) || value.is_nan() {
+                continue;
+            }
+
+            let predicted = grid
+                .cell(point.x, point.y)
+                .map(|(x, y)| band[grid.index(x, y)])
+                .filter(|v| *v != NODATA && !v.is_nan());
+
+            let Some(predicted) = predicted else {
+                accuracy.unpredicted += 1;
+                continue;
+            };
+
+            let error = predicted - value;
+            squares += error * error;
+            absolutes += error.abs();
+            sum += error;
+            accuracy.points += 1;
+        }
+
+        if accuracy.points > 0 {
+            let n = accuracy.points as f64;
+            accuracy.rmse = (squares / n).sqrt();
+            accuracy.mae = absolutes / n;
+            accuracy.bias = sum / n;
+        }
+
+        accuracy
+    }
+}
+
+/// Errors (predicted - withheld value) of a raster at withheld points.
+#[derive(Debug, Default, Serialize)]
+pub struct Accuracy {
+    /// Withheld points with a prediction.
+    pub points: u64,
+    /// Withheld points on NODATA cells or outside of the extent.
+    pub unpredicted: u64,
+    pub rmse: f64,
+    pub mae: f64,
+    /// Mean error.
+    pub bias: f64,
+}
+
+impl Accuracy {
+    pub fn log(&self) {
+        info!(
+            "Cross-validation over {} withheld points ({} without a prediction): RMSE {:.4}, \
+             MAE {:.4}, bias {:.4}",
+            self.points, self.unpredicted, self.rmse, self.mae, self.bias
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use las::{Bounds, Vector};
+
+    use super::*;
+
+    fn at(x: f64, y: f64, z: f64) -> Point {
+        Point {
+            x,
+            y,
+            z,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn withholds_a_repeatable_fraction() {
+        let points: Vec<Point> = (0..1000).map(|i| at(i as f64, 0.0, 0.0)).collect();
+        let taken = |fraction: f64| {
+            let holdout = Holdout::new(fraction);
+            let taken: Vec<bool> = points.iter().map(|p| holdout.take(p)).collect();
+            assert_eq!(
+                holdout.points.borrow().len(),
+                taken.iter().filter(|t| **t).count()
+            );
+
+            taken
+        };
+
+        assert!(taken(0.0).iter().all(|t| !t));
+        assert!(taken(1.0).iter().all(|t| *t));
+
+        let half = taken(0.5);
+        assert_eq!(half, taken(0.5));
+        assert!((400..600).contains(&half.iter().filter(|t| **t).count()));
+    }
+
+    #[test]
+    fn accuracy() {
+        let bounds = Bounds {
+            min: Vector {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            max: Vector {
+                x: 2.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        };
+        let grid = GridDefinition::new(bounds, 1.0);
+        let band = [1.0, NODATA];
+
+        let holdout = Holdout::new(1.0);
+        for point in [
+            at(0.5, 0.5, 2.0),
+            at(0.5, 0.5, 0.0),
+            at(0.2, 0.8, 1.0),
+            at(1.5, 0.5, 1.0),
+            at(10.0, 10.0, 1.0),
+        ] {
+            holdout.take(&point);
+        }
+
+        let accuracy = holdout.accuracy(
+            &band,
+            &grid,
+            &PointFilter::new(Vec::new(), None, None),
+            Variable::Z,
+        );
+
+        assert_eq!((accuracy.points, accuracy.unpredicted), (3, 2));
+        assert!((accuracy.rmse - (2.0f64 / 3.0).sqrt()).abs() < 1e-12);
+        assert!((accuracy.mae - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(accuracy.bias, 0.0);
+    }
+}
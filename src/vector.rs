@@ -0,0 +1,349 @@
+use std::path::{Path, PathBuf};
+
+use gdal::vector::{Geometry, LayerAccess};
+use gdal::{Dataset, Driver};
+use las::{Bounds, Vector};
+
+use crate::drivers::{create_driver, DriverKind};
+use crate::error::{Error, Result};
+use crate::util::GridDefinition;
+
+/// A single vector feature, flattened into its simple parts (points, lines and polygon rings).
+pub struct VectorFeature {
+    pub parts: Vec<Vec<[f64; 3]>>,
+    pub value: Option<f64>,
+}
+
+/// Parses a `path[:field]` argument. A colon followed by something that looks like a path (e.g.
+/// a Windows drive letter) is not treated as a field separator.
+pub fn path_field_parser(s: &str) -> core::result::Result<(PathBuf, Option<String>), String> {
+    match s.rsplit_once(':') {
+        Some((path, field))
+            if !path.is_empty() && !field.is_empty() && !field.contains(['/', '\\']) =>
+        {
+            Ok((PathBuf::from(path), Some(field.to_string())))
+        }
+        _ if s.is_empty() => Err("Empty vector path".to_string()),
+        _ => Ok((PathBuf::from(s), None)),
+    }
+}
+
+/// Finds a vector driver with creation support for the extension of `path`.
+pub fn vector_driver(path: &Path) -> Result<Driver> {
+    create_driver(path, DriverKind::Vector)
+}
+
+/// Reads every feature of the first layer in `path`, optionally reading `field` as a numeric
+/// value.
+pub fn read_features(path: &Path, field: Option<&str>) -> Result<Vec<VectorFeature>> {
+    let ds = Dataset::open(path)?;
+    let mut layer = ds.layer(0)?;
+
+    let mut features = Vec::new();
+
+    for feature in layer.features() {
+        let Some(geometry) = feature.geometry() else {
+            continue;
+        };
+
+        let value = match field {
+            Some(field) => Some(
+                feature
+                    .field_as_double_by_name(field)?
+                    .ok_or(Error::MissingField(field.to_string()))?,
+            ),
+            None => None,
+        };
+
+        let mut parts = Vec::new();
+        collect_parts(geometry, &mut parts);
+
+        features.push(VectorFeature { parts, value });
+    }
+
+    Ok(features)
+}
+
+/// Name and XY extent of every feature of the first layer in `path`, named by the value of
+/// `field`, or by the feature ID without one.
+pub fn read_named_extents(path: &Path, field: Option<&str>) -> Result<Vec<(String, Bounds)>> {
+    let ds = Dataset::open(path)?;
+    let mut layer = ds.layer(0)?;
+
+    let mut extents = Vec::new();
+
+    for feature in layer.features() {
+        let Some(geometry) = feature.geometry() else {
+            continue;
+        };
+
+        let name = match field {
+            Some(field) => feature
+                .field_as_string_by_name(field)?
+                .ok_or(Error::MissingField(field.to_string()))?,
+            None => feature.fid().unwrap_or_default().to_string(),
+        };
+
+        let envelope = geometry.envelope();
+
+        extents.push((
+            name,
+            Bounds {
+                min: Vector {
+                    x: envelope.MinX,
+                    y: envelope.MinY,
+                    z: f64::MIN,
+                },
+                max: Vector {
+                    x: envelope.MaxX,
+                    y: envelope.MaxY,
+                    z: f64::MAX,
+                },
+            },
+        ));
+    }
+
+    Ok(extents)
+}
+
+/// Recursively flattens multi-geometries and polygons into their coordinate sequences.
+fn collect_parts(geometry: &Geometry, parts: &mut Vec<Vec<[f64; 3]>>) {
+    let count = geometry.geometry_count();
+
+    if count == 0 {
+        let coords = geometry
+            .get_point_vec()
+            .into_iter()
+            .map(|(x, y, z)| [x, y, z])
+            .collect::<Vec<[f64; 3]>>();
+
+        if !coords.is_empty() {
+            parts.push(coords);
+        }
+
+        return;
+    }
+
+    for i in 0..count {
+        collect_parts(&geometry.get_geometry(i), parts);
+    }
+}
+
+/// Edges per band of a polygon's edge index, on average.
+const EDGES_PER_BAND: usize = 8;
+
+/// A polygon, with its edges indexed by horizontal bands of its bounding box so a point only
+/// tests the edges crossing its band.
+struct Polygon {
+    min: [f64; 2],
+    max: [f64; 2],
+    /// Edges of every ring.
+    edges: Vec<[[f64; 2]; 2]>,
+    /// Indices of the edges overlapping each band.
+    bands: Vec<Vec<usize>>,
+    band_height: f64,
+}
+
+impl Polygon {
+    fn new(rings: &[Vec<[f64; 2]>]) -> Self {
+        let mut min = [f64::MAX; 2];
+        let mut max = [f64::MIN; 2];
+
+        for [x, y] in rings.iter().flatten() {
+            min = [min[0].min(*x), min[1].min(*y)];
+            max = [max[0].max(*x), max[1].max(*y)];
+        }
+
+        // Each ring closed by its last edge, back to the first vertex.
+        let edges: Vec<[[f64; 2]; 2]> = rings
+            .iter()
+            .flat_map(|ring| {
+                (0..ring.len()).map(|i| [ring[(i + ring.len() - 1) % ring.len()], ring[i]])
+            })
+            .collect();
+
+        let count = (edges.len() / EDGES_PER_BAND).max(1);
+        let mut polygon = Self {
+            min,
+            max,
+            edges,
+            bands: vec![Vec::new(); count],
+            band_height: (max[1] - min[1]) / count as f64,
+        };
+
+        for (i, [a, b]) in polygon.edges.iter().enumerate() {
+            for band in polygon.band(a[1].min(b[1]))..=polygon.band(a[1].max(b[1])) {
+                polygon.bands[band].push(i);
+            }
+        }
+
+        polygon
+    }
+
+    /// The band of `y`, clamped to the bounding box.
+    fn band(&self, y: f64) -> usize {
+        // A flat polygon's band height is 0, whose NaN or infinite quotients clamp too.
+        (((y - self.min[1]) / self.band_height).max(0.0) as usize).min(self.bands.len() - 1)
+    }
+
+    /// Even-odd rule over all rings, so holes are handled without knowing ring orientation.
+    fn contains(&self, x: f64, y: f64) -> bool {
+        if x < self.min[0] || x > self.max[0] || y < self.min[1] || y > self.max[1] {
+            return false;
+        }
+
+        let mut inside = false;
+
+        for edge in self.bands[self.band(y)].iter() {
+            let [[xi, yi], [xj, yj]] = self.edges[*edge];
+
+            if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+        }
+
+        inside
+    }
+
+    /// Whether the rectangle `min` - `max` lies entirely inside: its center does and no edge
+    /// crosses it.
+    fn covers(&self, min: [f64; 2], max: [f64; 2]) -> bool {
+        if !self.contains((min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0) {
+            return false;
+        }
+
+        let corners = [min, [max[0], min[1]], max, [min[0], max[1]]];
+
+        (self.band(min[1])..=self.band(max[1]))
+            .flat_map(|band| self.bands[band].iter())
+            .all(|edge| {
+                let [a, b] = self.edges[*edge];
+
+                if a[0].max(b[0]) < min[0]
+                    || a[0].min(b[0]) > max[0]
+                    || a[1].max(b[1]) < min[1]
+                    || a[1].min(b[1]) > max[1]
+                {
+                    return true;
+                }
+
+                // The edge's line misses the rectangle if every corner is on the same side of it.
+                let side =
+                    |[x, y]: [f64; 2]| (b[0] - a[0]) * (y - a[1]) - (b[1] - a[1]) * (x - a[0]);
+                corners.iter().all(|c| side(*c) > 0.0) || corners.iter().all(|c| side(*c) < 0.0)
+            })
+    }
+}
+
+/// Polygon features for point-in-polygon tests.
+pub struct Polygons {
+    polygons: Vec<Polygon>,
+}
+
+impl Polygons {
+    pub fn new(features: &[VectorFeature]) -> Self {
+        let polygons = features
+            .iter()
+            .filter_map(|feature| {
+                let rings: Vec<Vec<[f64; 2]>> = feature
+                    .parts
+                    .iter()
+                    .filter(|part| part.len() >= 3)
+                    .map(|part| part.iter().map(|[x, y, _]| [*x, *y]).collect())
+                    .collect();
+
+                if rings.is_empty() {
+                    return None;
+                }
+
+                Some(Polygon::new(&rings))
+            })
+            .collect();
+
+        Self { polygons }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        Ok(Self::new(&read_features(path, None)?))
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        self.polygons.iter().any(|p| p.contains(x, y))
+    }
+
+    /// Bounding box of every polygon, None without any.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.polygons.iter().fold(None, |bounds, polygon| {
+            let (mut min, mut max) = (polygon.min, polygon.max);
+            if let Some(bounds) = bounds {
+                min = [min[0].min(bounds.min.x), min[1].min(bounds.min.y)];
+                max = [max[0].max(bounds.max.x), max[1].max(bounds.max.y)];
+            }
+
+            Some(Bounds {
+                min: Vector {
+                    x: min[0],
+                    y: min[1],
+                    z: f64::MIN,
+                },
+                max: Vector {
+                    x: max[0],
+                    y: max[1],
+                    z: f64::MAX,
+                },
+            })
+        })
+    }
+
+    /// Sets every cell of a band of `grid` lying entirely inside a polygon to `nodata`. Cells on
+    /// an edge keep the data of the points outside.
+    pub fn mask(&self, data: &mut [f64], grid: &GridDefinition, nodata: f64) {
+        for polygon in self.polygons.iter() {
+            let bounds = Bounds {
+                min: Vector {
+                    x: polygon.min[0],
+                    y: polygon.min[1],
+                    z: 0.0,
+                },
+                max: Vector {
+                    x: polygon.max[0],
+                    y: polygon.max[1],
+                    z: 0.0,
+                },
+            };
+
+            // Only the cells within the polygon's bounding box can be inside it.
+            let Some(((x0, y0), cells)) = grid.intersection(&bounds) else {
+                continue;
+            };
+
+            let half = grid.res / 2.0;
+
+            for y in y0..y0 + cells.height {
+                for x in x0..x0 + cells.width {
+                    let (center_x, center_y) = grid.cell_center(x, y);
+                    let (min, max) = (
+                        [center_x - half, center_y - half],
+                        [center_x + half, center_y + half],
+                    );
+
+                    if polygon.covers(min, max) {
+                        data[grid.index(x, y)] = nodata;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets every cell of a band of `grid` whose center falls outside every polygon to
+    /// `nodata`.
+    pub fn clip(&self, data: &mut [f64], grid: &GridDefinition, nodata: f64) {
+        for (i, value) in data.iter_mut().enumerate() {
+            let (x, y) = grid.center(i);
+
+            if !self.contains(x, y) {
+                *value = nodata;
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::Path;
+
+use gdal::{Dataset, GeoTransformEx};
+use las::{Bounds, Point, Vector};
+use tracing::info;
+
+use crate::binning::{bin_points, BinningParams};
+use crate::error::{Error, Result};
+use crate::filter::PointFilter;
+use crate::input::{buffer_points, Input};
+use crate::output::{write_raster, Band, BandType, RasterInfo};
+use crate::report::Report;
+use crate::util::GridDefinition;
+use crate::{Function, Variable, NODATA};
+
+/// Sample points (x, y offsets from the origin, value), off center in their cells and away from
+/// any symmetry, so flipped rows or swapped axes land on other values.
+const SAMPLES: [(f64, f64, f64); 5] = [
+    (0.3, 0.7, 1.0),
+    (9.6, 0.2, 2.0),
+    (0.5, 7.5, 3.0),
+    (4.2, 5.9, 4.0),
+    (8.9, 7.1, 5.0),
+];
+
+/// Origin of the sample extent, away from (0, 0) so a missing offset shows.
+const ORIGIN: (f64, f64) = (1000.0, 2000.0);
+
+/// Rasterizes a few points at known locations into `path`, reads them back out through GDAL's
+/// geotransform and checks each lands in a pixel whose center is within half a pixel of it, with
+/// the point's value. Catches row order and geotransform problems of a GDAL build and driver.
+pub fn run(path: &Path) -> Result<()> {
+    // A non-square extent, so swapped axes show.
+    let (origin_x, origin_y) = ORIGIN;
+    let res = 1.0;
+    let bounds = Bounds {
+        min: Vector {
+            x: origin_x,
+            y: origin_y,
+            z: 0.0,
+        },
+        max: Vector {
+            x: origin_x + 10.0,
+            y: origin_y + 8.0,
+            z: 10.0,
+        },
+    };
+    let grid = GridDefinition::new(bounds, res);
+    let (width, height) = (grid.width, grid.height);
+
+    let points = SAMPLES
+        .iter()
+        .map(|(x, y, z)| Point {
+            x: origin_x + x,
+            y: origin_y + y,
+            z: *z,
+            ..Default::default()
+        })
+        .collect();
+
+    let bins = bin_points(
+        Input::new(
+            vec![("verify".to_string(), buffer_points("verify", points)?)],
+            0,
+        ),
+        grid,
+        &PointFilter::default(),
+        Variable::Z,
+        &BinningParams {
+            funcs: vec![Function::Max],
+            breaklines: None,
+            weight_by_returns: false,
+            swath_edge_angle: None,
+            strict: true,
+            focal_radius: None,
+            carry: None,
+            min_count: 1,
+        },
+        &mut Report::default(),
+    )?;
+
+    let data = (0..grid.len())
+        .map(|i| bins.collapse(&Function::Max, i))
+        .collect();
+
+    info!("Writing {}", path.display());
+    write_raster(
+        path,
+        &grid,
+        NODATA,
+        vec![Band::new("Z".to_string(), data)],
+        BandType::F64,
+        None,
+        &RasterInfo::default(),
+    )?;
+
+    let result = check(path, width, height, res);
+
+    fs::remove_file(path)?;
+
+    result
+}
+
+fn check(path: &Path, width: usize, height: usize, res: f64) -> Result<()> {
+    let ds = Dataset::open(path)?;
+    let fail = |e: String| Err(Error::Verify(e));
+
+    if ds.raster_size() != (width, height) {
+        return fail(format!(
+            "raster size {:?}, expected {:?}",
+            ds.raster_size(),
+            (width, height)
+        ));
+    }
+
+    let transform = ds.geo_transform()?;
+    let inverse = transform.invert()?;
+    let band = ds.rasterband(1)?;
+
+    for (x, y, value) in SAMPLES {
+        let (x, y) = (ORIGIN.0 + x, ORIGIN.1 + y);
+        let (col, row) = inverse.apply(x, y);
+        let (col, row) = (col.floor(), row.floor());
+
+        if col < 0.0 || row < 0.0 || col >= width as f64 || row >= height as f64 {
+            return fail(format!(
+                "({x}, {y}) maps to pixel ({col}, {row}), outside the raster"
+            ));
+        }
+
+        let (center_x, center_y) = transform.apply(col + 0.5, row + 0.5);
+        if (center_x - x).abs() > res / 2.0 || (center_y - y).abs() > res / 2.0 {
+            return fail(format!(
+                "({x}, {y}) maps to pixel ({col}, {row}), centered at ({center_x}, {center_y})"
+            ));
+        }
+
+        let read = band.read_as::<f64>((col as isize, row as isize), (1, 1), (1, 1), None)?;
+        if read.data()[0] != value {
+            return fail(format!(
+                "pixel ({col}, {row}) at ({x}, {y}) holds {}, expected {value}",
+                read.data()[0]
+            ));
+        }
+
+        info!("({x}, {y}) -> pixel ({col}, {row}) = {value}: ok");
+    }
+
+    info!(
+        "Geolocation round-trips within half a pixel ({}, rows {})",
+        ds.driver().short_name(),
+        match transform[5] > 0.0 {
+            true => "south-up",
+            false => "north-up",
+        }
+    );
+
+    Ok(())
+}
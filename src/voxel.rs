@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+
+/// The point a voxel keeps.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VoxelKeep {
+    Lowest,
+    #[default]
+    Highest,
+    /// Nearest to the mean of the voxel's points, which are all held until the end.
+    Centroid,
+}
+
+/// Points of a voxel, the kept one first.
+struct Voxel<T> {
+    points: Vec<([f64; 3], T)>,
+    sum: [f64; 3],
+}
+
+/// Thins points to one per cube of a 3D grid, bounding the number of points by the volume
+/// they occupy rather than their density.
+pub struct VoxelGrid<T> {
+    size: f64,
+    keep: VoxelKeep,
+    voxels: HashMap<[i64; 3], Voxel<T>>,
+}
+
+impl<T> VoxelGrid<T> {
+    pub fn new(size: f64, keep: VoxelKeep) -> Self {
+        Self {
+            size,
+            keep,
+            voxels: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, xyz: [f64; 3], item: T) {
+        let key = xyz.map(|v| (v / self.size).floor() as i64);
+
+        let voxel = self.voxels.entry(key).or_insert_with(|| Voxel {
+            points: Vec::with_capacity(1),
+            sum: [0.0; 3],
+        });
+
+        for (sum, v) in voxel.sum.iter_mut().zip(xyz) {
+            *sum += v;
+        }
+
+        let replace = match (self.keep, voxel.points.first()) {
+            (_, None) | (VoxelKeep::Centroid, _) => {
+                voxel.points.push((xyz, item));
+                return;
+            }
+            (VoxelKeep::Lowest, Some((kept, _))) => xyz[2] < kept[2],
+            (VoxelKeep::Highest, Some((kept, _))) => xyz[2] > kept[2],
+        };
+
+        if replace {
+            voxel.points[0] = (xyz, item);
+        }
+    }
+
+    /// Number of occupied voxels.
+    pub fn occupied(&self) -> usize {
+        self.voxels.len()
+    }
+
+    /// The kept point of every voxel.
+    pub fn into_points(self) -> impl Iterator<Item = T> {
+        self.voxels.into_values().filter_map(|voxel| {
+            let n = voxel.points.len() as f64;
+            let centroid = voxel.sum.map(|s| s / n);
+            let distance = |xyz: &[f64; 3]| -> f64 {
+                xyz.iter()
+                    .zip(centroid)
+                    .map(|(v, c)| (v - c) * (v - c))
+                    .sum()
+            };
+
+            voxel
+                .points
+                .into_iter()
+                .min_by(|a, b| distance(&a.0).total_cmp(&distance(&b.0)))
+                .map(|(_, item)| item)
+        })
+    }
+}